@@ -0,0 +1,462 @@
+//! A command line client for attpc_envoy, for running the AT-TPC from scripts or over ssh without the GUI.
+//!
+//! Usage:
+//!     envoy_cli --config FILE [--timeout SECS] [--interval SECS] [--verbose] COMMAND
+//!
+//! COMMAND is one of connect, status, progress, regress, start, stop, or watch. Every command connects
+//! to the AT-TPC described by the config (and its topology), waits up to --timeout seconds for every module
+//! to answer a status query, does its work, and disconnects. The exit code tells a script what happened:
+//!
+//!     0  success
+//!     1  an operation or sequence failed
+//!     2  bad arguments, or the config or topology could not be loaded
+//!     3  one or more modules could not be reached
+//!     4  the command is not allowed from the current state of the system
+//!     5  the command timed out
+use attpc_envoy::envoy::ecc_operation::ECCStatus;
+use attpc_envoy::envoy::error::{EmbassyError, RunControlError};
+use attpc_envoy::envoy::run_controller::RunSequence;
+use attpc_envoy::envoy::surveyor_state::SurveyorState;
+use attpc_envoy::envoy::topology::ModuleRole;
+use attpc_envoy::session::data_monitor::DataFlow;
+use attpc_envoy::session::disk_forecast::format_time_to_full;
+use attpc_envoy::session::error::{AlarmError, SessionError};
+use attpc_envoy::session::run_log::{RunLog, RunLogLayer};
+use attpc_envoy::session::session::Session;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Builder;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+
+const USAGE: &str = "Usage: envoy_cli --config FILE [--timeout SECS] [--interval SECS] [--verbose] <connect|status|progress|regress|start|stop|watch>";
+
+/// How often the session is polled while waiting
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+/// Longest wait for the operations submitted by regress to be answered. The envoys give every
+/// operation its own deadline, so this should only be hit if something is badly wrong.
+const OPERATION_WAIT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Connect,
+    Status,
+    Progress,
+    Regress,
+    Start,
+    Stop,
+    Watch,
+}
+
+impl TryFrom<&str> for Command {
+    type Error = CliError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "connect" => Ok(Self::Connect),
+            "status" => Ok(Self::Status),
+            "progress" => Ok(Self::Progress),
+            "regress" => Ok(Self::Regress),
+            "start" => Ok(Self::Start),
+            "stop" => Ok(Self::Stop),
+            "watch" => Ok(Self::Watch),
+            _ => Err(CliError::Usage(format!("Unknown command {value}"))),
+        }
+    }
+}
+
+struct Options {
+    config: PathBuf,
+    command: Command,
+    timeout: Duration,
+    interval: Duration,
+    verbose: bool,
+}
+
+/// Everything which can end the client, each with its own exit code
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Config(SessionError),
+    Failed(String),
+    Unreachable(Vec<i32>),
+    Rejected(String),
+    Timeout(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Failed(_) => 1,
+            Self::Usage(_) | Self::Config(_) => 2,
+            Self::Unreachable(_) => 3,
+            Self::Rejected(_) => 4,
+            Self::Timeout(_) => 5,
+        }
+    }
+}
+
+impl From<SessionError> for CliError {
+    fn from(value: SessionError) -> Self {
+        match value {
+            SessionError::ConfigIOError(_)
+            | SessionError::ConfigParseError(_)
+            | SessionError::TopologyError(_) => Self::Config(value),
+            SessionError::InvalidState(_, _)
+            | SessionError::SequenceRunning
+            | SessionError::NoSequenceRunning
+            | SessionError::Connected
+            | SessionError::Replaying
+            | SessionError::NoModule(_)
+            | SessionError::ModuleMasked(_)
+            | SessionError::SubmitError(EmbassyError::OperationInFlight(_, _, _))
+            | SessionError::AlarmError(AlarmError::NoAlarm(_)) => Self::Rejected(value.to_string()),
+            SessionError::NotConnected
+            | SessionError::SubmitError(_)
+            | SessionError::ApiBindError(_)
+            | SessionError::RecordingError(_)
+            | SessionError::AlarmError(_) => Self::Failed(value.to_string()),
+        }
+    }
+}
+
+impl From<RunControlError> for CliError {
+    fn from(value: RunControlError) -> Self {
+        match value {
+            RunControlError::Timeout(_, _) => Self::Timeout(value.to_string()),
+            RunControlError::Rejected(_)
+            | RunControlError::SubmitError(EmbassyError::OperationInFlight(_, _, _)) => {
+                Self::Rejected(value.to_string())
+            }
+            _ => Self::Failed(value.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Usage(e) => write!(f, "{e}\n{USAGE}"),
+            Self::Config(e) => write!(f, "{e}"),
+            Self::Failed(e) => write!(f, "{e}"),
+            Self::Unreachable(ids) => write!(f, "Could not reach modules {ids:?}"),
+            Self::Rejected(e) => write!(f, "{e}"),
+            Self::Timeout(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a String, CliError> {
+    args.next()
+        .ok_or(CliError::Usage(format!("{flag} requires a value")))
+}
+
+fn parse_seconds(value: &str, flag: &str) -> Result<Duration, CliError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or(CliError::Usage(format!(
+            "{flag} must be a positive number of seconds"
+        )))
+}
+
+fn parse_args() -> Result<Options, CliError> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config: Option<PathBuf> = None;
+    let mut command: Option<Command> = None;
+    let mut timeout = Duration::from_secs(10);
+    let mut interval = Duration::from_secs(5);
+    let mut verbose = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" | "-c" => config = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--timeout" => timeout = parse_seconds(next_value(&mut iter, arg)?, arg)?,
+            "--interval" => interval = parse_seconds(next_value(&mut iter, arg)?, arg)?,
+            "--verbose" | "-v" => verbose = true,
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            other if command.is_none() && !other.starts_with('-') => {
+                command = Some(Command::try_from(other)?)
+            }
+            other => return Err(CliError::Usage(format!("Unexpected argument {other}"))),
+        }
+    }
+
+    return Ok(Options {
+        config: config.ok_or(CliError::Usage(String::from("--config is required")))?,
+        command: command.ok_or(CliError::Usage(String::from("No command given")))?,
+        timeout,
+        interval,
+        verbose,
+    });
+}
+
+/// Poll the session for a while
+fn poll_for(session: &mut Session, duration: Duration) {
+    let end = Instant::now() + duration;
+    while Instant::now() < end {
+        session.poll();
+        std::thread::sleep(POLL_PERIOD);
+    }
+}
+
+/// Wait for every module to answer a status query, up to the timeout. Any module which never answered,
+/// or whose server could not be reached, is an error.
+fn wait_for_contact(session: &mut Session, timeout: Duration) -> Result<(), CliError> {
+    let start = Instant::now();
+    while !session.get_status().has_reported_all() && start.elapsed() < timeout {
+        session.poll();
+        std::thread::sleep(POLL_PERIOD);
+    }
+    let unreachable = session.get_status().get_unreachable_modules();
+    if !unreachable.is_empty() {
+        return Err(CliError::Unreachable(unreachable));
+    }
+    return Ok(());
+}
+
+/// Wait for the running sequence to finish, printing each step as it is reported
+fn wait_for_sequence(session: &mut Session) -> Result<RunSequence, CliError> {
+    let mut last_step = String::new();
+    loop {
+        session.poll();
+        if session.get_run_step() != last_step {
+            last_step = String::from(session.get_run_step());
+            println!("{last_step}");
+        }
+        if let Some((sequence, result)) = session.take_sequence_outcome() {
+            result?;
+            return Ok(sequence);
+        }
+        std::thread::sleep(POLL_PERIOD);
+    }
+}
+
+/// Wait for every operation in flight to be answered, then check that none of them failed
+fn wait_for_operations(session: &mut Session) -> Result<(), CliError> {
+    let start = Instant::now();
+    while session.has_pending_operations() {
+        if start.elapsed() > OPERATION_WAIT {
+            return Err(CliError::Timeout(format!(
+                "Operations were not answered after {}s",
+                OPERATION_WAIT.as_secs()
+            )));
+        }
+        session.poll();
+        std::thread::sleep(POLL_PERIOD);
+    }
+    let status = session.get_status();
+    let failures: Vec<String> = session
+        .get_topology()
+        .module_ids()
+        .into_iter()
+        .filter_map(|id| {
+            status
+                .get_ecc_error(id as usize)
+                .map(|e| format!("module {id}: {e}"))
+        })
+        .collect();
+    if !failures.is_empty() {
+        return Err(CliError::Failed(format!(
+            "Operations failed for {}",
+            failures.join(", ")
+        )));
+    }
+    return Ok(());
+}
+
+/// Print the status of every module as plain text, one module per line
+fn print_status(session: &Session) {
+    let status = session.get_status();
+    let topology = session.get_topology();
+    println!(
+        "Run {} | ECC system: {} | Surveyor system: {}",
+        session.config.run_number,
+        status.get_system_ecc_status(),
+        status.get_surveyor_system_status()
+    );
+    println!(
+        "{:<8}{:<8}{:<14}{:<16}{:<8}{:<12}{:>12}  Full In",
+        "Module", "Role", "ECC", "Surveyor", "Files", "Bytes", "Rate(MB/s)"
+    );
+    let eccs = status.get_ecc_status_response();
+    let surveyors = status.get_surveyor_status_response();
+    let surveyor_ids = status.get_surveyor_ids();
+    for id in topology.module_ids() {
+        let role = match topology.get_module(id) {
+            Some(module) => module.role.clone(),
+            None => ModuleRole::CoBo,
+        };
+        if status.is_masked(id as usize) {
+            println!("{:<8}{:<8}Masked", id, role.to_string());
+            continue;
+        }
+        let ecc = &eccs[id as usize];
+        let ecc_text = match ecc.connection.get_detail() {
+            Some(_) => ecc.connection.to_string(),
+            None => ECCStatus::from(ecc.state).to_string(),
+        };
+        let surveyor = surveyor_ids
+            .iter()
+            .position(|sid| *sid == id)
+            .map(|idx| &surveyors[idx]);
+        match surveyor {
+            Some(s) => {
+                let surveyor_text = match s.connection.get_detail() {
+                    Some(_) => s.connection.to_string(),
+                    None => SurveyorState::from(s.state).to_string(),
+                };
+                let full_in = match session.get_disk_forecast(id) {
+                    Some(forecast) => format!(
+                        "{} ({})",
+                        format_time_to_full(forecast.time_to_full),
+                        forecast.level
+                    ),
+                    None => String::from("N/A"),
+                };
+                println!(
+                    "{:<8}{:<8}{:<14}{:<16}{:<8}{:<12}{:>12.2}  {}",
+                    id,
+                    role.to_string(),
+                    ecc_text,
+                    surveyor_text,
+                    s.files,
+                    human_bytes::human_bytes(s.bytes_used as f64),
+                    s.data_rate,
+                    full_in
+                );
+            }
+            None => println!("{:<8}{:<8}{:<14}-", id, role.to_string(), ecc_text),
+        }
+        if let Some(error) = status.get_ecc_error(id as usize) {
+            println!("        Last error: {error}");
+        }
+        match session.get_data_flow(id) {
+            Some(DataFlow::Ok) | None => (),
+            Some(flow) => println!("        Data flow: {flow}"),
+        }
+    }
+}
+
+fn execute(session: &mut Session, options: &Options) -> Result<(), CliError> {
+    if options.command == Command::Watch {
+        //Unreachable modules are part of what is being watched, so they are not an error here
+        let _ = wait_for_contact(session, options.timeout);
+        loop {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO);
+            println!("--- {} ---", now.as_secs());
+            print_status(session);
+            poll_for(session, options.interval);
+        }
+    }
+
+    let contact = wait_for_contact(session, options.timeout);
+    //The status is worth printing even if some modules could not be reached
+    if options.command == Command::Status {
+        print_status(session);
+    }
+    contact?;
+    match options.command {
+        Command::Connect => {
+            println!(
+                "Connected to all {} modules of {}",
+                session
+                    .get_topology()
+                    .active_modules(&session.config.masked_modules)
+                    .len(),
+                session.get_topology().name
+            );
+        }
+        Command::Progress => {
+            session.progress_system()?;
+            wait_for_sequence(session)?;
+            println!("System is {}", session.get_status().get_system_ecc_status());
+        }
+        Command::Regress => {
+            let submitted = session.regress_system()?;
+            wait_for_operations(session)?;
+            println!("{submitted} operations complete");
+        }
+        Command::Start => {
+            let run_number = session.config.run_number;
+            session.start_run()?;
+            wait_for_sequence(session)?;
+            println!("Run {run_number} started");
+        }
+        Command::Stop => {
+            let run_number = session.config.run_number;
+            let duration = session.config.get_run_duration();
+            session.stop_run()?;
+            wait_for_sequence(session)?;
+            match duration {
+                Some(d) => println!("Run {run_number} stopped after {}s", d.as_secs()),
+                None => println!("Run {run_number} stopped"),
+            }
+        }
+        Command::Status | Command::Watch => (),
+    }
+    return Ok(());
+}
+
+fn run() -> Result<(), CliError> {
+    let options = parse_args()?;
+
+    //Logs go to stderr, so that stdout can be read by scripts. The log of the current run gets everything at info.
+    let run_log = RunLog::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .compact()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_target(false)
+                .with_filter(match options.verbose {
+                    true => LevelFilter::INFO,
+                    false => LevelFilter::WARN,
+                }),
+        )
+        .with(RunLogLayer::new(&run_log).with_filter(LevelFilter::INFO));
+    tracing::subscriber::set_global_default(subscriber)
+        .expect("Could not initialize the tracing system!");
+
+    let runtime: tokio::runtime::Runtime = Builder::new_multi_thread()
+        .worker_threads(5)
+        .enable_time()
+        .enable_io()
+        .build()
+        .expect("Could not startup async runtime!");
+
+    let mut session = Session::new(runtime);
+    session.set_run_log(run_log);
+    session
+        .read_config(options.config.clone())
+        .map_err(CliError::Config)?;
+    session.connect()?;
+    let result = execute(&mut session, &options);
+    if let Err(e) = session.disconnect() {
+        tracing::error!("{}", e);
+    }
+    return result;
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("envoy_cli: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
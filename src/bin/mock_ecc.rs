@@ -0,0 +1,166 @@
+//! A stand-in for the ECC servers of the AT-TPC, for testing attpc_envoy without the CoBo rack.
+//!
+//! Usage:
+//!     mock_ecc [--bind ADDR] [--port PORT] [--count N] [--topology FILE]
+//!              [--latency [ID:]OP=MS]... [--error [ID:]OP=CODE]...
+//!
+//! With --topology, one server is started for each module on the ECC port given in the topology.
+//! Otherwise N servers are started on consecutive ports beginning at PORT (default 8083), the last
+//! of which is the MuTaNT. Latencies and injected error codes can be given for all servers, or for
+//! a single module by prefixing the module id.
+use attpc_envoy::envoy::topology::{ModuleRole, Topology};
+use attpc_envoy::mock::ecc::{MockECCOptions, MockECCServer};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: mock_ecc [--bind ADDR] [--port PORT] [--count N] [--topology FILE] [--latency [ID:]OP=MS]... [--error [ID:]OP=CODE]...";
+
+/// An override of the form [ID:]OP=VALUE
+struct Override {
+    id: Option<i32>,
+    operation: String,
+    value: i64,
+}
+
+fn parse_override(arg: &str) -> Result<Override, String> {
+    let (target, value) = arg
+        .split_once('=')
+        .ok_or(format!("Expected [ID:]OP=VALUE, got {arg}"))?;
+    let (id, operation) = match target.split_once(':') {
+        Some((id, op)) => (
+            Some(
+                id.parse::<i32>()
+                    .map_err(|e| format!("Bad id in {arg}: {e}"))?,
+            ),
+            op,
+        ),
+        None => (None, target),
+    };
+    let value = value
+        .parse::<i64>()
+        .map_err(|e| format!("Bad value in {arg}: {e}"))?;
+    Ok(Override {
+        id,
+        operation: String::from(operation),
+        value,
+    })
+}
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a String, String> {
+    args.next().ok_or(format!("{flag} requires a value"))
+}
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut bind: IpAddr = IpAddr::from([127, 0, 0, 1]);
+    let mut port: u16 = 8083;
+    let mut count: i32 = 1;
+    let mut topology_path: Option<PathBuf> = None;
+    let mut latencies: Vec<Override> = vec![];
+    let mut errors: Vec<Override> = vec![];
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bind" => {
+                bind = next_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|e| format!("Bad bind address: {e}"))?
+            }
+            "--port" => {
+                port = next_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|e| format!("Bad port: {e}"))?
+            }
+            "--count" => {
+                count = next_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|e| format!("Bad count: {e}"))?
+            }
+            "--topology" => topology_path = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--latency" => latencies.push(parse_override(next_value(&mut iter, arg)?)?),
+            "--error" => errors.push(parse_override(next_value(&mut iter, arg)?)?),
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => return Err(format!("Unrecognized argument {arg}\n{USAGE}")),
+        }
+    }
+
+    //(id, port, role) of each server to start
+    let servers: Vec<(i32, u16, ModuleRole)> = match topology_path {
+        Some(path) => {
+            let topology = Topology::read(&path).map_err(|e| e.to_string())?;
+            topology
+                .modules
+                .iter()
+                .map(|m| (m.id, m.ecc.port, m.role.clone()))
+                .collect()
+        }
+        None => (0..count)
+            .map(|id| {
+                let role = if id == count - 1 {
+                    ModuleRole::Mutant
+                } else {
+                    ModuleRole::CoBo
+                };
+                (id, port + id as u16, role)
+            })
+            .collect(),
+    };
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Could not startup async runtime: {e}"))?;
+
+    runtime.block_on(async {
+        let mut handles = vec![];
+        for (id, port, role) in servers {
+            let mut options = MockECCOptions {
+                latency: HashMap::new(),
+                errors: HashMap::new(),
+                role: Some(role),
+            };
+            for o in latencies
+                .iter()
+                .filter(|o| o.id.is_none() || o.id == Some(id))
+            {
+                options
+                    .latency
+                    .insert(o.operation.clone(), Duration::from_millis(o.value as u64));
+            }
+            for o in errors.iter().filter(|o| o.id.is_none() || o.id == Some(id)) {
+                options.errors.insert(o.operation.clone(), o.value as i32);
+            }
+            let server = MockECCServer::new(id, SocketAddr::new(bind, port), options);
+            handles.push(tokio::spawn(async move {
+                if let Err(e) = server.serve().await {
+                    tracing::error!("Mock ECC server {} stopped: {}", id, e);
+                }
+            }));
+        }
+        for handle in handles {
+            let _ = handle.await;
+        }
+    });
+    Ok(())
+}
+
+fn main() {
+    tracing_subscriber::fmt()
+        .compact()
+        .with_target(false)
+        .init();
+
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,134 @@
+//! A stand-in for the Surveyors of the AT-TPC data routers, for testing attpc_envoy without the DAQ Macs.
+//!
+//! Usage:
+//!     mock_surveyor [--bind ADDR] [--port PORT] [--count N] [--topology FILE] [--rate MB/s]
+//!                   [--file-size MB] [--disk-size GB] [--disk-used GB] [--directory DIR]
+//!
+//! With --topology, one server is started for each module with a Surveyor on the port given in the topology.
+//! Otherwise N servers are started on consecutive ports beginning at PORT (default 8081). Each server can be
+//! driven while running by POSTing to /control/offline, /control/online, /control/pause, /control/resume,
+//! /control/move and /control/rate?mbps=X.
+use attpc_envoy::envoy::topology::Topology;
+use attpc_envoy::mock::surveyor::{MockSurveyorOptions, MockSurveyorServer};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: mock_surveyor [--bind ADDR] [--port PORT] [--count N] [--topology FILE] [--rate MB/s] [--file-size MB] [--disk-size GB] [--disk-used GB] [--directory DIR]";
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a String, String> {
+    args.next().ok_or(format!("{flag} requires a value"))
+}
+
+/// Parse a (possibly fractional) amount of some unit into bytes
+fn parse_bytes(value: &str, unit: f64, flag: &str) -> Result<u64, String> {
+    match value.parse::<f64>() {
+        Ok(v) if v >= 0.0 => Ok((v * unit) as u64),
+        _ => Err(format!("Bad value for {flag}: {value}")),
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut bind: IpAddr = IpAddr::from([127, 0, 0, 1]);
+    let mut port: u16 = 8081;
+    let mut count: i32 = 1;
+    let mut topology_path: Option<PathBuf> = None;
+    let mut options = MockSurveyorOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bind" => {
+                bind = next_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|e| format!("Bad bind address: {e}"))?
+            }
+            "--port" => {
+                port = next_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|e| format!("Bad port: {e}"))?
+            }
+            "--count" => {
+                count = next_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|e| format!("Bad count: {e}"))?
+            }
+            "--topology" => topology_path = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--rate" => options.rate = parse_bytes(next_value(&mut iter, arg)?, 1.0e6, arg)?,
+            "--file-size" => {
+                options.file_size = parse_bytes(next_value(&mut iter, arg)?, 1.0e6, arg)?
+            }
+            "--disk-size" => {
+                options.disk_size = parse_bytes(next_value(&mut iter, arg)?, 1.0e9, arg)?
+            }
+            "--disk-used" => {
+                options.disk_used = parse_bytes(next_value(&mut iter, arg)?, 1.0e9, arg)?
+            }
+            "--directory" => options.directory = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => return Err(format!("Unrecognized argument {arg}\n{USAGE}")),
+        }
+    }
+
+    if options.file_size == 0 {
+        return Err(String::from("--file-size must be greater than zero"));
+    }
+
+    //(id, port) of each server to start
+    let servers: Vec<(i32, u16)> = match topology_path {
+        Some(path) => {
+            let topology = Topology::read(&path).map_err(|e| e.to_string())?;
+            topology
+                .modules
+                .iter()
+                .filter_map(|m| m.surveyor.as_ref().map(|s| (m.id, s.port)))
+                .collect()
+        }
+        None => (0..count).map(|id| (id, port + id as u16)).collect(),
+    };
+
+    let mut mocks = vec![];
+    for (id, port) in servers {
+        let mock = MockSurveyorServer::new(id, SocketAddr::new(bind, port), options.clone())
+            .map_err(|e| format!("Could not create mock Surveyor {id}: {e}"))?;
+        mocks.push((id, mock));
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Could not startup async runtime: {e}"))?;
+
+    runtime.block_on(async {
+        let mut handles = vec![];
+        for (id, mock) in mocks {
+            handles.push(tokio::spawn(async move {
+                if let Err(e) = mock.serve().await {
+                    tracing::error!("Mock Surveyor {} stopped: {}", id, e);
+                }
+            }));
+        }
+        for handle in handles {
+            let _ = handle.await;
+        }
+    });
+    Ok(())
+}
+
+fn main() {
+    tracing_subscriber::fmt()
+        .compact()
+        .with_target(false)
+        .init();
+
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
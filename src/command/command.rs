@@ -115,11 +115,19 @@ pub fn check_run_exists(
     run_number: &i32,
 ) -> Result<CommandStatus, std::io::Error> {
     let sub_command = format!("{SCRIPT_DIR}test_graw.sh");
+    let data = match surveyor_data.first() {
+        Some(d) => d,
+        None => {
+            return Err(std::io::Error::other(
+                "there are no active data routers to check",
+            ))
+        }
+    };
     let output = Command::new("zsh")
         .args([
             &sub_command,
-            &surveyor_data[0].address,
-            &surveyor_data[0].location,
+            &data.address,
+            &data.location,
             experiment,
             &(run_number.to_string()),
         ])
@@ -0,0 +1,66 @@
+use super::error::{EnvoyError, TransportError};
+use serde::{Deserialize, Serialize};
+
+const CONNECTION_NO_CONTACT_TEXT: &str = "No Contact";
+const CONNECTION_CONNECTED_TEXT: &str = "Connected";
+const CONNECTION_UNREACHABLE_TEXT: &str = "Unreachable";
+const CONNECTION_TIMEOUT_TEXT: &str = "Timeout";
+const CONNECTION_PARSE_ERROR_TEXT: &str = "Parse Error";
+
+/// # ConnectionStatus
+/// The outcome of the last attempt of an envoy to reach its server. This is separate from the state
+/// reported by the server, so that an unreachable server does not look like an Offline one.
+/// The failure variants carry the text of the underlying EnvoyError.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum ConnectionStatus {
+    #[default]
+    NoContact,
+    Connected,
+    Unreachable(String),
+    Timeout(String),
+    ParseError(String),
+}
+
+impl ConnectionStatus {
+    pub fn is_failure(&self) -> bool {
+        match self {
+            Self::Unreachable(_) | Self::Timeout(_) | Self::ParseError(_) => true,
+            Self::NoContact | Self::Connected => false,
+        }
+    }
+
+    /// The error behind a failure, if any
+    pub fn get_detail(&self) -> Option<&str> {
+        match self {
+            Self::Unreachable(e) | Self::Timeout(e) | Self::ParseError(e) => Some(e),
+            Self::NoContact | Self::Connected => None,
+        }
+    }
+}
+
+impl From<&EnvoyError> for ConnectionStatus {
+    fn from(value: &EnvoyError) -> Self {
+        let text = value.to_string();
+        match value {
+            EnvoyError::RequestError(e) if e.is_timeout() => Self::Timeout(text),
+            EnvoyError::TransportError(TransportError::Timeout) => Self::Timeout(text),
+            EnvoyError::OperationTimeout(_, _) => Self::Timeout(text),
+            EnvoyError::RequestError(_)
+            | EnvoyError::TransportError(_)
+            | EnvoyError::SendError(_) => Self::Unreachable(text),
+            _ => Self::ParseError(text),
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoContact => write!(f, "{CONNECTION_NO_CONTACT_TEXT}"),
+            Self::Connected => write!(f, "{CONNECTION_CONNECTED_TEXT}"),
+            Self::Unreachable(_) => write!(f, "{CONNECTION_UNREACHABLE_TEXT}"),
+            Self::Timeout(_) => write!(f, "{CONNECTION_TIMEOUT_TEXT}"),
+            Self::ParseError(_) => write!(f, "{CONNECTION_PARSE_ERROR_TEXT}"),
+        }
+    }
+}
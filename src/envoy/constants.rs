@@ -1,5 +1,15 @@
+//! Defaults describing the full AT-TPC. These are only used to build the
+//! topology when no topology file has been given; see envoy::topology.
 pub const NUMBER_OF_MODULES: i32 = 12;
 pub const MUTANT_ID: i32 = 11;
 pub const PROTOCOL: &str = "TCP";
-pub const LISTENER_PORT: i32 = 46005;
+pub const LISTENER_PORT: u16 = 46005;
 pub const ADDRESS_START: &str = "192.168.41";
+pub const ECC_PORT: u16 = 8083;
+pub const SURVEYOR_PORT: u16 = 8081;
+pub const SURVEYOR_PATH: &str = "/~attpc/surveyor.html";
+
+/// Seconds between status queries made by the ECC and Surveyor status envoys
+pub const STATUS_POLL_SECS: u64 = 2;
+/// A module is stale if no status update has arrived within this many poll intervals
+pub const STALE_POLL_INTERVALS: u64 = 5;
@@ -1,17 +1,20 @@
-use super::constants::{ADDRESS_START, LISTENER_PORT, MUTANT_ID, NUMBER_OF_MODULES, PROTOCOL};
-use super::ecc_operation::ECCOperation;
-use super::error::EnvoyError;
-use super::message::EmbassyMessage;
-use reqwest::{Client, Response};
+use super::connection_status::ConnectionStatus;
+use super::constants::STATUS_POLL_SECS;
+use super::ecc_operation::{ECCOperation, OperationPolicy};
+use super::error::{EnvoyError, TransportError};
+use super::message::{EmbassyMessage, MessageTiming};
+use super::soap::{parse_ecc_operation_response, parse_ecc_status_response};
+use super::supervisor::{EnvoyKind, Supervisor};
+use super::topology::{ModuleConfig, ModuleRole, Topology};
+use super::transport::{HttpTransport, Transport};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 
-const ECC_URL_PORT: i32 = 8083;
-
 const ECC_SOAP_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
     <SOAP-ENV:Envelope 
     xmlns:SOAP-ENV="http://schemas.xmlsoap.org/soap/envelope/" 
@@ -27,44 +30,78 @@ const ECC_SOAP_FOOTER: &str = r#"
     </SOAP-ENV:Envelope>
 "#;
 
+/// Time allowed to connect to an ECC server. Request timeouts are set per operation (see OperationPolicy)
+const ECC_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error code reported when an operation did not complete within its deadline
+pub const ECC_TIMEOUT_ERROR_CODE: i32 = -1;
+/// Error code reported when an operation failed on our side (unreachable server, bad reply, ...)
+pub const ECC_ENVOY_ERROR_CODE: i32 = -2;
+
 /// Response type for ECC Operations (transitions)
-/// Native format is XML
+/// Native format is XML, where the fields are named as in the aliases (see envoy::soap)
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct ECCOperationResponse {
+    #[serde(alias = "ErrorCode")]
     pub error_code: i32,
+    #[serde(alias = "ErrorMessage", default)]
     pub error_message: String,
+    #[serde(alias = "Text", default)]
     pub text: String,
 }
 
+impl ECCOperationResponse {
+    /// A response for an operation which never got a reply from the server
+    pub fn failure(error: &EnvoyError) -> Self {
+        let error_code = match error {
+            EnvoyError::OperationTimeout(_, _) => ECC_TIMEOUT_ERROR_CODE,
+            _ => ECC_ENVOY_ERROR_CODE,
+        };
+        Self {
+            error_code,
+            error_message: error.to_string(),
+            text: String::new(),
+        }
+    }
+}
+
 /// Response type for ECC status query
-/// Native format is XML
+/// Native format is XML, where the fields are named as in the aliases (see envoy::soap)
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct ECCStatusResponse {
+    #[serde(alias = "ErrorCode")]
     pub error_code: i32,
+    #[serde(alias = "ErrorMessage", default)]
     pub error_message: String,
+    #[serde(alias = "State")]
     pub state: i32,
+    #[serde(alias = "Transition", default)]
     pub transition: i32,
+    #[serde(default)]
+    pub connection: ConnectionStatus,
 }
 
 #[derive(Debug, Clone)]
 pub struct ECCConfig {
     id: i32,
+    role: ModuleRole,
     experiment: String,
-    address: String,
+    router_address: String,
+    router_port: u16,
+    router_protocol: String,
     url: String,
 }
 
 impl ECCConfig {
-    pub fn new(id: i32, experiment: &str) -> ECCConfig {
-        let address = match id {
-            MUTANT_ID => format!("{ADDRESS_START}.1"),
-            _ => format!("{ADDRESS_START}.{}", 60 + id),
-        };
-        let url = Self::url(&address);
+    pub fn new(module: &ModuleConfig, experiment: &str) -> ECCConfig {
+        let url = Self::url(&module.ecc.host, module.ecc.port);
         return ECCConfig {
-            id,
+            id: module.id,
+            role: module.role.clone(),
             experiment: experiment.to_string(),
-            address,
+            router_address: module.data_router.ip.clone(),
+            router_port: module.data_router.port,
+            router_protocol: module.data_router.protocol.clone(),
             url,
         };
     }
@@ -92,14 +129,16 @@ impl ECCConfig {
 
     fn compose_data_link_body(&self) -> String {
         let source = self.source();
-        let ip = self.address.clone();
+        let ip = self.router_address.clone();
         let router = self.data_router();
+        let port = self.router_port;
+        let protocol = self.router_protocol.clone();
         format!(
             r#"<table>
                         <DataLinkSet>
                             <DataLink>
                                 <DataSender id="{source}" />
-                                <DataRouter ipAddress="{ip}" name="{router}" port="{LISTENER_PORT}" type="{PROTOCOL}" />
+                                <DataRouter ipAddress="{ip}" name="{router}" port="{port}" type="{protocol}" />
                             </DataLink>
                         </DataLinkSet>
                     </table>"#
@@ -107,16 +146,16 @@ impl ECCConfig {
     }
 
     fn describe(&self) -> String {
-        match self.id {
-            MUTANT_ID => self.experiment.clone(),
-            _ => format!("cobo{}", self.id),
+        match self.role {
+            ModuleRole::Mutant => self.experiment.clone(),
+            ModuleRole::CoBo => format!("cobo{}", self.id),
         }
     }
 
     fn source(&self) -> String {
-        match self.id {
-            MUTANT_ID => format!("Mutant[master]"),
-            _ => format!("CoBo[{}]", self.id),
+        match self.role {
+            ModuleRole::Mutant => String::from("Mutant[master]"),
+            ModuleRole::CoBo => format!("CoBo[{}]", self.id),
         }
     }
 
@@ -124,8 +163,8 @@ impl ECCConfig {
         format!("data{}", self.id)
     }
 
-    fn url(address: &str) -> String {
-        format!("http://{}:{}", address, ECC_URL_PORT)
+    fn url(host: &str, port: u16) -> String {
+        format!("http://{}:{}", host, port)
     }
 }
 
@@ -134,10 +173,12 @@ impl ECCConfig {
 /// ECCEnvoys have two modes, status check and transition. Transition envoys tell the server
 /// when to load/unload configuration data. Status check envoys simply check the status
 /// of the server every few seconds.
+///
+/// Requests go through a Transport, which is an HttpTransport to the ECC server outside of tests.
 #[derive(Debug)]
 pub struct ECCEnvoy {
     config: ECCConfig,
-    connection: Client,
+    transport: Arc<dyn Transport>,
     incoming: mpsc::Receiver<EmbassyMessage>,
     outgoing: mpsc::Sender<EmbassyMessage>,
     cancel: broadcast::Receiver<EmbassyMessage>,
@@ -146,31 +187,24 @@ pub struct ECCEnvoy {
 impl ECCEnvoy {
     pub fn new(
         config: ECCConfig,
+        transport: Arc<dyn Transport>,
         rx: mpsc::Receiver<EmbassyMessage>,
         tx: mpsc::Sender<EmbassyMessage>,
         cancel: broadcast::Receiver<EmbassyMessage>,
-    ) -> Result<Self, EnvoyError> {
-        //120s (2min) default timeouts to match ECCServer/Client
-        let connection_out = Duration::from_secs(120);
-        let req_timeout = Duration::from_secs(120);
-
-        //Probably need some options here, for now just set some timeouts
-        let client = Client::builder()
-            .connect_timeout(connection_out)
-            .timeout(req_timeout)
-            .build()?;
-        return Ok(Self {
+    ) -> Self {
+        return Self {
             config,
-            connection: client,
+            transport,
             incoming: rx,
             outgoing: tx,
             cancel,
-        });
+        };
     }
 
     /// This one of the core task loops for an ECCEnvoy. Waits for a
     /// message from the embassy to transition the configuration of
-    /// an ECC Server.
+    /// an ECC Server. Every transition gets a reply; if the operation fails or times out
+    /// the reply carries the reason, so that the UI can release the module.
     pub async fn wait_for_transition(&mut self) -> Result<(), EnvoyError> {
         loop {
             tokio::select! {
@@ -179,11 +213,21 @@ impl ECCEnvoy {
                 }
 
                 data = self.incoming.recv() => {
-                    if let Some(message) = data {
-                        let response = self.submit_transition(message).await?;
-                        self.outgoing.send(response).await?;
-                    } else {
-                        return Ok(())
+                    match data {
+                        Some(EmbassyMessage::ECCOperation { operation, op_id, .. }) => {
+                            let timing = MessageTiming::start();
+                            let response = match self.submit_transition(&operation).await {
+                                Ok(r) => r,
+                                Err(e) => {
+                                    tracing::error!("ECC envoy {} failed to {}: {}", self.config.id, operation, e);
+                                    ECCOperationResponse::failure(&e)
+                                }
+                            };
+                            let message = EmbassyMessage::compose_ecc_response(operation, op_id, response, self.config.id, timing.finish());
+                            self.outgoing.send(message).await?;
+                        }
+                        Some(message) => tracing::warn!("ECC envoy {} ignored unexpected message: {}", self.config.id, message),
+                        None => return Ok(())
                     }
                 }
             }
@@ -199,165 +243,107 @@ impl ECCEnvoy {
                     return Ok(());
                 }
 
-                _ = tokio::time::sleep(Duration::from_secs(2)) => {
-                    if let Ok(response) = self.submit_check_status().await {
-                        self.outgoing.send(response).await?
-                    } else {
-                        let response = ECCStatusResponse { error_code: 0, error_message: String::from(""), state: 0, transition: 0 };
-                        let message = EmbassyMessage::compose_ecc_status(serde_yaml::to_string(&response)?, self.config.id);
-                        self.outgoing.send(message).await?
-                    }
+                _ = tokio::time::sleep(Duration::from_secs(STATUS_POLL_SECS)) => {
+                    let timing = MessageTiming::start();
+                    let response = match self.submit_check_status().await {
+                        Ok(response) => response,
+                        Err(e) => ECCStatusResponse { connection: ConnectionStatus::from(&e), ..Default::default() },
+                    };
+                    let message = EmbassyMessage::compose_ecc_status(response, self.config.id, timing.finish());
+                    self.outgoing.send(message).await?
                 }
             }
         }
     }
 
+    /// Submit a transition following the policy of the operation. The whole exchange, including retries,
+    /// must complete within the policy timeout.
     async fn submit_transition(
         &self,
-        message: EmbassyMessage,
-    ) -> Result<EmbassyMessage, EnvoyError> {
-        let ecc_message = self.compose_ecc_transition_request(message)?;
+        operation: &ECCOperation,
+    ) -> Result<ECCOperationResponse, EnvoyError> {
+        let policy = operation.get_policy();
+        let ecc_message = self.compose_ecc_transition_request(operation);
         let response = self
-            .connection
-            .post(&self.config.url)
-            .header("ContentType", "text/xml")
-            .body(ecc_message)
-            .send()
+            .post_with_policy(&operation.to_string(), ecc_message, &policy)
             .await?;
-        let parsed_response = self.parse_ecc_operation_response(response).await?;
+        let parsed_response = parse_ecc_operation_response(&response)?;
         Ok(parsed_response)
     }
 
-    async fn submit_check_status(&self) -> Result<EmbassyMessage, EnvoyError> {
+    async fn submit_check_status(&self) -> Result<ECCStatusResponse, EnvoyError> {
         let message = format!("{ECC_SOAP_HEADER}<GetState>\n</GetState>\n{ECC_SOAP_FOOTER}");
         let response = self
-            .connection
-            .post(&self.config.url)
-            .header("ContentType", "text/xml")
-            .body(message)
-            .send()
+            .post_with_policy("GetState", message, &OperationPolicy::get_state())
             .await?;
-        let parsed_response = self.parse_ecc_status_response(response).await?;
+        let mut parsed_response = parse_ecc_status_response(&response)?;
+        parsed_response.connection = ConnectionStatus::Connected;
         Ok(parsed_response)
     }
 
-    async fn parse_ecc_operation_response(
+    /// Post a message to the ECC server. If the server cannot be reached the message is re-sent
+    /// up to policy.retries times, as long as there is time left before the deadline.
+    async fn post_with_policy(
         &self,
-        response: Response,
-    ) -> Result<EmbassyMessage, EnvoyError> {
-        let text = response.text().await?;
-        let mut reader = quick_xml::Reader::from_str(&text);
-        let mut parsed = ECCOperationResponse::default();
-
-        reader.read_event()?; //Opening
-        reader.read_event()?; //Junk
-        reader.read_event()?; //SOAP Decl
-        reader.read_event()?; //SOAP Body
-        reader.read_event()?; //ECC
-        reader.read_event()?; //ErrorCode start tag
-        let event = reader.read_event()?; //ErrorCode payload
-        parsed.error_code = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?.parse()?,
-            _ => return Err(EnvoyError::XMLConversionError),
-        };
-        reader.read_event()?; //ErrorCode end tag
-        reader.read_event()?; //ErrorMesage start tag
-        let event = reader.read_event()?; //ErrorMessage payload or end tag
-        let mut is_msg = true;
-        parsed.error_message = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?,
-            _ => {
-                is_msg = false;
-                String::from("")
+        name: &str,
+        message: String,
+        policy: &OperationPolicy,
+    ) -> Result<String, EnvoyError> {
+        let deadline = tokio::time::Instant::now() + policy.timeout;
+        let mut attempt = 0;
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(EnvoyError::OperationTimeout(
+                    String::from(name),
+                    policy.timeout,
+                ));
             }
-        };
-        if is_msg {
-            reader.read_event()?; //ErrorMessage end tag
-        }
-        reader.read_event()?; //Text start tag
-        let event = reader.read_event()?; //Text payload
-        parsed.text = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?,
-            _ => String::from(""),
-        };
-
-        Ok(EmbassyMessage::compose_ecc_response(
-            serde_yaml::to_string(&parsed)?,
-            self.config.id,
-        ))
-    }
-
-    async fn parse_ecc_status_response(
-        &self,
-        response: Response,
-    ) -> Result<EmbassyMessage, EnvoyError> {
-        let text = response.text().await?;
-        let mut reader = quick_xml::Reader::from_str(&text);
-        let mut parsed: ECCStatusResponse = ECCStatusResponse::default();
-
-        reader.read_event()?; //Opening
-        reader.read_event()?; //Junk
-        reader.read_event()?; //SOAP Decl
-        reader.read_event()?; //SOAP Body
-        reader.read_event()?; //ECC
-        reader.read_event()?; //ErrorCode start tag
-        let event = reader.read_event()?; //ErrorCode payload
-        parsed.error_code = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?.parse()?,
-            _ => return Err(EnvoyError::XMLConversionError),
-        };
-        reader.read_event()?; //ErrorCode end tag
-        reader.read_event()?; //ErrorMesage start tag
-        let event = reader.read_event()?; //ErrorMessage payload or end tag
-        let mut is_msg = true;
-        parsed.error_message = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?,
-            _ => {
-                is_msg = false;
-                String::from("")
+            let result = self
+                .transport
+                .post(&self.config.url, message.clone(), remaining)
+                .await;
+            match result {
+                Ok(response) => return Ok(response),
+                Err(TransportError::Timeout) => {
+                    return Err(EnvoyError::OperationTimeout(
+                        String::from(name),
+                        policy.timeout,
+                    ))
+                }
+                Err(TransportError::Connect(e)) if attempt < policy.retries => {
+                    attempt += 1;
+                    tracing::warn!(
+                        "ECC envoy {} could not reach the server for {}, retrying ({}/{}): {}",
+                        self.config.id,
+                        name,
+                        attempt,
+                        policy.retries,
+                        e
+                    );
+                    tokio::time::sleep(policy.retry_delay.min(remaining)).await;
+                }
+                Err(e) => return Err(e.into()),
             }
-        };
-        if is_msg {
-            reader.read_event()?; //ErrorMessage end tag
         }
-        reader.read_event()?; //State start tag
-        let event = reader.read_event()?; //State payload
-        parsed.state = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?.parse()?,
-            _ => return Err(EnvoyError::XMLConversionError),
-        };
-        reader.read_event()?; //State end tag
-        reader.read_event()?; //Transition start tag
-        let event = reader.read_event()?; //Transition payload
-        parsed.transition = match event {
-            quick_xml::events::Event::Text(t) => String::from_utf8(t.to_vec())?.parse()?,
-            _ => return Err(EnvoyError::XMLConversionError),
-        };
-
-        let status_response =
-            EmbassyMessage::compose_ecc_status(serde_yaml::to_string(&parsed)?, self.config.id);
-        Ok(status_response)
     }
 
-    fn compose_ecc_transition_request(
-        &self,
-        message: EmbassyMessage,
-    ) -> Result<String, EnvoyError> {
-        let op = ECCOperation::try_from(message.operation)?;
+    fn compose_ecc_transition_request(&self, op: &ECCOperation) -> String {
         let config = self.config.compose_config_body();
         let link = self.config.compose_data_link_body();
-        return Ok(format!(
-            "{ECC_SOAP_HEADER}<{op}>\n{config}{link}</{op}>\n{ECC_SOAP_FOOTER}"
-        ));
+        return format!("{ECC_SOAP_HEADER}<{op}>\n{config}{link}</{op}>\n{ECC_SOAP_FOOTER}");
     }
 }
 
 /// Startup the ECC communication system
-/// Takes in a runtime, experiment name, and a channel to send data to the embassy. Spawns the ECCEnvoys with tasks to either wait for
-/// a command to transition that ECC DAQ or to periodically check the status of that particular ECC DAQ.
+/// Takes in a runtime, experiment name, topology, and a channel to send data to the embassy. Spawns the ECCEnvoys with tasks to either wait for
+/// a command to transition that ECC DAQ or to periodically check the status of that particular ECC DAQ. Masked modules do not get envoys.
+/// Every envoy runs under a Supervisor, which restarts it if it dies.
 pub fn startup_ecc_envoys(
     runtime: &mut tokio::runtime::Runtime,
     experiment: &str,
+    topology: &Topology,
+    masked: &[i32],
     ecc_tx: &mpsc::Sender<EmbassyMessage>,
     cancel: &broadcast::Sender<EmbassyMessage>,
 ) -> (
@@ -368,44 +354,55 @@ pub fn startup_ecc_envoys(
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
     //spin up the transition envoys
-    for id in 0..NUMBER_OF_MODULES {
-        let config = ECCConfig::new(id, experiment);
+    for module in topology.active_modules(masked) {
+        let config = ECCConfig::new(module, experiment);
         let (embassy_tx, ecc_rx) = mpsc::channel::<EmbassyMessage>(10);
         let this_ecc_tx = ecc_tx.clone();
-        let this_cancel = cancel.subscribe();
-        let handle = runtime.spawn(async move {
-            match ECCEnvoy::new(config, ecc_rx, this_ecc_tx, this_cancel) {
-                Ok(mut ev) => match ev.wait_for_transition().await {
-                    Ok(()) => (),
-                    Err(e) => tracing::error!("ECC transition envoy ran into an error: {}", e),
-                },
-                Err(e) => tracing::error!("Error creating ECC transition envoy: {}", e),
-            }
-        });
+        let supervisor = Supervisor::new(
+            module.id,
+            EnvoyKind::ECCTransition,
+            Some(ecc_rx),
+            ecc_tx,
+            cancel,
+            move |rx, this_cancel| {
+                let config = config.clone();
+                let tx = this_ecc_tx.clone();
+                async move {
+                    let transport = Arc::new(HttpTransport::new(ECC_CONNECT_TIMEOUT)?);
+                    let mut ev = ECCEnvoy::new(config, transport, rx, tx, this_cancel);
+                    ev.wait_for_transition().await
+                }
+            },
+        );
 
-        transition_switchboard.insert(id, embassy_tx);
-        handles.push(handle);
+        transition_switchboard.insert(module.id, embassy_tx);
+        handles.push(supervisor.spawn(runtime));
     }
 
     //spin up the status envoys
-    for id in 0..NUMBER_OF_MODULES {
-        let config = ECCConfig::new(id, experiment);
+    for module in topology.active_modules(masked) {
+        let config = ECCConfig::new(module, experiment);
         //The incoming channel is unused in the status envoy, however this may be changed later.
         //Could be useful to tie the update rate to the GUI?
-        let (_, ecc_rx) = mpsc::channel::<EmbassyMessage>(10);
         let this_ecc_tx = ecc_tx.clone();
-        let this_cancel = cancel.subscribe();
-        let handle = runtime.spawn(async move {
-            match ECCEnvoy::new(config, ecc_rx, this_ecc_tx, this_cancel) {
-                Ok(mut ev) => match ev.wait_check_status().await {
-                    Ok(()) => (),
-                    Err(e) => tracing::error!("ECC status envoy ran into an error: {}", e),
-                },
-                Err(e) => tracing::error!("Error creating ECC status envoy: {}", e),
-            }
-        });
+        let supervisor = Supervisor::new(
+            module.id,
+            EnvoyKind::ECCStatus,
+            None,
+            ecc_tx,
+            cancel,
+            move |rx, this_cancel| {
+                let config = config.clone();
+                let tx = this_ecc_tx.clone();
+                async move {
+                    let transport = Arc::new(HttpTransport::new(ECC_CONNECT_TIMEOUT)?);
+                    let mut ev = ECCEnvoy::new(config, transport, rx, tx, this_cancel);
+                    ev.wait_check_status().await
+                }
+            },
+        );
 
-        handles.push(handle);
+        handles.push(supervisor.spawn(runtime));
     }
 
     return (handles, transition_switchboard);
@@ -1,4 +1,6 @@
 use super::error::{ECCOperationError, ECCStatusError};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 const ECC_OFFLINE_STATUS: &str = "Offline";
 const ECC_BUSY_STATUS: &str = "Busy";
@@ -69,8 +71,8 @@ impl Into<i32> for ECCStatus {
         match self {
             Self::Offline => 0,
             Self::Idle => 1,
-            Self::Prepared => 2,
-            Self::Described => 3,
+            Self::Described => 2,
+            Self::Prepared => 3,
             Self::Ready => 4,
             Self::Running => 5,
             Self::Busy => 6,
@@ -150,7 +152,7 @@ impl ECCStatus {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ECCOperation {
     Describe,
     Prepare,
@@ -177,6 +179,24 @@ impl std::fmt::Display for ECCOperation {
     }
 }
 
+impl ECCOperation {
+    /// The GET state machine: the status a server lands in once the operation completes. Returns None if the
+    /// operation is not allowed from the status.
+    pub fn get_next_status(&self, status: &ECCStatus) -> Option<ECCStatus> {
+        match (self, status) {
+            (Self::Describe, ECCStatus::Idle) => Some(ECCStatus::Described),
+            (Self::Prepare, ECCStatus::Described) => Some(ECCStatus::Prepared),
+            (Self::Configure, ECCStatus::Prepared) => Some(ECCStatus::Ready),
+            (Self::Start, ECCStatus::Ready) => Some(ECCStatus::Running),
+            (Self::Stop, ECCStatus::Running) => Some(ECCStatus::Ready),
+            (Self::Breakup, ECCStatus::Ready) => Some(ECCStatus::Prepared),
+            (Self::Undo, ECCStatus::Prepared) => Some(ECCStatus::Described),
+            (Self::Undo, ECCStatus::Described) => Some(ECCStatus::Idle),
+            _ => None,
+        }
+    }
+}
+
 impl TryFrom<String> for ECCOperation {
     type Error = ECCOperationError;
     fn try_from(value: String) -> Result<Self, Self::Error> {
@@ -208,3 +228,48 @@ impl Into<String> for ECCOperation {
         })
     }
 }
+
+/// # OperationPolicy
+/// How long an ECC request may take before it is abandoned, and how many times it may be retried.
+/// Retries are only made when the server could not be reached at all; once a request has been received
+/// the server may be acting on it, so it is never sent twice.
+#[derive(Debug, Clone)]
+pub struct OperationPolicy {
+    pub timeout: Duration,
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl OperationPolicy {
+    /// Policy for the periodic GetState query. It is asked again in a few seconds anyways, so no retries
+    pub fn get_state() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            retries: 0,
+            retry_delay: Duration::ZERO,
+        }
+    }
+}
+
+impl ECCOperation {
+    /// The policy for this kind of operation. Describe, Prepare, and Configure load configuration
+    /// data onto the modules and can take minutes on the full detector. Start and Stop are quick but
+    /// timing critical, so they are given an extra retry.
+    pub fn get_policy(&self) -> OperationPolicy {
+        let (secs, retries) = match self {
+            Self::Describe => (120, 1),
+            Self::Prepare => (300, 1),
+            Self::Configure => (600, 1),
+            Self::Start => (30, 2),
+            Self::Stop => (30, 2),
+            Self::Undo => (120, 1),
+            Self::Breakup => (120, 1),
+            Self::Invalid => (5, 0),
+        };
+        OperationPolicy {
+            timeout: Duration::from_secs(secs),
+            retries,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
@@ -1,7 +1,12 @@
 use super::ecc_envoy::startup_ecc_envoys;
+use super::ecc_operation::ECCOperation;
 use super::error::EmbassyError;
-use super::message::{EmbassyMessage, MessageKind};
+use super::message::{EmbassyMessage, OperationId};
+use super::simulation::{startup_simulated_envoys, Simulation};
+use super::supervisor::{EnvoyKind, EnvoyTaskState};
 use super::surveyor_envoy::startup_surveyor_envoys;
+use super::switchboard::{ECCSwitchboard, PendingOperation};
+use super::topology::Topology;
 use std::collections::HashMap;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
@@ -11,9 +16,12 @@ use tokio::sync::mpsc;
 /// the synchronous UI-application. The embassy is essentially a
 /// container of channels used to communicate back-and-forth between these
 /// two runtimes.
+///
+/// ECC operations are submitted through the ECCSwitchboard, and responses are matched to the operation
+/// in flight for their module as they are polled. Stale responses (to an operation which is no longer in flight) are dropped.
 #[derive(Debug)]
 pub struct Embassy {
-    ecc_senders: HashMap<i32, mpsc::Sender<EmbassyMessage>>,
+    switchboard: ECCSwitchboard,
     envoy_reciever: mpsc::Receiver<EmbassyMessage>,
     cancel: broadcast::Sender<EmbassyMessage>,
 }
@@ -25,7 +33,7 @@ impl Embassy {
         cancel: broadcast::Sender<EmbassyMessage>,
     ) -> Self {
         Embassy {
-            ecc_senders,
+            switchboard: ECCSwitchboard::new(ecc_senders),
             envoy_reciever,
             cancel,
         }
@@ -38,20 +46,86 @@ impl Embassy {
             .expect("Some how all the envoys are already dead?");
     }
 
-    pub fn submit_message(&mut self, message: EmbassyMessage) -> Result<(), EmbassyError> {
-        if message.kind == MessageKind::ECCOperation {
-            if let Some(sender) = self.ecc_senders.get_mut(&message.id) {
-                sender.blocking_send(message)?;
+    /// Submit an operation to the transition envoy of a module. Rejected if the module already has an operation in flight.
+    pub fn submit_operation(
+        &mut self,
+        operation: ECCOperation,
+        id: i32,
+    ) -> Result<OperationId, EmbassyError> {
+        self.switchboard.submit_blocking(operation, id)
+    }
+
+    /// A handle to the switchboard, for tasks which drive transitions themselves (i.e. the RunController)
+    pub fn get_switchboard(&self) -> ECCSwitchboard {
+        self.switchboard.clone()
+    }
+
+    pub fn has_pending_operations(&self) -> bool {
+        self.switchboard.has_pending()
+    }
+
+    /// The operation in flight for a module, if any
+    pub fn get_pending_operation(&self, id: i32) -> Option<PendingOperation> {
+        self.switchboard.get_pending(id)
+    }
+
+    /// Match an operation response to the pending table. An operation lost with a transition envoy
+    /// which died is removed from the table. Returns false if the message is a stale response.
+    fn track_operation(&self, message: &EmbassyMessage) -> bool {
+        match message {
+            EmbassyMessage::ECCOperationResponse {
+                id,
+                op_id,
+                operation,
+                ..
+            } => match self.switchboard.complete(*id, *op_id) {
+                Some(pending) => {
+                    tracing::debug!(
+                        "Operation {} {} for module {} answered after {}s",
+                        operation,
+                        op_id,
+                        id,
+                        pending.submitted.elapsed().as_secs_f64()
+                    );
+                    true
+                }
+                None => {
+                    tracing::warn!(
+                        "Dropping stale response to operation {} {} for module {}",
+                        operation,
+                        op_id,
+                        id
+                    );
+                    false
+                }
+            },
+            EmbassyMessage::EnvoyHealth { id, health }
+                if health.kind == EnvoyKind::ECCTransition
+                    && health.state == EnvoyTaskState::Restarting =>
+            {
+                if let Some(pending) = self.switchboard.abandon(*id) {
+                    tracing::warn!(
+                        "Operation {} {} for module {} was lost with its envoy",
+                        pending.operation,
+                        pending.op_id,
+                        id
+                    );
+                }
+                true
             }
+            _ => true,
         }
-        Ok(())
     }
 
     pub fn poll_messages(&mut self) -> Result<Vec<EmbassyMessage>, EmbassyError> {
         let mut messages: Vec<EmbassyMessage> = vec![];
         loop {
             match self.envoy_reciever.try_recv() {
-                Ok(message) => messages.push(message.into()),
+                Ok(message) => {
+                    if self.track_operation(&message) {
+                        messages.push(message)
+                    }
+                }
                 Err(mpsc::error::TryRecvError::Empty) => break,
                 Err(mpsc::error::TryRecvError::Disconnected) => {
                     return Err(EmbassyError::MessageRecieveError)
@@ -63,16 +137,30 @@ impl Embassy {
 }
 
 /// This is the function to create and connect an Embassy as well as all of the envoys.
+/// Modules in the masked list are left out entirely. If a simulation is given, simulated envoys are spawned in place of
+/// the real ones.
 pub fn connect_embassy(
     runtime: &mut tokio::runtime::Runtime,
     experiment: &str,
+    topology: &Topology,
+    masked: &[i32],
+    simulation: Option<&Simulation>,
 ) -> (Embassy, Vec<tokio::task::JoinHandle<()>>) {
-    let (envoy_tx, embassy_rx) = mpsc::channel::<EmbassyMessage>(33);
+    //Room for one message from every envoy (2 ECC per module, 1 Surveyor per data router) and from each of their supervisors
+    let channel_size = 2 * (2 * topology.number_of_modules() + topology.surveyor_ids().len());
+    let (envoy_tx, embassy_rx) = mpsc::channel::<EmbassyMessage>(channel_size);
     let (cancel_tx, _) = broadcast::channel::<EmbassyMessage>(10);
 
+    if let Some(sim) = simulation {
+        let (handles, ecc_switchboard) =
+            startup_simulated_envoys(runtime, topology, masked, &envoy_tx, &cancel_tx, sim);
+        let embassy = Embassy::new(embassy_rx, ecc_switchboard, cancel_tx);
+        return (embassy, handles);
+    }
+
     let (mut handles, ecc_switchboard) =
-        startup_ecc_envoys(runtime, experiment, &envoy_tx, &cancel_tx);
-    let mut sur_handles = startup_surveyor_envoys(runtime, &envoy_tx, &cancel_tx);
+        startup_ecc_envoys(runtime, experiment, topology, masked, &envoy_tx, &cancel_tx);
+    let mut sur_handles = startup_surveyor_envoys(runtime, topology, masked, &envoy_tx, &cancel_tx);
 
     let embassy = Embassy::new(embassy_rx, ecc_switchboard, cancel_tx);
 
@@ -1,4 +1,4 @@
-use super::message::{EmbassyMessage, MessageKind};
+use super::message::{EmbassyMessage, OperationId};
 use tokio::sync::mpsc::error::SendError;
 
 #[derive(Debug)]
@@ -31,18 +31,72 @@ impl std::fmt::Display for ECCStatusError {
 
 impl std::error::Error for ECCStatusError {}
 
+/// What was wrong with a surveyor page
+#[derive(Debug)]
+pub enum SurveyorParseError {
+    Empty,
+    BadState(String),
+    MissingLocation,
+    MissingDiskLine,
+    BadDiskLine(String),
+    BadDiskSize(String),
+    BadPercentUsed(String),
+    BadFileLine(String),
+}
+
+impl std::fmt::Display for SurveyorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Surveyor page is empty"),
+            Self::BadState(s) => write!(f, "Surveyor page has an invalid state: {s}"),
+            Self::MissingLocation => write!(f, "Surveyor page is missing the data location"),
+            Self::MissingDiskLine => write!(f, "Surveyor page is missing the disk usage"),
+            Self::BadDiskLine(s) => write!(f, "Surveyor page has an invalid disk usage line: {s}"),
+            Self::BadDiskSize(s) => write!(f, "Surveyor page has an invalid disk size: {s}"),
+            Self::BadPercentUsed(s) => {
+                write!(f, "Surveyor page has an invalid percent of disk used: {s}")
+            }
+            Self::BadFileLine(s) => write!(f, "Surveyor page has an invalid file line: {s}"),
+        }
+    }
+}
+
+impl std::error::Error for SurveyorParseError {}
+
+/// Why a Transport request got no reply
+#[derive(Debug)]
+pub enum TransportError {
+    Timeout,
+    Connect(String),
+    Request(String),
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "Request timed out"),
+            Self::Connect(e) => write!(f, "Could not connect: {e}"),
+            Self::Request(e) => write!(f, "Request failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
 #[derive(Debug)]
 pub enum EnvoyError {
     RequestError(reqwest::Error),
-    SendError(SendError<EmbassyMessage>),
+    TransportError(TransportError),
+    SurveyorParseError(SurveyorParseError),
+    SendError(Box<SendError<EmbassyMessage>>),
     StatusError(ECCStatusError),
     OperationError(ECCOperationError),
-    MessageParseError(serde_yaml::Error),
     StringToIntError(std::num::ParseIntError),
     StringToFloatError(std::num::ParseFloatError),
-    XMLError(quick_xml::Error),
-    XMLUtf8Error(std::string::FromUtf8Error),
+    XMLDeserializeError(quick_xml::DeError),
     XMLConversionError,
+    SoapFault(String, String),
+    OperationTimeout(String, std::time::Duration),
 }
 
 impl From<reqwest::Error> for EnvoyError {
@@ -51,9 +105,21 @@ impl From<reqwest::Error> for EnvoyError {
     }
 }
 
+impl From<TransportError> for EnvoyError {
+    fn from(value: TransportError) -> Self {
+        Self::TransportError(value)
+    }
+}
+
+impl From<SurveyorParseError> for EnvoyError {
+    fn from(value: SurveyorParseError) -> Self {
+        Self::SurveyorParseError(value)
+    }
+}
+
 impl From<SendError<EmbassyMessage>> for EnvoyError {
     fn from(value: SendError<EmbassyMessage>) -> Self {
-        Self::SendError(value)
+        Self::SendError(Box::new(value))
     }
 }
 
@@ -69,12 +135,6 @@ impl From<ECCOperationError> for EnvoyError {
     }
 }
 
-impl From<serde_yaml::Error> for EnvoyError {
-    fn from(value: serde_yaml::Error) -> Self {
-        Self::MessageParseError(value)
-    }
-}
-
 impl From<std::num::ParseIntError> for EnvoyError {
     fn from(value: std::num::ParseIntError) -> Self {
         Self::StringToIntError(value)
@@ -87,15 +147,9 @@ impl From<std::num::ParseFloatError> for EnvoyError {
     }
 }
 
-impl From<quick_xml::Error> for EnvoyError {
-    fn from(value: quick_xml::Error) -> Self {
-        Self::XMLError(value)
-    }
-}
-
-impl From<std::string::FromUtf8Error> for EnvoyError {
-    fn from(value: std::string::FromUtf8Error) -> Self {
-        Self::XMLUtf8Error(value)
+impl From<quick_xml::DeError> for EnvoyError {
+    fn from(value: quick_xml::DeError) -> Self {
+        Self::XMLDeserializeError(value)
     }
 }
 
@@ -105,15 +159,25 @@ impl std::fmt::Display for EnvoyError {
             Self::RequestError(e) => {
                 write!(f, "Envoy recieved an error while making a request: {e}")
             }
-            Self::MessageParseError(e) => write!(f, "Envoy failed to parse a message to yaml: {e}"),
+            Self::TransportError(e) => {
+                write!(f, "Envoy recieved an error while making a request: {e}")
+            }
+            Self::SurveyorParseError(e) => write!(f, "Envoy failed to parse surveyor page: {e}"),
             Self::OperationError(e) => write!(f, "Envoy recieved operation error: {e}"),
             Self::StatusError(e) => write!(f, "Envoy recieved status error: {e}"),
             Self::SendError(e) => write!(f, "Envoy failed to send a message: {e}"),
             Self::StringToIntError(e) => write!(f, "Envoy failed to parse string to integer: {e}"),
             Self::StringToFloatError(e) => write!(f, "Envoy failed to parse string to float: {e}"),
-            Self::XMLError(e) => write!(f, "Envoy failed to parse XML body: {e}"),
-            Self::XMLUtf8Error(e) => write!(f, "Envoy failed to convert XML to String: {e}"),
+            Self::XMLDeserializeError(e) => write!(f, "Envoy failed to parse XML body: {e}"),
             Self::XMLConversionError => write!(f, "Envoy failed to convert XML data!"),
+            Self::SoapFault(code, message) => {
+                write!(f, "ECC server replied with SOAP fault {code}: {message}")
+            }
+            Self::OperationTimeout(op, timeout) => write!(
+                f,
+                "ECC operation {op} timed out after {}s",
+                timeout.as_secs()
+            ),
         }
     }
 }
@@ -122,40 +186,116 @@ impl std::error::Error for EnvoyError {}
 
 #[derive(Debug)]
 pub enum EmbassyError {
-    MessageSendError(SendError<EmbassyMessage>),
-    MessageKindError(MessageKind, MessageKind),
-    MessageParseError(serde_yaml::Error),
+    MessageSendError(Box<SendError<EmbassyMessage>>),
     MessageRecieveError,
+    NoEnvoy(i32),
+    OperationInFlight(i32, OperationId, String),
 }
 
 impl From<SendError<EmbassyMessage>> for EmbassyError {
     fn from(value: SendError<EmbassyMessage>) -> Self {
-        Self::MessageSendError(value)
-    }
-}
-
-impl From<serde_yaml::Error> for EmbassyError {
-    fn from(value: serde_yaml::Error) -> Self {
-        Self::MessageParseError(value)
+        Self::MessageSendError(Box::new(value))
     }
 }
 
 impl std::fmt::Display for EmbassyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::MessageKindError(expected, recieved) => write!(
-                f,
-                "Embassy expected {expected} message, recieved {recieved} message!"
-            ),
             Self::MessageSendError(e) => {
                 write!(f, "Embassy had an error sending the following message: {e}")
             }
-            Self::MessageParseError(e) => write!(f, "Embassy had an error parsing a message: {e}"),
             Self::MessageRecieveError => {
                 write!(f, "Embassy communication lines were disconnected!")
             }
+            Self::NoEnvoy(id) => write!(f, "Embassy has no transition envoy for module {id}!"),
+            Self::OperationInFlight(id, op_id, operation) => write!(
+                f,
+                "Module {id} already has operation {operation} {op_id} in flight!"
+            ),
         }
     }
 }
 
 impl std::error::Error for EmbassyError {}
+
+#[derive(Debug)]
+pub enum TopologyError {
+    IOError(std::io::Error),
+    ParseError(serde_yaml::Error),
+    NoModules,
+    BadModuleId(i32),
+    MutantCount(usize),
+}
+
+impl From<std::io::Error> for TopologyError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IOError(value)
+    }
+}
+
+impl From<serde_yaml::Error> for TopologyError {
+    fn from(value: serde_yaml::Error) -> Self {
+        Self::ParseError(value)
+    }
+}
+
+impl std::fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IOError(e) => write!(f, "Could not read topology file: {e}"),
+            Self::ParseError(e) => write!(f, "Could not parse topology file: {e}"),
+            Self::NoModules => write!(f, "Topology does not contain any modules!"),
+            Self::BadModuleId(id) => write!(
+                f,
+                "Topology module id {id} is invalid; ids must be unique and run from 0 to N-1!"
+            ),
+            Self::MutantCount(n) => {
+                write!(f, "Topology must contain exactly one MuTaNT, found {n}!")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+#[derive(Debug)]
+pub enum RunControlError {
+    SubmitError(EmbassyError),
+    Timeout(String, std::time::Duration),
+    OperationFailed(i32, String),
+    Rejected(String),
+    CommandFailed(String),
+    Disconnected,
+    Aborted,
+}
+
+impl From<EmbassyError> for RunControlError {
+    fn from(value: EmbassyError) -> Self {
+        Self::SubmitError(value)
+    }
+}
+
+impl std::fmt::Display for RunControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SubmitError(e) => write!(f, "Run controller could not submit an operation: {e}"),
+            Self::Timeout(step, timeout) => write!(
+                f,
+                "Run controller timed out after {}s waiting for: {step}",
+                timeout.as_secs()
+            ),
+            Self::OperationFailed(id, reason) => {
+                write!(
+                    f,
+                    "Run controller operation failed for module {id}: {reason}"
+                )
+            }
+            Self::Rejected(reason) => write!(f, "Run controller rejected the request: {reason}"),
+            Self::CommandFailed(reason) => write!(f, "Run controller command failed: {reason}"),
+            Self::Disconnected => write!(f, "Run controller lost the status of the envoys!"),
+            Self::Aborted => write!(f, "Run controller was aborted by the user"),
+        }
+    }
+}
+
+impl std::error::Error for RunControlError {}
@@ -1,16 +1,16 @@
 use super::ecc_envoy::{ECCOperationResponse, ECCStatusResponse};
-use super::error::EmbassyError;
+use super::ecc_operation::ECCOperation;
+use super::supervisor::EnvoyHealth;
 use super::surveyor_envoy::SurveyorResponse;
-
-const MESSAGE_EMPTY_FIELD: &str = "None";
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Clone, PartialEq)]
-#[allow(dead_code)]
 pub enum MessageKind {
     ECCOperation,
     ECCStatus,
     Surveyor,
-    Other,
+    EnvoyHealth,
     Cancel,
 }
 
@@ -20,164 +20,206 @@ impl std::fmt::Display for MessageKind {
             Self::ECCOperation => write!(f, "ECCOperation"),
             Self::ECCStatus => write!(f, "ECCStatus"),
             Self::Surveyor => write!(f, "Surveyor"),
-            Self::Other => write!(f, "Other"),
+            Self::EnvoyHealth => write!(f, "EnvoyHealth"),
             Self::Cancel => write!(f, "Cancel"),
         }
     }
 }
 
+/// Unique id given to every ECC operation submitted through the ECCSwitchboard, and echoed in its response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OperationId(pub u64);
+
+impl std::fmt::Display for OperationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// # MessageTiming
+/// When the request behind a response was sent, and how long it took to get an answer (or give up).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MessageTiming {
+    pub sent: SystemTime,
+    pub elapsed: Duration,
+}
+
+impl MessageTiming {
+    /// Start timing a request now
+    pub fn start() -> Self {
+        Self {
+            sent: SystemTime::now(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// The timing of a request started by start() which has just finished
+    pub fn finish(self) -> Self {
+        Self {
+            sent: self.sent,
+            elapsed: self.sent.elapsed().unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
 /// # EmbassyMessage
 /// A unified message type to simplify the transfer of data from the various types of envoys to
 /// the embassy and the embassy to the UI.
-/// Typically the data contained is some form of xml, json, or yaml string. Can be cast to specific message
-/// types using the TryFrom trait.
-#[derive(Debug, Clone)]
-pub struct EmbassyMessage {
-    pub kind: MessageKind,
-    pub id: i32,
-    pub operation: String,
-    pub response: String,
+/// Each variant carries the data for one kind of message, so that a response can only be built with the payload
+/// that belongs to it. Responses to requests made by an envoy carry the timing of the request.
+/// Failures are part of the payload: an ECCOperationResponse with an error code, or a ConnectionStatus describing why
+/// a server could not be reached. Messages are serializable so that they can be recorded and replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EmbassyMessage {
+    /// Request for an ECC transition envoy to perform an operation
+    ECCOperation {
+        id: i32,
+        op_id: OperationId,
+        operation: ECCOperation,
+    },
+    /// The outcome of an ECC operation, with the id of the request
+    ECCOperationResponse {
+        id: i32,
+        op_id: OperationId,
+        operation: ECCOperation,
+        response: ECCOperationResponse,
+        timing: MessageTiming,
+    },
+    ECCStatus {
+        id: i32,
+        response: ECCStatusResponse,
+        timing: MessageTiming,
+    },
+    Surveyor {
+        id: i32,
+        response: SurveyorResponse,
+        timing: MessageTiming,
+    },
+    EnvoyHealth {
+        id: i32,
+        health: EnvoyHealth,
+    },
+    Cancel,
 }
 
 impl std::fmt::Display for EmbassyMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "EmbassyMessage from {} of kind {} for operation {} with response: {}",
-            self.id, self.kind, self.operation, self.response
-        )
+        match self {
+            Self::ECCOperation {
+                id,
+                op_id,
+                operation,
+            } => write!(
+                f,
+                "EmbassyMessage to {id} requesting operation {operation} {op_id}"
+            ),
+            Self::ECCOperationResponse {
+                id,
+                op_id,
+                operation,
+                response,
+                ..
+            } => write!(
+                f,
+                "EmbassyMessage from {id} for operation {operation} {op_id} with error code {}",
+                response.error_code
+            ),
+            Self::Cancel => write!(f, "EmbassyMessage of kind Cancel"),
+            _ => write!(
+                f,
+                "EmbassyMessage from {} of kind {}",
+                self.get_id(),
+                self.get_kind()
+            ),
+        }
     }
 }
 
 impl EmbassyMessage {
-    pub fn compose_surveyor_response(response: String, id: i32) -> Self {
-        EmbassyMessage {
-            kind: MessageKind::Surveyor,
-            id,
-            operation: String::from(MESSAGE_EMPTY_FIELD),
-            response,
+    /// The module the message is from (or for). Cancel messages are for everyone and have id 0.
+    pub fn get_id(&self) -> i32 {
+        match self {
+            Self::ECCOperation { id, .. }
+            | Self::ECCOperationResponse { id, .. }
+            | Self::ECCStatus { id, .. }
+            | Self::Surveyor { id, .. }
+            | Self::EnvoyHealth { id, .. } => *id,
+            Self::Cancel => 0,
         }
     }
 
-    pub fn compose_ecc_op(operation: String, id: i32) -> Self {
-        EmbassyMessage {
-            kind: MessageKind::ECCOperation,
-            id,
-            operation,
-            response: String::from(MESSAGE_EMPTY_FIELD),
+    pub fn get_kind(&self) -> MessageKind {
+        match self {
+            Self::ECCOperation { .. } | Self::ECCOperationResponse { .. } => {
+                MessageKind::ECCOperation
+            }
+            Self::ECCStatus { .. } => MessageKind::ECCStatus,
+            Self::Surveyor { .. } => MessageKind::Surveyor,
+            Self::EnvoyHealth { .. } => MessageKind::EnvoyHealth,
+            Self::Cancel => MessageKind::Cancel,
         }
     }
 
-    pub fn compose_ecc_response(response: String, id: i32) -> Self {
-        EmbassyMessage {
-            kind: MessageKind::ECCOperation,
-            id,
-            operation: String::from(MESSAGE_EMPTY_FIELD),
-            response,
+    /// The timing of the request behind a response, if the message is one
+    pub fn get_timing(&self) -> Option<&MessageTiming> {
+        match self {
+            Self::ECCOperationResponse { timing, .. }
+            | Self::ECCStatus { timing, .. }
+            | Self::Surveyor { timing, .. } => Some(timing),
+            _ => None,
         }
     }
 
-    pub fn compose_ecc_status(response: String, id: i32) -> Self {
-        EmbassyMessage {
-            kind: MessageKind::ECCStatus,
+    pub fn compose_surveyor_response(
+        response: SurveyorResponse,
+        id: i32,
+        timing: MessageTiming,
+    ) -> Self {
+        EmbassyMessage::Surveyor {
             id,
-            operation: String::from(MESSAGE_EMPTY_FIELD),
             response,
+            timing,
         }
     }
 
-    pub fn compose_cancel() -> Self {
-        EmbassyMessage {
-            kind: MessageKind::Cancel,
-            id: 0,
-            operation: String::from(MESSAGE_EMPTY_FIELD),
-            response: String::from(MESSAGE_EMPTY_FIELD),
-        }
-    }
-}
-
-impl TryInto<ECCStatusResponse> for EmbassyMessage {
-    type Error = EmbassyError;
-    fn try_into(self) -> Result<ECCStatusResponse, Self::Error> {
-        match self.kind {
-            MessageKind::ECCStatus => {
-                Ok(serde_yaml::from_str::<ECCStatusResponse>(&self.response)?)
-            }
-            _ => Err(Self::Error::MessageKindError(
-                MessageKind::ECCStatus,
-                self.kind,
-            )),
-        }
-    }
-}
-
-impl TryInto<ECCStatusResponse> for &EmbassyMessage {
-    type Error = EmbassyError;
-    fn try_into(self) -> Result<ECCStatusResponse, Self::Error> {
-        match self.kind {
-            MessageKind::ECCStatus => {
-                Ok(serde_yaml::from_str::<ECCStatusResponse>(&self.response)?)
-            }
-            _ => Err(Self::Error::MessageKindError(
-                MessageKind::ECCStatus,
-                self.kind.clone(),
-            )),
+    /// Only the ECCSwitchboard should compose operations, as it hands out the OperationIds
+    pub fn compose_ecc_op(operation: ECCOperation, id: i32, op_id: OperationId) -> Self {
+        EmbassyMessage::ECCOperation {
+            id,
+            op_id,
+            operation,
         }
     }
-}
 
-impl TryInto<ECCOperationResponse> for EmbassyMessage {
-    type Error = EmbassyError;
-    fn try_into(self) -> Result<ECCOperationResponse, Self::Error> {
-        match self.kind {
-            MessageKind::ECCOperation => Ok(serde_yaml::from_str::<ECCOperationResponse>(
-                &self.response,
-            )?),
-            _ => Err(Self::Error::MessageKindError(
-                MessageKind::ECCOperation,
-                self.kind,
-            )),
+    pub fn compose_ecc_response(
+        operation: ECCOperation,
+        op_id: OperationId,
+        response: ECCOperationResponse,
+        id: i32,
+        timing: MessageTiming,
+    ) -> Self {
+        EmbassyMessage::ECCOperationResponse {
+            id,
+            op_id,
+            operation,
+            response,
+            timing,
         }
     }
-}
 
-impl TryInto<ECCOperationResponse> for &EmbassyMessage {
-    type Error = EmbassyError;
-    fn try_into(self) -> Result<ECCOperationResponse, Self::Error> {
-        match self.kind {
-            MessageKind::ECCOperation => Ok(serde_yaml::from_str::<ECCOperationResponse>(
-                &self.response,
-            )?),
-            _ => Err(Self::Error::MessageKindError(
-                MessageKind::ECCOperation,
-                self.kind.clone(),
-            )),
+    pub fn compose_ecc_status(response: ECCStatusResponse, id: i32, timing: MessageTiming) -> Self {
+        EmbassyMessage::ECCStatus {
+            id,
+            response,
+            timing,
         }
     }
-}
 
-impl TryInto<SurveyorResponse> for EmbassyMessage {
-    type Error = EmbassyError;
-    fn try_into(self) -> Result<SurveyorResponse, Self::Error> {
-        match self.kind {
-            MessageKind::Surveyor => Ok(serde_yaml::from_str::<SurveyorResponse>(&self.response)?),
-            _ => Err(Self::Error::MessageKindError(
-                MessageKind::Surveyor,
-                self.kind,
-            )),
-        }
+    pub fn compose_envoy_health(health: EnvoyHealth, id: i32) -> Self {
+        EmbassyMessage::EnvoyHealth { id, health }
     }
-}
 
-impl TryInto<SurveyorResponse> for &EmbassyMessage {
-    type Error = EmbassyError;
-    fn try_into(self) -> Result<SurveyorResponse, Self::Error> {
-        match self.kind {
-            MessageKind::Surveyor => Ok(serde_yaml::from_str::<SurveyorResponse>(&self.response)?),
-            _ => Err(Self::Error::MessageKindError(
-                MessageKind::Surveyor,
-                self.kind.clone(),
-            )),
-        }
+    pub fn compose_cancel() -> Self {
+        EmbassyMessage::Cancel
     }
 }
@@ -1,8 +1,17 @@
+pub mod connection_status;
 pub mod constants;
 pub mod ecc_envoy;
 pub mod ecc_operation;
 pub mod embassy;
 pub mod error;
 pub mod message;
+pub mod run_controller;
+pub mod simulation;
+pub mod soap;
+pub mod supervisor;
 pub mod surveyor_envoy;
+pub mod surveyor_page;
 pub mod surveyor_state;
+pub mod switchboard;
+pub mod topology;
+pub mod transport;
@@ -0,0 +1,406 @@
+use super::ecc_operation::{ECCOperation, ECCStatus};
+use super::error::RunControlError;
+use super::simulation::Simulation;
+use super::surveyor_envoy::SurveyorResponse;
+use super::switchboard::ECCSwitchboard;
+use crate::command::command::{execute, CommandName, CommandStatus};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Time allowed on top of the operation timeout for the status envoys to see the result of a transition
+const STATUS_MARGIN: Duration = Duration::from_secs(15);
+/// Time allowed for a shell command (run number check, moving files, config backup)
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// # RunSequence
+/// The multi-step sequences driven by the RunController. Progress moves the whole system forward by one
+/// operation, which needs ordering between the MuTaNT and the CoBos for Prepare and Configure.
+#[derive(Debug, Clone)]
+pub enum RunSequence {
+    Progress(ECCOperation),
+    Start,
+    Stop,
+}
+
+impl std::fmt::Display for RunSequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Progress(op) => write!(f, "Progress ({op})"),
+            Self::Start => write!(f, "Start Run"),
+            Self::Stop => write!(f, "Stop Run"),
+        }
+    }
+}
+
+/// # RunProgress
+/// Reports sent from the RunController back to the UI as the sequence advances.
+/// Busy lists the modules which were just sent an operation, so that the UI can hold their status.
+#[derive(Debug)]
+pub enum RunProgress {
+    Step(String),
+    Busy(Vec<i32>),
+    Done(RunSequence, Result<(), RunControlError>),
+}
+
+/// # StatusSnapshot
+/// A copy of the state of the system as seen by the UI's StatusManager. The UI publishes a new snapshot
+/// through a watch channel every time it handles messages from the embassy, and the RunController waits on it.
+/// ECC status and the count of failed operations are indexed by module id; surveyors are only those of active
+/// (unmasked) modules.
+#[derive(Debug, Clone, Default)]
+pub struct StatusSnapshot {
+    pub ecc: Vec<ECCStatus>,
+    pub failures: Vec<u32>,
+    pub surveyors: Vec<SurveyorResponse>,
+}
+
+impl StatusSnapshot {
+    fn is_at(&self, ids: &[i32], expected: &ECCStatus) -> bool {
+        ids.iter().all(|id| match self.ecc.get(*id as usize) {
+            Some(status) => status == expected,
+            None => false,
+        })
+    }
+
+    fn get_failures(&self, id: i32) -> u32 {
+        match self.failures.get(id as usize) {
+            Some(count) => *count,
+            None => 0,
+        }
+    }
+}
+
+/// # RunHandle
+/// The UI side of a running sequence. Progress is polled every frame, and the sequence can be aborted at any time.
+/// Aborting stops the sequence where it is; operations which were already sent are not undone.
+#[derive(Debug)]
+pub struct RunHandle {
+    sequence: RunSequence,
+    progress: mpsc::UnboundedReceiver<RunProgress>,
+    abort: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl RunHandle {
+    pub fn get_sequence(&self) -> &RunSequence {
+        &self.sequence
+    }
+
+    pub fn abort(&self) {
+        self.abort.send_replace(true);
+    }
+
+    pub fn poll_progress(&mut self) -> Vec<RunProgress> {
+        let mut reports = vec![];
+        while let Ok(report) = self.progress.try_recv() {
+            reports.push(report);
+        }
+        reports
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+/// # RunController
+/// A state machine which drives a RunSequence step by step on the tokio runtime, so that the UI never
+/// blocks. Operations are submitted to the ECC transition envoys through the ECCSwitchboard, and the result of each step is
+/// observed through the StatusSnapshot published by the UI. Every step has a timeout, derived from the
+/// OperationPolicy of the operation, and a step fails early if any module reports that its operation failed.
+#[derive(Debug)]
+pub struct RunController {
+    sequence: RunSequence,
+    experiment: String,
+    run_number: i32,
+    mutant_id: i32,
+    cobo_ids: Vec<i32>,
+    switchboard: ECCSwitchboard,
+    status: watch::Receiver<StatusSnapshot>,
+    progress: mpsc::UnboundedSender<RunProgress>,
+    abort: watch::Receiver<bool>,
+    simulation: Option<Simulation>,
+}
+
+impl RunController {
+    /// Spawn a controller for the sequence on the runtime. cobo_ids should only contain active (unmasked) CoBos.
+    /// If a simulation is given, the shell commands are run against it instead of the data routers.
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn(
+        runtime: &tokio::runtime::Runtime,
+        sequence: RunSequence,
+        experiment: &str,
+        run_number: i32,
+        mutant_id: i32,
+        cobo_ids: Vec<i32>,
+        switchboard: ECCSwitchboard,
+        status: watch::Receiver<StatusSnapshot>,
+        simulation: Option<Simulation>,
+    ) -> RunHandle {
+        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+        let (abort_tx, abort_rx) = watch::channel(false);
+        let mut controller = RunController {
+            sequence: sequence.clone(),
+            experiment: String::from(experiment),
+            run_number,
+            mutant_id,
+            cobo_ids,
+            switchboard,
+            status,
+            progress: progress_tx,
+            abort: abort_rx,
+            simulation,
+        };
+        let handle = runtime.spawn(async move {
+            let result = controller.run().await;
+            match &result {
+                Ok(()) => tracing::info!("{} completed", controller.sequence),
+                Err(e) => tracing::error!("{} did not complete: {}", controller.sequence, e),
+            }
+            let _ = controller
+                .progress
+                .send(RunProgress::Done(controller.sequence.clone(), result));
+        });
+        return RunHandle {
+            sequence,
+            progress: progress_rx,
+            abort: abort_tx,
+            handle,
+        };
+    }
+
+    async fn run(&mut self) -> Result<(), RunControlError> {
+        match self.sequence.clone() {
+            RunSequence::Progress(op) => self.progress_system(op).await,
+            RunSequence::Start => self.start_run().await,
+            RunSequence::Stop => self.stop_run().await,
+        }
+    }
+
+    /// Transition the system forward. Order matters for two of the phases (Prepare and Configure)
+    async fn progress_system(&mut self, operation: ECCOperation) -> Result<(), RunControlError> {
+        let mutant = [self.mutant_id];
+        let cobos = self.cobo_ids.clone();
+        let mut all = cobos.clone();
+        all.push(self.mutant_id);
+        match operation {
+            //Describe operation: order doesn't matter
+            ECCOperation::Describe => {
+                self.transition(
+                    &operation,
+                    &all,
+                    ECCStatus::Described,
+                    "all modules described",
+                )
+                .await
+            }
+            //Prepare operation: mutant first, then cobos
+            ECCOperation::Prepare => {
+                self.transition(&operation, &mutant, ECCStatus::Prepared, "MuTaNT prepared")
+                    .await?;
+                self.transition(&operation, &cobos, ECCStatus::Prepared, "CoBos prepared")
+                    .await
+            }
+            //Configure operation: cobos first, then mutant
+            ECCOperation::Configure => {
+                self.transition(&operation, &cobos, ECCStatus::Ready, "CoBos configured")
+                    .await?;
+                self.transition(&operation, &mutant, ECCStatus::Ready, "MuTaNT configured")
+                    .await
+            }
+            _ => Err(RunControlError::Rejected(format!(
+                "{operation} is not a system progress operation"
+            ))),
+        }
+    }
+
+    /// Start a run. First make sure that the run number was not already used. Then the MuTaNT is re-configured
+    /// to reset the timestamps, the CoBos are started, and only once all CoBos are running does the MuTaNT start.
+    async fn start_run(&mut self) -> Result<(), RunControlError> {
+        let mutant = [self.mutant_id];
+        let cobos = self.cobo_ids.clone();
+
+        self.report_step(format!(
+            "Checking that run {} is unused...",
+            self.run_number
+        ));
+        match self.command(CommandName::CheckRunExists).await? {
+            CommandStatus::Success => {
+                return Err(RunControlError::Rejected(format!(
+                    "run number {} was already used! Either delete the extant data or change the run number",
+                    self.run_number
+                )))
+            }
+            CommandStatus::Failure => (),
+            CommandStatus::CouldNotExecute => {
+                return Err(RunControlError::CommandFailed(String::from(
+                    "could not check the run number",
+                )))
+            }
+        }
+
+        self.report_step(String::from("Re-configuring MuTaNT to reset timestamps..."));
+        self.transition(
+            &ECCOperation::Breakup,
+            &mutant,
+            ECCStatus::Prepared,
+            "MuTaNT broken up",
+        )
+        .await?;
+        self.transition(
+            &ECCOperation::Configure,
+            &mutant,
+            ECCStatus::Ready,
+            "MuTaNT re-configured",
+        )
+        .await?;
+
+        self.report_step(String::from("Starting CoBos..."));
+        self.transition(
+            &ECCOperation::Start,
+            &cobos,
+            ECCStatus::Running,
+            "CoBos running",
+        )
+        .await?;
+
+        self.report_step(String::from("Starting MuTaNT..."));
+        self.transition(
+            &ECCOperation::Start,
+            &mutant,
+            ECCStatus::Running,
+            "MuTaNT running",
+        )
+        .await
+    }
+
+    /// Stop a run. First the MuTaNT is stopped, then the CoBos. Once everything has stopped the data is moved to a run
+    /// specific location and the ECC configuration files are backed up. Failures of either command are reported
+    /// but do not fail the sequence, as the run has already stopped.
+    async fn stop_run(&mut self) -> Result<(), RunControlError> {
+        let mutant = [self.mutant_id];
+        let cobos = self.cobo_ids.clone();
+
+        self.report_step(String::from("Stopping MuTaNT..."));
+        self.transition(
+            &ECCOperation::Stop,
+            &mutant,
+            ECCStatus::Ready,
+            "MuTaNT stopped",
+        )
+        .await?;
+
+        self.report_step(String::from("Stopping CoBos..."));
+        self.transition(
+            &ECCOperation::Stop,
+            &cobos,
+            ECCStatus::Ready,
+            "CoBos stopped",
+        )
+        .await?;
+
+        self.report_step(String::from("Moving .graw files..."));
+        match self.command(CommandName::MoveGrawFiles).await {
+            Ok(CommandStatus::Success) => (),
+            Ok(_) => tracing::error!("Unable to move the graw files after the stop run signal!"),
+            Err(RunControlError::Aborted) => return Err(RunControlError::Aborted),
+            Err(e) => tracing::error!("{}", e),
+        }
+
+        self.report_step(String::from("Backing up GET configuration..."));
+        match self.command(CommandName::BackupConfig).await {
+            Ok(CommandStatus::Success) => (),
+            Ok(_) => tracing::error!("Could not backup config files after the stop run signal"),
+            Err(RunControlError::Aborted) => return Err(RunControlError::Aborted),
+            Err(e) => tracing::error!("{}", e),
+        }
+        Ok(())
+    }
+
+    fn report_step(&self, step: String) {
+        tracing::info!("{}", step);
+        let _ = self.progress.send(RunProgress::Step(step));
+    }
+
+    /// Send an operation to the transition envoys of the given modules and wait until every one of them is at the expected status
+    async fn transition(
+        &mut self,
+        operation: &ECCOperation,
+        ids: &[i32],
+        expected: ECCStatus,
+        step: &str,
+    ) -> Result<(), RunControlError> {
+        if *self.abort.borrow() {
+            return Err(RunControlError::Aborted);
+        }
+
+        //Failure counts only ever go up, so anything above what we see now is a failure of this operation
+        let baseline: Vec<u32> = {
+            let snapshot = self.status.borrow();
+            ids.iter().map(|id| snapshot.get_failures(*id)).collect()
+        };
+        //Modules which took the operation are reported busy even if a later one refused it
+        let mut submitted: Vec<i32> = vec![];
+        for id in ids {
+            match self.switchboard.submit(operation.clone(), *id).await {
+                Ok(_) => submitted.push(*id),
+                Err(e) => {
+                    let _ = self.progress.send(RunProgress::Busy(submitted));
+                    return Err(e.into());
+                }
+            }
+        }
+        let _ = self.progress.send(RunProgress::Busy(submitted));
+
+        let timeout = operation.get_policy().timeout + STATUS_MARGIN;
+        let deadline = Instant::now() + timeout;
+        loop {
+            {
+                let snapshot = self.status.borrow_and_update();
+                for (id, count) in ids.iter().zip(baseline.iter()) {
+                    if snapshot.get_failures(*id) > *count {
+                        return Err(RunControlError::OperationFailed(
+                            *id,
+                            format!("{operation} failed while waiting for {step}"),
+                        ));
+                    }
+                }
+                if snapshot.is_at(ids, &expected) {
+                    return Ok(());
+                }
+            }
+            tokio::select! {
+                changed = self.status.changed() => {
+                    if changed.is_err() {
+                        return Err(RunControlError::Disconnected);
+                    }
+                }
+                _ = self.abort.changed() => return Err(RunControlError::Aborted),
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(RunControlError::Timeout(String::from(step), timeout));
+                }
+            }
+        }
+    }
+
+    /// Run one of the shell commands off of the async threads, as they block
+    async fn command(&mut self, command: CommandName) -> Result<CommandStatus, RunControlError> {
+        if let Some(sim) = &self.simulation {
+            return Ok(sim.execute(&command, &self.experiment, self.run_number));
+        }
+        let surveyors = self.status.borrow().surveyors.clone();
+        let experiment = self.experiment.clone();
+        let run_number = self.run_number;
+        let name = command.to_string();
+        let task = tokio::task::spawn_blocking(move || {
+            execute(command, &surveyors, &experiment, &run_number)
+        });
+        tokio::select! {
+            result = task => result.map_err(|e| RunControlError::CommandFailed(format!("{name}: {e}"))),
+            _ = self.abort.changed() => Err(RunControlError::Aborted),
+            _ = tokio::time::sleep(COMMAND_TIMEOUT) => Err(RunControlError::Timeout(name, COMMAND_TIMEOUT)),
+        }
+    }
+}
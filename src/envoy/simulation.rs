@@ -0,0 +1,528 @@
+use super::connection_status::ConnectionStatus;
+use super::constants::STATUS_POLL_SECS;
+use super::ecc_envoy::{ECCOperationResponse, ECCStatusResponse};
+use super::ecc_operation::{ECCOperation, ECCStatus};
+use super::error::EnvoyError;
+use super::message::{EmbassyMessage, MessageTiming};
+use super::supervisor::{EnvoyKind, Supervisor};
+use super::surveyor_envoy::{RateMeter, SurveyorResponse};
+use super::topology::Topology;
+use crate::command::command::{CommandName, CommandStatus};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Error code of a simulated operation failure
+const SIM_ERROR_CODE: i32 = 3;
+/// Data rate of a CoBo for a 16C beam at 10 MeV/U, in MB/s
+const SIM_BASE_RATE_MBPS: f64 = 5.0;
+const SIM_DISK_SIZE: u64 = 1_000_000_000_000;
+/// Space on each data router disk taken by other data
+const SIM_DISK_USED: u64 = 150_000_000_000;
+/// Graw files are rolled over at this size
+const SIM_FILE_SIZE: u64 = 1_073_741_824;
+
+/// # SimulationOptions
+/// Behavior of the simulated DAQ. Operation latencies are multiplied by latency_scale (use less than 1 to speed things up),
+/// and each operation fails with probability error_rate. Status queries time out with a tenth of that probability.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationOptions {
+    pub latency_scale: f64,
+    pub error_rate: f64,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        Self {
+            latency_scale: 1.0,
+            error_rate: 0.02,
+        }
+    }
+}
+
+/// Range of time (in ms) an operation takes on a real ECC server
+fn operation_latency(operation: &ECCOperation) -> (u64, u64) {
+    match operation {
+        ECCOperation::Describe => (3000, 8000),
+        ECCOperation::Prepare => (5000, 15000),
+        ECCOperation::Configure => (5000, 20000),
+        ECCOperation::Start | ECCOperation::Stop => (500, 2000),
+        ECCOperation::Breakup | ECCOperation::Undo => (1000, 3000),
+        ECCOperation::Invalid => (0, 0),
+    }
+}
+
+/// Range of time (in ms) a status query takes
+const STATUS_LATENCY: (u64, u64) = (20, 200);
+/// No operation completes faster than this, however the latencies are scaled. The real servers never answer sooner, and
+/// the session expects to see an operation submitted before it sees the response.
+const MIN_OPERATION_LATENCY: Duration = Duration::from_millis(200);
+
+#[derive(Debug)]
+struct SimulatedECC {
+    status: ECCStatus,
+    in_flight: Option<ECCOperation>,
+}
+
+#[derive(Debug)]
+struct SimulatedRouter {
+    address: String,
+    bytes: u64,
+    /// How this router's rate differs from the beam rate, as some CoBos see more of the beam than others
+    rate_factor: f64,
+    last_update: Instant,
+}
+
+#[derive(Debug)]
+struct SimulationState {
+    eccs: HashMap<i32, SimulatedECC>,
+    routers: HashMap<i32, SimulatedRouter>,
+    mutant_id: i32,
+    used_runs: HashSet<(String, i32)>,
+}
+
+/// # Simulation
+/// An in-process stand-in for the whole DAQ: the GET state machine of every ECC server, and the disk of every data router.
+/// ECC operations take a realistic (random) time and occasionally fail. Once a CoBo and the MuTaNT are both Running,
+/// graw files grow on the CoBo's data router at a rate set by the beam. The RunController's shell commands are simulated
+/// too, so that the run sequences can complete: moving the graw files empties the data routers and marks the run as used.
+///
+/// The simulation is shared by the simulated envoys and the RunController, and lasts until the session disconnects.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    options: SimulationOptions,
+    rate_mbps: f64,
+    state: Arc<Mutex<SimulationState>>,
+}
+
+impl Simulation {
+    pub fn new(topology: &Topology, options: &SimulationOptions, beam: &str, energy: f32) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut eccs = HashMap::new();
+        let mut routers = HashMap::new();
+        for module in topology.modules.iter() {
+            eccs.insert(
+                module.id,
+                SimulatedECC {
+                    status: ECCStatus::Idle,
+                    in_flight: None,
+                },
+            );
+            if let Some(surveyor) = &module.surveyor {
+                routers.insert(
+                    module.id,
+                    SimulatedRouter {
+                        address: surveyor.host.clone(),
+                        bytes: 0,
+                        rate_factor: rng.gen_range(0.8..1.2),
+                        last_update: Instant::now(),
+                    },
+                );
+            }
+        }
+        let rate_mbps = Self::beam_rate(beam, energy);
+        tracing::info!(
+            "Simulating {} modules with a {} beam at {} MeV/U ({:.2} MB/s per CoBo)",
+            topology.number_of_modules(),
+            beam,
+            energy,
+            rate_mbps
+        );
+        return Self {
+            options: options.clone(),
+            rate_mbps,
+            state: Arc::new(Mutex::new(SimulationState {
+                eccs,
+                routers,
+                mutant_id: topology.mutant_id(),
+                used_runs: HashSet::new(),
+            })),
+        };
+    }
+
+    /// Data rate of a CoBo in MB/s for a beam. Heavier and faster beams leave more charge in the detector, so the rate scales
+    /// with the square root of the mass number (read from the front of the beam name, i.e. 16 for 16C) and with the energy.
+    pub fn beam_rate(beam: &str, energy: f32) -> f64 {
+        let digits: String = beam.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let mass = match digits.parse::<f64>() {
+            Ok(a) if a > 0.0 => a,
+            _ => 16.0,
+        };
+        let energy_factor = ((energy as f64) / 10.0).clamp(0.2, 3.0);
+        return SIM_BASE_RATE_MBPS * (mass / 16.0).sqrt() * energy_factor;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimulationState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn random_latency(&self, range: (u64, u64)) -> Duration {
+        let ms = match range.0 < range.1 {
+            true => rand::thread_rng().gen_range(range.0..range.1),
+            false => range.0,
+        };
+        Duration::from_millis(ms).mul_f64(self.options.latency_scale.max(0.0))
+    }
+
+    /// Run an operation on a module: go Busy, wait out the latency, then land in the next state (or fail)
+    pub async fn transition(&self, id: i32, operation: &ECCOperation) -> ECCOperationResponse {
+        let previous = {
+            let mut state = self.lock();
+            self.advance(&mut state);
+            let ecc = match state.eccs.get_mut(&id) {
+                Some(ecc) => ecc,
+                None => return Self::operation_failure(format!("There is no module {id}")),
+            };
+            if let Some(in_flight) = &ecc.in_flight {
+                return Self::operation_failure(format!(
+                    "Cannot {operation}, {in_flight} is already in progress"
+                ));
+            }
+            if operation.get_next_status(&ecc.status).is_none() {
+                return Self::operation_failure(format!(
+                    "Cannot {operation} from state {}",
+                    ecc.status
+                ));
+            }
+            let previous = ecc.status.clone();
+            ecc.status = ECCStatus::Busy;
+            ecc.in_flight = Some(operation.clone());
+            previous
+        };
+
+        let latency = self
+            .random_latency(operation_latency(operation))
+            .max(MIN_OPERATION_LATENCY);
+        tokio::time::sleep(latency).await;
+
+        let is_failure = rand::thread_rng().gen_bool(self.options.error_rate.clamp(0.0, 1.0));
+        let mut state = self.lock();
+        self.advance(&mut state);
+        let ecc = state.eccs.get_mut(&id).expect("Checked above");
+        ecc.in_flight = None;
+        if is_failure {
+            ecc.status = previous;
+            return Self::operation_failure(format!("Simulated failure of {operation}"));
+        }
+        ecc.status = operation.get_next_status(&previous).expect("Checked above");
+        return ECCOperationResponse {
+            error_code: 0,
+            error_message: String::new(),
+            text: format!("{operation} done"),
+        };
+    }
+
+    fn operation_failure(message: String) -> ECCOperationResponse {
+        ECCOperationResponse {
+            error_code: SIM_ERROR_CODE,
+            error_message: message,
+            text: String::new(),
+        }
+    }
+
+    /// Answer a status query for a module's ECC server
+    pub async fn get_ecc_status(&self, id: i32) -> ECCStatusResponse {
+        let latency = self.random_latency(STATUS_LATENCY);
+        tokio::time::sleep(latency).await;
+        if rand::thread_rng().gen_bool((self.options.error_rate / 10.0).clamp(0.0, 1.0)) {
+            return ECCStatusResponse {
+                connection: ConnectionStatus::Timeout(String::from(
+                    "Simulated timeout of GetState",
+                )),
+                ..Default::default()
+            };
+        }
+        let state = self.lock();
+        let status = match state.eccs.get(&id) {
+            Some(ecc) => ecc.status.clone(),
+            None => ECCStatus::Offline,
+        };
+        return ECCStatusResponse {
+            error_code: 0,
+            error_message: String::new(),
+            state: status.into(),
+            transition: 0,
+            connection: ConnectionStatus::Connected,
+        };
+    }
+
+    /// Answer a status query for a module's Surveyor. The data rate is left to the envoy.
+    pub async fn get_surveyor_status(&self, id: i32) -> SurveyorResponse {
+        let latency = self.random_latency(STATUS_LATENCY);
+        tokio::time::sleep(latency).await;
+        let mut state = self.lock();
+        self.advance(&mut state);
+        let router = match state.routers.get(&id) {
+            Some(router) => router,
+            None => return SurveyorResponse::default(),
+        };
+        let used = SIM_DISK_USED + router.bytes;
+        return SurveyorResponse {
+            state: 1,
+            address: router.address.clone(),
+            location: format!("/Volumes/simulation/cobo{id}"),
+            disk_status: match router.bytes {
+                0 => String::from("Empty"),
+                _ => String::from("Filled"),
+            },
+            percent_used: format!("{:.0}%", (used as f64) / (SIM_DISK_SIZE as f64) * 100.0),
+            disk_space: SIM_DISK_SIZE,
+            files: router.bytes.div_ceil(SIM_FILE_SIZE) as i32,
+            bytes_used: router.bytes,
+            data_rate: 0.0,
+            connection: ConnectionStatus::Connected,
+            measured_at: Some(SystemTime::now()),
+        };
+    }
+
+    /// Write the data taken since the last update. A CoBo only takes data while it and the MuTaNT (which provides the
+    /// trigger) are Running.
+    fn advance(&self, state: &mut SimulationState) {
+        let is_triggering = match state.eccs.get(&state.mutant_id) {
+            Some(mutant) => mutant.status == ECCStatus::Running,
+            None => false,
+        };
+        let mut rng = rand::thread_rng();
+        let now = Instant::now();
+        for (id, router) in state.routers.iter_mut() {
+            let elapsed = now.duration_since(router.last_update);
+            router.last_update = now;
+            let is_running = match state.eccs.get(id) {
+                Some(ecc) => ecc.status == ECCStatus::Running,
+                None => false,
+            };
+            if !is_triggering || !is_running {
+                continue;
+            }
+            let rate = self.rate_mbps * 1.0e6 * router.rate_factor * rng.gen_range(0.9..1.1);
+            let written = (rate * elapsed.as_secs_f64()) as u64;
+            let free = SIM_DISK_SIZE.saturating_sub(SIM_DISK_USED + router.bytes);
+            router.bytes += written.min(free);
+        }
+    }
+
+    /// Stand in for one of the RunController's shell commands
+    pub fn execute(
+        &self,
+        command: &CommandName,
+        experiment: &str,
+        run_number: i32,
+    ) -> CommandStatus {
+        let mut state = self.lock();
+        self.advance(&mut state);
+        let run = (experiment.to_string(), run_number);
+        match command {
+            CommandName::CheckRunExists => match state.used_runs.contains(&run) {
+                true => CommandStatus::Success,
+                false => CommandStatus::Failure,
+            },
+            CommandName::MoveGrawFiles => {
+                for router in state.routers.values_mut() {
+                    router.bytes = 0;
+                }
+                state.used_runs.insert(run);
+                CommandStatus::Success
+            }
+            CommandName::BackupConfig => CommandStatus::Success,
+        }
+    }
+}
+
+/// # SimulatedECCEnvoy
+/// Plays the part of an ECCEnvoy against the Simulation, in either the transition or the status mode
+#[derive(Debug)]
+pub struct SimulatedECCEnvoy {
+    id: i32,
+    simulation: Simulation,
+    incoming: mpsc::Receiver<EmbassyMessage>,
+    outgoing: mpsc::Sender<EmbassyMessage>,
+    cancel: broadcast::Receiver<EmbassyMessage>,
+}
+
+impl SimulatedECCEnvoy {
+    pub fn new(
+        id: i32,
+        simulation: Simulation,
+        rx: mpsc::Receiver<EmbassyMessage>,
+        tx: mpsc::Sender<EmbassyMessage>,
+        cancel: broadcast::Receiver<EmbassyMessage>,
+    ) -> Self {
+        return Self {
+            id,
+            simulation,
+            incoming: rx,
+            outgoing: tx,
+            cancel,
+        };
+    }
+
+    pub async fn wait_for_transition(&mut self) -> Result<(), EnvoyError> {
+        loop {
+            tokio::select! {
+                _ = self.cancel.recv() => {
+                    return Ok(())
+                }
+
+                data = self.incoming.recv() => {
+                    match data {
+                        Some(EmbassyMessage::ECCOperation { operation, op_id, .. }) => {
+                            let timing = MessageTiming::start();
+                            let response = self.simulation.transition(self.id, &operation).await;
+                            let message = EmbassyMessage::compose_ecc_response(operation, op_id, response, self.id, timing.finish());
+                            self.outgoing.send(message).await?;
+                        }
+                        Some(message) => tracing::warn!("ECC envoy {} ignored unexpected message: {}", self.id, message),
+                        None => return Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn wait_check_status(&mut self) -> Result<(), EnvoyError> {
+        loop {
+            tokio::select! {
+                _ = self.cancel.recv() => {
+                    return Ok(());
+                }
+
+                _ = tokio::time::sleep(Duration::from_secs(STATUS_POLL_SECS)) => {
+                    let timing = MessageTiming::start();
+                    let response = self.simulation.get_ecc_status(self.id).await;
+                    let message = EmbassyMessage::compose_ecc_status(response, self.id, timing.finish());
+                    self.outgoing.send(message).await?
+                }
+            }
+        }
+    }
+}
+
+/// # SimulatedSurveyorEnvoy
+/// Plays the part of a SurveyorEnvoy against the Simulation
+#[derive(Debug)]
+pub struct SimulatedSurveyorEnvoy {
+    id: i32,
+    simulation: Simulation,
+    outgoing: mpsc::Sender<EmbassyMessage>,
+    cancel: broadcast::Receiver<EmbassyMessage>,
+    meter: RateMeter,
+}
+
+impl SimulatedSurveyorEnvoy {
+    pub fn new(
+        id: i32,
+        simulation: Simulation,
+        tx: mpsc::Sender<EmbassyMessage>,
+        cancel: broadcast::Receiver<EmbassyMessage>,
+    ) -> Self {
+        return Self {
+            id,
+            simulation,
+            outgoing: tx,
+            cancel,
+            meter: RateMeter::new(),
+        };
+    }
+
+    pub async fn wait_check_status(&mut self) -> Result<(), EnvoyError> {
+        loop {
+            tokio::select! {
+                _ = self.cancel.recv() => {
+                    return Ok(());
+                }
+
+                _ = tokio::time::sleep(Duration::from_secs(STATUS_POLL_SECS)) => {
+                    let timing = MessageTiming::start();
+                    let mut response = self.simulation.get_surveyor_status(self.id).await;
+                    response.data_rate = self.meter.measure(response.bytes_used);
+                    let message = EmbassyMessage::compose_surveyor_response(response, self.id, timing.finish());
+                    self.outgoing.send(message).await?
+                }
+            }
+        }
+    }
+}
+
+/// Create the simulated envoys for the active modules, in place of startup_ecc_envoys and startup_surveyor_envoys.
+/// Like the real envoys, every simulated envoy runs under a Supervisor. Returns the handles to the tasks and the
+/// transition switchboard.
+pub fn startup_simulated_envoys(
+    runtime: &mut tokio::runtime::Runtime,
+    topology: &Topology,
+    masked: &[i32],
+    envoy_tx: &mpsc::Sender<EmbassyMessage>,
+    cancel: &broadcast::Sender<EmbassyMessage>,
+    simulation: &Simulation,
+) -> (
+    Vec<JoinHandle<()>>,
+    HashMap<i32, mpsc::Sender<EmbassyMessage>>,
+) {
+    let mut transition_switchboard = HashMap::new();
+    let mut handles: Vec<JoinHandle<()>> = vec![];
+
+    for module in topology.active_modules(masked) {
+        let id = module.id;
+
+        let (embassy_tx, ecc_rx) = mpsc::channel::<EmbassyMessage>(10);
+        let tx = envoy_tx.clone();
+        let sim = simulation.clone();
+        let supervisor = Supervisor::new(
+            id,
+            EnvoyKind::ECCTransition,
+            Some(ecc_rx),
+            envoy_tx,
+            cancel,
+            move |rx, this_cancel| {
+                let mut ev = SimulatedECCEnvoy::new(id, sim.clone(), rx, tx.clone(), this_cancel);
+                async move { ev.wait_for_transition().await }
+            },
+        );
+        transition_switchboard.insert(id, embassy_tx);
+        handles.push(supervisor.spawn(runtime));
+
+        let tx = envoy_tx.clone();
+        let sim = simulation.clone();
+        let supervisor = Supervisor::new(
+            id,
+            EnvoyKind::ECCStatus,
+            None,
+            envoy_tx,
+            cancel,
+            move |rx, this_cancel| {
+                let mut ev = SimulatedECCEnvoy::new(id, sim.clone(), rx, tx.clone(), this_cancel);
+                async move { ev.wait_check_status().await }
+            },
+        );
+        handles.push(supervisor.spawn(runtime));
+
+        //Only modules with a data router get a Surveyor envoy (not the MuTaNT)
+        if module.surveyor.is_none() {
+            continue;
+        }
+        let tx = envoy_tx.clone();
+        let sim = simulation.clone();
+        let supervisor = Supervisor::new(
+            id,
+            EnvoyKind::Surveyor,
+            None,
+            envoy_tx,
+            cancel,
+            move |_, this_cancel| {
+                let mut ev = SimulatedSurveyorEnvoy::new(id, sim.clone(), tx.clone(), this_cancel);
+                async move { ev.wait_check_status().await }
+            },
+        );
+        handles.push(supervisor.spawn(runtime));
+    }
+
+    return (handles, transition_switchboard);
+}
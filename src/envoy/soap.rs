@@ -0,0 +1,164 @@
+use super::ecc_envoy::{ECCOperationResponse, ECCStatusResponse};
+use super::error::EnvoyError;
+use serde::de::{DeserializeOwned, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::marker::PhantomData;
+
+const SOAP_FAULT_TAG: &str = "Fault";
+
+/// # SoapFault
+/// The standard SOAP 1.1 fault body. Any detail block is ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SoapFault {
+    #[serde(default)]
+    pub faultcode: String,
+    #[serde(default)]
+    pub faultstring: String,
+}
+
+/// The content of a SOAP Body: either a fault or the payload, along with the
+/// local name of the payload element.
+#[derive(Debug)]
+enum SoapBody<T> {
+    Fault(SoapFault),
+    Payload(String, T),
+    Empty,
+}
+
+/// A SOAP envelope. quick-xml matches elements on their local names, so namespace
+/// prefixes (SOAP-ENV:, soap:, ns:, ...) do not matter.
+#[derive(Debug, Deserialize)]
+#[serde(bound = "T: DeserializeOwned")]
+struct SoapEnvelope<T> {
+    #[serde(rename = "Body")]
+    body: SoapBody<T>,
+}
+
+/// The ECC server wraps its payload in an element named after the operation, which we only care about for requests.
+/// So the Body is read as a map: a Fault child is a fault, any other child element is the payload, and
+/// attributes (namespace declarations) are skipped.
+impl<'de, T: DeserializeOwned> Deserialize<'de> for SoapBody<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(SoapBodyVisitor(PhantomData))
+    }
+}
+
+struct SoapBodyVisitor<T>(PhantomData<T>);
+
+impl<'de, T: DeserializeOwned> Visitor<'de> for SoapBodyVisitor<T> {
+    type Value = SoapBody<T>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a SOAP Body containing a Fault or a response element")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut body = SoapBody::Empty;
+        while let Some(key) = map.next_key::<String>()? {
+            if key.starts_with('@') || !matches!(body, SoapBody::Empty) {
+                map.next_value::<IgnoredAny>()?;
+            } else if key == SOAP_FAULT_TAG {
+                body = SoapBody::Fault(map.next_value()?);
+            } else {
+                body = SoapBody::Payload(key, map.next_value()?);
+            }
+        }
+        Ok(body)
+    }
+}
+
+/// Deserialize a SOAP envelope and pull out the payload and its element name, converting a Fault to an EnvoyError
+fn parse_soap<T: DeserializeOwned>(text: &str) -> Result<(String, T), EnvoyError> {
+    let envelope: SoapEnvelope<T> = quick_xml::de::from_str(text)?;
+    match envelope.body {
+        SoapBody::Payload(name, payload) => Ok((name, payload)),
+        SoapBody::Fault(fault) => Err(EnvoyError::SoapFault(fault.faultcode, fault.faultstring)),
+        SoapBody::Empty => Err(EnvoyError::XMLConversionError),
+    }
+}
+
+/// Parse the reply to an ECC transition (Describe, Prepare, Configure, ...)
+pub fn parse_ecc_operation_response(text: &str) -> Result<ECCOperationResponse, EnvoyError> {
+    Ok(parse_soap::<ECCOperationResponse>(text)?.1)
+}
+
+/// Parse the reply to an ECC GetState request
+pub fn parse_ecc_status_response(text: &str) -> Result<ECCStatusResponse, EnvoyError> {
+    Ok(parse_soap::<ECCStatusResponse>(text)?.1)
+}
+
+/// One of the sub-configurations named in a ConfigId
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubConfigId {
+    #[serde(rename = "@type")]
+    pub kind: String,
+    #[serde(rename = "$text", default)]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigId {
+    #[serde(rename = "SubConfigId", default)]
+    pub sub_configs: Vec<SubConfigId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigIdBlock {
+    #[serde(rename = "ConfigId")]
+    pub config_id: ConfigId,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataSender {
+    #[serde(rename = "@id")]
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataRouter {
+    #[serde(rename = "@ipAddress")]
+    pub ip_address: String,
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@port")]
+    pub port: u16,
+    #[serde(rename = "@type")]
+    pub protocol: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataLink {
+    #[serde(rename = "DataSender")]
+    pub sender: DataSender,
+    #[serde(rename = "DataRouter")]
+    pub router: DataRouter,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataLinkSet {
+    #[serde(rename = "DataLink", default)]
+    pub links: Vec<DataLink>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataLinkTable {
+    #[serde(rename = "DataLinkSet")]
+    pub link_set: DataLinkSet,
+}
+
+/// # ECCRequest
+/// The body of a request made to an ECC server, as composed by ECCEnvoy. GetState has neither block;
+/// transitions carry both the ConfigId and the DataLinkSet.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ECCRequest {
+    #[serde(rename = "configID")]
+    pub config: Option<ConfigIdBlock>,
+    #[serde(rename = "table")]
+    pub data_links: Option<DataLinkTable>,
+}
+
+/// Parse a request made to an ECC server, returning the operation name and the body.
+/// This is the server side of the protocol, used by the mock ECC server.
+pub fn parse_ecc_request(text: &str) -> Result<(String, ECCRequest), EnvoyError> {
+    parse_soap::<ECCRequest>(text)
+}
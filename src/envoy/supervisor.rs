@@ -0,0 +1,234 @@
+use super::error::EnvoyError;
+use super::message::EmbassyMessage;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+/// Wait before the first restart of a failed envoy. Doubled for each consecutive failure.
+const RESTART_BACKOFF_START: Duration = Duration::from_secs(1);
+/// Longest wait between restarts
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// An envoy which ran at least this long before failing is considered to have been healthy,
+/// and the backoff starts over
+const RESTART_HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// The kinds of envoy task run for a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EnvoyKind {
+    ECCTransition,
+    ECCStatus,
+    Surveyor,
+}
+
+impl std::fmt::Display for EnvoyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ECCTransition => write!(f, "ECC Transition"),
+            Self::ECCStatus => write!(f, "ECC Status"),
+            Self::Surveyor => write!(f, "Surveyor"),
+        }
+    }
+}
+
+/// The state of a supervised envoy task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvoyTaskState {
+    Running,
+    Restarting,
+    Stopped,
+}
+
+impl std::fmt::Display for EnvoyTaskState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Running => write!(f, "Running"),
+            Self::Restarting => write!(f, "Restarting"),
+            Self::Stopped => write!(f, "Stopped"),
+        }
+    }
+}
+
+/// # EnvoyHealth
+/// Reported by the supervisor of an envoy task every time the task changes state.
+/// restarts counts every restart of the task during the session, and last_error is why the task last died.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvoyHealth {
+    pub kind: EnvoyKind,
+    pub state: EnvoyTaskState,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+/// # Supervisor
+/// Runs an envoy as a task, watching its JoinHandle. If the envoy returns an error or panics it is
+/// created again from `make_envoy` after a backoff, until the cancel signal is given. An envoy which returns
+/// Ok has been cancelled (or lost its embassy) and is not restarted.
+///
+/// Every incarnation of the envoy gets a new incoming channel and cancel subscription. If the supervisor is given
+/// an incoming channel (i.e. for transition envoys), messages are forwarded from it to the current incarnation, so that
+/// the embassy keeps the same sender for the whole session.
+///
+/// Health is reported to the embassy as EnvoyHealth messages.
+pub struct Supervisor<F> {
+    id: i32,
+    kind: EnvoyKind,
+    incoming: Option<mpsc::Receiver<EmbassyMessage>>,
+    outgoing: mpsc::Sender<EmbassyMessage>,
+    cancel: broadcast::Sender<EmbassyMessage>,
+    make_envoy: F,
+    health: EnvoyHealth,
+}
+
+impl<F, Fut> Supervisor<F>
+where
+    F: FnMut(mpsc::Receiver<EmbassyMessage>, broadcast::Receiver<EmbassyMessage>) -> Fut
+        + Send
+        + 'static,
+    Fut: Future<Output = Result<(), EnvoyError>> + Send + 'static,
+{
+    pub fn new(
+        id: i32,
+        kind: EnvoyKind,
+        incoming: Option<mpsc::Receiver<EmbassyMessage>>,
+        outgoing: &mpsc::Sender<EmbassyMessage>,
+        cancel: &broadcast::Sender<EmbassyMessage>,
+        make_envoy: F,
+    ) -> Self {
+        return Self {
+            id,
+            kind,
+            incoming,
+            outgoing: outgoing.clone(),
+            cancel: cancel.clone(),
+            make_envoy,
+            health: EnvoyHealth {
+                kind,
+                state: EnvoyTaskState::Running,
+                restarts: 0,
+                last_error: None,
+            },
+        };
+    }
+
+    /// Spawn the supervisor (and with it the envoy) on the runtime. Everything the envoy logs is attributed to
+    /// its module and kind through the `envoy` span.
+    pub fn spawn(self, runtime: &tokio::runtime::Runtime) -> JoinHandle<()> {
+        let span = tracing::info_span!("envoy", module = self.id, envoy = %self.kind);
+        runtime.spawn(self.supervise().instrument(span))
+    }
+
+    async fn supervise(mut self) {
+        let mut cancel = self.cancel.subscribe();
+        let mut backoff = RESTART_BACKOFF_START;
+        loop {
+            if self.report(EnvoyTaskState::Running).await.is_err() {
+                return;
+            }
+            let started = tokio::time::Instant::now();
+            let error = match self.run_envoy().await {
+                Some(e) => e,
+                None => {
+                    let _ = self.report(EnvoyTaskState::Stopped).await;
+                    return;
+                }
+            };
+
+            if started.elapsed() >= RESTART_HEALTHY_RUN {
+                backoff = RESTART_BACKOFF_START;
+            }
+            tracing::error!(
+                "{} envoy {} died: {}. Restarting in {}s",
+                self.kind,
+                self.id,
+                error,
+                backoff.as_secs()
+            );
+            self.health.restarts += 1;
+            self.health.last_error = Some(error);
+            if self.report(EnvoyTaskState::Restarting).await.is_err() {
+                return;
+            }
+
+            tokio::select! {
+                _ = cancel.recv() => {
+                    let _ = self.report(EnvoyTaskState::Stopped).await;
+                    return;
+                }
+                _ = tokio::time::sleep(backoff) => ()
+            }
+            backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+        }
+    }
+
+    /// Run one incarnation of the envoy to completion. Returns the reason the envoy died, or None if it stopped cleanly.
+    /// If the embassy drops the incoming channel, the channel to the envoy is closed too.
+    async fn run_envoy(&mut self) -> Option<String> {
+        let (envoy_tx, envoy_rx) = mpsc::channel::<EmbassyMessage>(10);
+        let mut envoy_tx = Some(envoy_tx);
+        let mut handle = tokio::spawn(
+            (self.make_envoy)(envoy_rx, self.cancel.subscribe())
+                .instrument(tracing::Span::current()),
+        );
+        let result = loop {
+            tokio::select! {
+                result = &mut handle => break result,
+                message = Self::recv_incoming(&mut self.incoming) => match (message, envoy_tx.as_ref()) {
+                    (Some(m), Some(tx)) => {
+                        if let Err(e) = tx.send(m).await {
+                            tracing::warn!("{} envoy {} dropped a message: {}", self.kind, self.id, e);
+                        }
+                    }
+                    _ => {
+                        self.incoming = None;
+                        envoy_tx = None;
+                    }
+                }
+            }
+        };
+
+        match result {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(e) if e.is_panic() => {
+                let payload = e.into_panic();
+                let reason = match payload.downcast_ref::<&str>() {
+                    Some(s) => String::from(*s),
+                    None => match payload.downcast_ref::<String>() {
+                        Some(s) => s.clone(),
+                        None => String::from("unknown cause"),
+                    },
+                };
+                Some(format!("Envoy task panicked: {reason}"))
+            }
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    /// Receive from the incoming channel, or wait forever if there is none
+    async fn recv_incoming(
+        incoming: &mut Option<mpsc::Receiver<EmbassyMessage>>,
+    ) -> Option<EmbassyMessage> {
+        match incoming {
+            Some(rx) => rx.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Send the health of the envoy to the embassy. Fails only if the embassy is gone.
+    /// Stopped is reported on a best effort basis, as the embassy is not read while disconnecting.
+    async fn report(&mut self, state: EnvoyTaskState) -> Result<(), EnvoyError> {
+        self.health.state = state;
+        let message = EmbassyMessage::compose_envoy_health(self.health.clone(), self.id);
+        match state {
+            EnvoyTaskState::Stopped => {
+                let _ = self.outgoing.try_send(message);
+            }
+            _ => self.outgoing.send(message).await?,
+        }
+        Ok(())
+    }
+}
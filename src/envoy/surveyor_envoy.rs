@@ -1,14 +1,22 @@
-use super::constants::{ADDRESS_START, NUMBER_OF_MODULES};
-use super::error::EnvoyError;
-use super::message::EmbassyMessage;
-use reqwest::{Client, Response};
+use super::connection_status::ConnectionStatus;
+use super::constants::STATUS_POLL_SECS;
+use super::error::{EnvoyError, SurveyorParseError};
+use super::message::{EmbassyMessage, MessageTiming};
+use super::supervisor::{EnvoyKind, Supervisor};
+use super::surveyor_page::parse_surveyor_page;
+use super::topology::{SurveyorEndpoint, Topology};
+use super::transport::{HttpTransport, Transport};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
-const SURVEYOR_URL_PORT: i32 = 8081;
+//5s timeouts, surveyor.html is small and is asked for again in a few seconds anyways
+const SURVEYOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const SURVEYOR_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// # SurveyorResponse
 /// The message delivered from the SurveyorEnvoy
@@ -25,6 +33,10 @@ pub struct SurveyorResponse {
     pub files: i32,
     pub bytes_used: u64,
     pub data_rate: f64,
+    pub connection: ConnectionStatus,
+    /// When the surveyor page was read. None if it could not be (recordings made before this was added also have None)
+    #[serde(default)]
+    pub measured_at: Option<SystemTime>,
 }
 
 impl Default for SurveyorResponse {
@@ -39,10 +51,56 @@ impl Default for SurveyorResponse {
             files: 0,
             bytes_used: 0,
             data_rate: 0.0,
+            connection: ConnectionStatus::NoContact,
+            measured_at: None,
         }
     }
 }
 
+/// # RateMeter
+/// Works out the data rate of a data router from successive byte counts, over the time which actually passed
+/// between them. Polls are two seconds apart only when the requests are quick; under load they can be much more.
+/// The first count has nothing to compare to and gives a rate of zero.
+#[derive(Debug, Default)]
+pub struct RateMeter {
+    last: Option<(Instant, u64)>,
+}
+
+impl RateMeter {
+    pub fn new() -> Self {
+        return Self { last: None };
+    }
+
+    /// Record a byte count, taken now, and return the rate since the last one in MB/s
+    pub fn measure(&mut self, bytes_used: u64) -> f64 {
+        let now = Instant::now();
+        let (last_time, last_bytes) = match self.last.replace((now, bytes_used)) {
+            Some(last) => last,
+            None => return 0.0,
+        };
+
+        //The byte count goes down when the graw files are moved off of the disk at the end of a run.
+        //Everything now on the disk was written since then.
+        let written = match bytes_used.checked_sub(last_bytes) {
+            Some(written) => written,
+            None => {
+                tracing::info!(
+                    "Bytes used went from {} to {}, the files were moved",
+                    last_bytes,
+                    bytes_used
+                );
+                bytes_used
+            }
+        };
+
+        let elapsed = now.duration_since(last_time).as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        return (written as f64) * 1.0e-6 / elapsed; //MB/s
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct SurveyorConfig {
@@ -52,19 +110,16 @@ pub struct SurveyorConfig {
 }
 
 impl SurveyorConfig {
-    pub fn new(id: i32) -> Self {
-        let address = Self::address(&id);
-        let url = Self::url(&address);
+    pub fn new(id: i32, endpoint: &SurveyorEndpoint) -> Self {
+        let address = endpoint.host.clone();
+        let url = Self::url(endpoint);
 
         Self { id, address, url }
     }
 
-    fn address(id: &i32) -> String {
-        format!("{ADDRESS_START}.{}", 60 + id)
-    }
-
-    fn url(address: &str) -> String {
-        format!("http://{address}:{SURVEYOR_URL_PORT}/~attpc/surveyor.html")
+    fn url(endpoint: &SurveyorEndpoint) -> String {
+        let path = endpoint.path.trim_start_matches('/');
+        format!("http://{}:{}/{path}", endpoint.host, endpoint.port)
     }
 }
 
@@ -72,37 +127,31 @@ impl SurveyorConfig {
 /// The structure encompassing an async task associated with the Surveyor/Data Router system.
 /// At the moment, SurveyorEnvoys can only check the status of the DataRouter as no commands can
 /// be sent to them. But maybe in the future this will change.
+///
+/// Requests go through a Transport, which is an HttpTransport to the Surveyor outside of tests.
 #[derive(Debug)]
 pub struct SurveyorEnvoy {
     config: SurveyorConfig,
-    connection: Client,
+    transport: Arc<dyn Transport>,
     outgoing: mpsc::Sender<EmbassyMessage>,
     cancel: broadcast::Receiver<EmbassyMessage>,
-    last_bytes: u64,
+    meter: RateMeter,
 }
 
 impl SurveyorEnvoy {
     pub fn new(
         config: SurveyorConfig,
+        transport: Arc<dyn Transport>,
         tx: mpsc::Sender<EmbassyMessage>,
         cancel: broadcast::Receiver<EmbassyMessage>,
-    ) -> Result<Self, EnvoyError> {
-        //10s default timeouts
-        let connection_out = Duration::from_secs(10);
-        let req_timeout = Duration::from_secs(10);
-
-        //Probably need some options here, for now just set some timeouts
-        let client = Client::builder()
-            .connect_timeout(connection_out)
-            .timeout(req_timeout)
-            .build()?;
-        return Ok(Self {
+    ) -> Self {
+        return Self {
             config,
-            connection: client,
+            transport,
             outgoing: tx,
             cancel,
-            last_bytes: 0,
-        });
+            meter: RateMeter::new(),
+        };
     }
 
     /// This is the core task loop for a SurveyorEnvoy. Every two seconds check the
@@ -114,107 +163,88 @@ impl SurveyorEnvoy {
                     return Ok(());
                 }
 
-                _ = tokio::time::sleep(Duration::from_secs(2)) => {
-                    if let Ok(response) = self.submit_check_status().await {
-                        match response {
-                            Some(resp) => self.outgoing.send(resp).await?,
-                            None => ()
-                        }
-                    } else {
-                        let message = EmbassyMessage::compose_surveyor_response(serde_yaml::to_string(&SurveyorResponse::default())?, self.config.id);
-                        self.outgoing.send(message).await?
-                    }
+                _ = tokio::time::sleep(Duration::from_secs(STATUS_POLL_SECS)) => {
+                    let timing = MessageTiming::start();
+                    let response = match self.submit_check_status().await {
+                        Ok(Some(resp)) => resp,
+                        Ok(None) => continue,
+                        Err(e) => SurveyorResponse { connection: ConnectionStatus::from(&e), ..Default::default() },
+                    };
+                    let message = EmbassyMessage::compose_surveyor_response(response, self.config.id, timing.finish());
+                    self.outgoing.send(message).await?
                 }
             }
         }
     }
 
-    async fn submit_check_status(&mut self) -> Result<Option<EmbassyMessage>, EnvoyError> {
-        let response = self.connection.get(&self.config.url).send().await?;
-        let parsed_response = self.parse_response(response).await?;
+    async fn submit_check_status(&mut self) -> Result<Option<SurveyorResponse>, EnvoyError> {
+        let response = self
+            .transport
+            .get(&self.config.url, SURVEYOR_REQUEST_TIMEOUT)
+            .await?;
+        let parsed_response = self.parse_response(&response)?;
         Ok(parsed_response)
     }
 
-    /// Parses the html response.
-    async fn parse_response(
+    /// Parses the surveyor page (see parse_surveyor_page), stamps it with the time it was read, and works out the data rate.
+    /// An empty page is skipped.
+    fn parse_response(
         &mut self,
-        response: Response,
-    ) -> Result<Option<EmbassyMessage>, EnvoyError> {
-        let response_text = response.text().await?;
-        let mut status = SurveyorResponse::default();
-        let lines: Vec<&str> = response_text.lines().collect();
-
-        if lines.len() == 0 {
-            return Ok(None);
-        }
-
-        status.state = lines[0].parse::<i32>()?;
+        response_text: &str,
+    ) -> Result<Option<SurveyorResponse>, EnvoyError> {
+        let mut status = match parse_surveyor_page(response_text) {
+            Ok(status) => status,
+            Err(SurveyorParseError::Empty) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        status.connection = ConnectionStatus::Connected;
+        status.measured_at = Some(SystemTime::now());
         if status.state == 0 {
-            return Ok(Some(EmbassyMessage::compose_surveyor_response(
-                serde_yaml::to_string(&status)?,
-                self.config.id,
-            )));
+            return Ok(Some(status));
         }
         status.address = self.config.address.clone();
-        status.location = String::from(lines[1]);
-        let line_entries: Vec<&str> = lines[3].split_whitespace().collect();
-        status.percent_used = String::from(line_entries[4]);
-        status.disk_space = line_entries[1].parse::<u64>()? * 512;
-
-        let mut bytes: u64 = 0;
-        let mut n_files = 0;
-        for line in lines[4..].iter() {
-            if line.contains("graw") {
-                let line_entries: Vec<&str> = line.split_whitespace().collect();
-                bytes += line_entries[4].parse::<u64>()?;
-                n_files += 1;
-            }
-        }
-
-        if n_files > 0 {
-            status.disk_status = String::from("Filled");
-        } else {
-            status.disk_status = String::from("Empty");
-        }
+        status.data_rate = self.meter.measure(status.bytes_used);
 
-        status.files = n_files;
-        status.bytes_used = bytes;
-
-        status.data_rate = ((bytes - self.last_bytes) as f64) * 1.0e-6 / 2.0; //MB/s
-
-        self.last_bytes = bytes;
-
-        Ok(Some(EmbassyMessage::compose_surveyor_response(
-            serde_yaml::to_string(&status)?,
-            self.config.id,
-        )))
+        Ok(Some(status))
     }
 }
 
 /// Function to create all of the SurveyorEnvoys and spawn their tatsks. Returns handles to the tasks.
+/// Masked modules do not get envoys. Every envoy runs under a Supervisor, which restarts it if it dies.
 pub fn startup_surveyor_envoys(
     runtime: &mut tokio::runtime::Runtime,
+    topology: &Topology,
+    masked: &[i32],
     surveyor_tx: &mpsc::Sender<EmbassyMessage>,
     cancel: &broadcast::Sender<EmbassyMessage>,
 ) -> Vec<JoinHandle<()>> {
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
-    //spin up the surveyor envoys, Mutant does not get a data router/surveyor
-    for id in 0..(NUMBER_OF_MODULES - 1) {
-        let config = SurveyorConfig::new(id);
+    //spin up the surveyor envoys, only modules with a data router/surveyor get one (not Mutant)
+    for module in topology.active_modules(masked) {
+        let config = match &module.surveyor {
+            Some(endpoint) => SurveyorConfig::new(module.id, endpoint),
+            None => continue,
+        };
         let this_surveyor_tx = surveyor_tx.clone();
-        let this_cancel = cancel.subscribe();
-        let handle = runtime.spawn(async move {
-            match SurveyorEnvoy::new(config, this_surveyor_tx, this_cancel) {
-                Ok(mut ev) => match ev.wait_check_status().await {
-                    Ok(()) => (),
-                    Err(e) => tracing::error!("Surveyor status envoy ran into an error: {}", e),
-                },
-                Err(e) => tracing::error!("Error creating Surveyor status envoy: {}", e),
-            }
-        });
+        let supervisor = Supervisor::new(
+            module.id,
+            EnvoyKind::Surveyor,
+            None,
+            surveyor_tx,
+            cancel,
+            move |_, this_cancel| {
+                let config = config.clone();
+                let tx = this_surveyor_tx.clone();
+                async move {
+                    let transport = Arc::new(HttpTransport::new(SURVEYOR_CONNECT_TIMEOUT)?);
+                    let mut ev = SurveyorEnvoy::new(config, transport, tx, this_cancel);
+                    ev.wait_check_status().await
+                }
+            },
+        );
 
-        handles.push(handle);
+        handles.push(supervisor.spawn(runtime));
     }
 
     return handles;
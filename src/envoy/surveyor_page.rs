@@ -0,0 +1,81 @@
+use super::error::SurveyorParseError;
+use super::surveyor_envoy::SurveyorResponse;
+
+/// df reports the size of the disk in blocks of this many bytes
+const DF_BLOCK_SIZE: u64 = 512;
+const DF_HEADER_START: &str = "Filesystem";
+const GRAW_EXTENSION: &str = ".graw";
+
+/// Parse the surveyor.html page served by a data router. The page is plain text, line by line:
+///
+/// - the state of the Surveyor (1 for online, 0 for offline, in which case nothing follows)
+/// - the directory the data is written to
+/// - the output of `df` for the disk (the header may be missing)
+/// - the output of `ls -l` for the directory, where the .graw files are counted
+///
+/// Blank lines, surrounding whitespace, and Windows line endings are ignored, as are lines of the listing which are not
+/// .graw files. Anything else which does not fit is an error naming the bad field. The address, data rate, and connection
+/// of the response are left for the envoy.
+pub fn parse_surveyor_page(text: &str) -> Result<SurveyorResponse, SurveyorParseError> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    let state_line = lines.next().ok_or(SurveyorParseError::Empty)?;
+    let state = state_line
+        .parse::<i32>()
+        .map_err(|_| SurveyorParseError::BadState(String::from(state_line)))?;
+    let mut status = SurveyorResponse {
+        state,
+        ..Default::default()
+    };
+    if state == 0 {
+        return Ok(status);
+    }
+
+    status.location = String::from(lines.next().ok_or(SurveyorParseError::MissingLocation)?);
+
+    let mut disk_line = lines.next().ok_or(SurveyorParseError::MissingDiskLine)?;
+    if disk_line.starts_with(DF_HEADER_START) {
+        disk_line = lines.next().ok_or(SurveyorParseError::MissingDiskLine)?;
+    }
+    let disk_entries: Vec<&str> = disk_line.split_whitespace().collect();
+    if disk_entries.len() < 5 {
+        return Err(SurveyorParseError::BadDiskLine(String::from(disk_line)));
+    }
+    let blocks = disk_entries[1]
+        .parse::<u64>()
+        .map_err(|_| SurveyorParseError::BadDiskSize(String::from(disk_entries[1])))?;
+    status.disk_space = blocks.saturating_mul(DF_BLOCK_SIZE);
+    let percent_used = disk_entries[4];
+    match percent_used.strip_suffix('%').map(|p| p.parse::<f64>()) {
+        Some(Ok(_)) => status.percent_used = String::from(percent_used),
+        _ => {
+            return Err(SurveyorParseError::BadPercentUsed(String::from(
+                percent_used,
+            )))
+        }
+    }
+
+    let mut bytes: u64 = 0;
+    let mut n_files = 0;
+    for line in lines {
+        let file_entries: Vec<&str> = line.split_whitespace().collect();
+        match file_entries.last() {
+            Some(name) if name.ends_with(GRAW_EXTENSION) => (),
+            _ => continue,
+        }
+        let size = match file_entries.get(4).map(|s| s.parse::<u64>()) {
+            Some(Ok(size)) => size,
+            _ => return Err(SurveyorParseError::BadFileLine(String::from(line))),
+        };
+        bytes = bytes.saturating_add(size);
+        n_files += 1;
+    }
+
+    status.disk_status = match n_files {
+        0 => String::from("Empty"),
+        _ => String::from("Filled"),
+    };
+    status.files = n_files;
+    status.bytes_used = bytes;
+    return Ok(status);
+}
@@ -0,0 +1,127 @@
+use super::ecc_operation::ECCOperation;
+use super::error::EmbassyError;
+use super::message::{EmbassyMessage, OperationId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// An ECC operation which has been submitted to a transition envoy and has not been answered yet
+#[derive(Debug, Clone)]
+pub struct PendingOperation {
+    pub op_id: OperationId,
+    pub operation: ECCOperation,
+    pub submitted: Instant,
+}
+
+/// # ECCSwitchboard
+/// The only way to submit operations to the ECC transition envoys. Every submission is given a unique OperationId,
+/// which the envoy echoes in its response, and is kept in the pending-operations table until that response is handled.
+/// A module can only have one operation in flight; further submissions are rejected until it is answered.
+///
+/// The switchboard is cheap to clone and clones share the table, so that the UI (through the Embassy) and
+/// the RunController see the same operations in flight.
+#[derive(Debug, Clone)]
+pub struct ECCSwitchboard {
+    senders: Arc<HashMap<i32, mpsc::Sender<EmbassyMessage>>>,
+    pending: Arc<Mutex<HashMap<i32, PendingOperation>>>,
+    next_op_id: Arc<AtomicU64>,
+}
+
+impl ECCSwitchboard {
+    pub fn new(senders: HashMap<i32, mpsc::Sender<EmbassyMessage>>) -> Self {
+        return Self {
+            senders: Arc::new(senders),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_op_id: Arc::new(AtomicU64::new(1)),
+        };
+    }
+
+    /// Submit an operation from synchronous code (i.e. the UI)
+    pub fn submit_blocking(
+        &self,
+        operation: ECCOperation,
+        id: i32,
+    ) -> Result<OperationId, EmbassyError> {
+        let (op_id, sender, message) = self.reserve(operation, id)?;
+        if let Err(e) = sender.blocking_send(message) {
+            self.abandon(id);
+            return Err(e.into());
+        }
+        return Ok(op_id);
+    }
+
+    /// Submit an operation from async code (i.e. the RunController)
+    pub async fn submit(
+        &self,
+        operation: ECCOperation,
+        id: i32,
+    ) -> Result<OperationId, EmbassyError> {
+        let (op_id, sender, message) = self.reserve(operation, id)?;
+        if let Err(e) = sender.send(message).await {
+            self.abandon(id);
+            return Err(e.into());
+        }
+        return Ok(op_id);
+    }
+
+    /// Check that the module can take an operation and add it to the pending table
+    fn reserve(
+        &self,
+        operation: ECCOperation,
+        id: i32,
+    ) -> Result<(OperationId, mpsc::Sender<EmbassyMessage>, EmbassyMessage), EmbassyError> {
+        let sender = self.senders.get(&id).ok_or(EmbassyError::NoEnvoy(id))?;
+        let mut pending = self.lock_pending();
+        if let Some(in_flight) = pending.get(&id) {
+            return Err(EmbassyError::OperationInFlight(
+                id,
+                in_flight.op_id,
+                in_flight.operation.to_string(),
+            ));
+        }
+        let op_id = OperationId(self.next_op_id.fetch_add(1, Ordering::Relaxed));
+        pending.insert(
+            id,
+            PendingOperation {
+                op_id,
+                operation: operation.clone(),
+                submitted: Instant::now(),
+            },
+        );
+        let message = EmbassyMessage::compose_ecc_op(operation, id, op_id);
+        return Ok((op_id, sender.clone(), message));
+    }
+
+    /// Remove the operation answered by a response from the table. Returns None if the response
+    /// does not belong to the operation in flight for the module, i.e. the response is stale.
+    pub fn complete(&self, id: i32, op_id: OperationId) -> Option<PendingOperation> {
+        let mut pending = self.lock_pending();
+        match pending.get(&id) {
+            Some(p) if p.op_id == op_id => pending.remove(&id),
+            _ => None,
+        }
+    }
+
+    /// Forget the operation in flight for a module, which will never be answered (i.e. its envoy died)
+    pub fn abandon(&self, id: i32) -> Option<PendingOperation> {
+        self.lock_pending().remove(&id)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.lock_pending().is_empty()
+    }
+
+    pub fn get_pending(&self, id: i32) -> Option<PendingOperation> {
+        self.lock_pending().get(&id).cloned()
+    }
+
+    /// The table is only ever held for a lookup, so a poisoned lock still holds a consistent table
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, HashMap<i32, PendingOperation>> {
+        match self.pending.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
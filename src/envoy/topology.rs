@@ -0,0 +1,202 @@
+use super::constants::{
+    ADDRESS_START, ECC_PORT, LISTENER_PORT, MUTANT_ID, NUMBER_OF_MODULES, PROTOCOL, SURVEYOR_PATH,
+    SURVEYOR_PORT,
+};
+use super::error::TopologyError;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const MODULE_ROLE_COBO: &str = "CoBo";
+const MODULE_ROLE_MUTANT: &str = "MuTaNT";
+
+/// The role a module plays in the GET system
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ModuleRole {
+    CoBo,
+    #[serde(rename = "MuTaNT", alias = "Mutant")]
+    Mutant,
+}
+
+impl std::fmt::Display for ModuleRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CoBo => write!(f, "{MODULE_ROLE_COBO}"),
+            Self::Mutant => write!(f, "{MODULE_ROLE_MUTANT}"),
+        }
+    }
+}
+
+/// Where to find the ECC server of a module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ECCEndpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Where to find the Surveyor page of a module's data router
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurveyorEndpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+/// The data router a module sends its data to. This is what is given to
+/// the ECC server in the DataLinkSet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataRouterEndpoint {
+    pub ip: String,
+    pub port: u16,
+    pub protocol: String,
+}
+
+/// # ModuleConfig
+/// A single entry in the topology: one CoBo or the MuTaNT, and all of the
+/// addresses needed to talk to it. The MuTaNT does not have a Surveyor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleConfig {
+    pub id: i32,
+    pub role: ModuleRole,
+    pub ecc: ECCEndpoint,
+    #[serde(default)]
+    pub surveyor: Option<SurveyorEndpoint>,
+    pub data_router: DataRouterEndpoint,
+}
+
+/// # Topology
+/// The layout of the detector DAQ: which modules exist and where they live.
+/// Module ids are used as indices throughout the application, so they must
+/// run from 0 to N-1 (in any order in the file) with exactly one MuTaNT.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Topology {
+    pub name: String,
+    pub modules: Vec<ModuleConfig>,
+}
+
+impl Default for Topology {
+    /// The full AT-TPC, built from the values in envoy::constants
+    fn default() -> Self {
+        let mut modules: Vec<ModuleConfig> = vec![];
+        for id in 0..NUMBER_OF_MODULES {
+            let address = match id {
+                MUTANT_ID => format!("{ADDRESS_START}.1"),
+                _ => format!("{ADDRESS_START}.{}", 60 + id),
+            };
+            let (role, surveyor) = match id {
+                MUTANT_ID => (ModuleRole::Mutant, None),
+                _ => (
+                    ModuleRole::CoBo,
+                    Some(SurveyorEndpoint {
+                        host: address.clone(),
+                        port: SURVEYOR_PORT,
+                        path: String::from(SURVEYOR_PATH),
+                    }),
+                ),
+            };
+            modules.push(ModuleConfig {
+                id,
+                role,
+                ecc: ECCEndpoint {
+                    host: address.clone(),
+                    port: ECC_PORT,
+                },
+                surveyor,
+                data_router: DataRouterEndpoint {
+                    ip: address,
+                    port: LISTENER_PORT,
+                    protocol: String::from(PROTOCOL),
+                },
+            });
+        }
+        return Self {
+            name: String::from("AT-TPC"),
+            modules,
+        };
+    }
+}
+
+impl Topology {
+    /// Read a topology from a YAML file and validate it
+    pub fn read(path: &Path) -> Result<Self, TopologyError> {
+        let mut yaml_str = String::new();
+        File::open(path)?.read_to_string(&mut yaml_str)?;
+        let mut topology = serde_yaml::from_str::<Topology>(&yaml_str)?;
+        topology.validate()?;
+        return Ok(topology);
+    }
+
+    /// Check that ids are 0..N-1 and that there is exactly one MuTaNT.
+    /// Modules are sorted by id so that they can be indexed directly.
+    pub fn validate(&mut self) -> Result<(), TopologyError> {
+        if self.modules.is_empty() {
+            return Err(TopologyError::NoModules);
+        }
+
+        self.modules.sort_by_key(|m| m.id);
+        for (idx, module) in self.modules.iter().enumerate() {
+            if module.id != idx as i32 {
+                return Err(TopologyError::BadModuleId(module.id));
+            }
+        }
+
+        let n_mutants = self
+            .modules
+            .iter()
+            .filter(|m| m.role == ModuleRole::Mutant)
+            .count();
+        if n_mutants != 1 {
+            return Err(TopologyError::MutantCount(n_mutants));
+        }
+
+        Ok(())
+    }
+
+    pub fn number_of_modules(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn mutant_id(&self) -> i32 {
+        self.modules
+            .iter()
+            .find(|m| m.role == ModuleRole::Mutant)
+            .map(|m| m.id)
+            .expect("Topology was not validated; no MuTaNT!")
+    }
+
+    /// All of the module ids, MuTaNT included
+    pub fn module_ids(&self) -> Vec<i32> {
+        self.modules.iter().map(|m| m.id).collect()
+    }
+
+    /// All of the CoBo ids (everything but the MuTaNT)
+    pub fn cobo_ids(&self) -> Vec<i32> {
+        self.modules
+            .iter()
+            .filter(|m| m.role == ModuleRole::CoBo)
+            .map(|m| m.id)
+            .collect()
+    }
+
+    /// The ids of modules which have a Surveyor/data router page to monitor
+    pub fn surveyor_ids(&self) -> Vec<i32> {
+        self.modules
+            .iter()
+            .filter(|m| m.surveyor.is_some())
+            .map(|m| m.id)
+            .collect()
+    }
+
+    /// The modules which have not been masked out of the system. The MuTaNT can never be masked.
+    pub fn active_modules(&self, masked: &[i32]) -> Vec<&ModuleConfig> {
+        self.modules
+            .iter()
+            .filter(|m| m.role == ModuleRole::Mutant || !masked.contains(&m.id))
+            .collect()
+    }
+
+    pub fn get_module(&self, id: i32) -> Option<&ModuleConfig> {
+        self.modules.get(id as usize)
+    }
+}
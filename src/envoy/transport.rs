@@ -0,0 +1,69 @@
+use super::error::{EnvoyError, TransportError};
+use reqwest::Client;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// The future returned by a Transport request: the text of the reply, or why there was none
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<String, TransportError>> + Send + 'a>>;
+
+/// # Transport
+/// How the envoys reach their servers. The ECCEnvoy posts SOAP messages and the SurveyorEnvoy gets the surveyor page;
+/// both only need the text of the reply. The envoys are given an HttpTransport to talk to the AT-TPC, but any
+/// implementation can be injected (i.e. an in-memory one returning canned replies, for tests).
+pub trait Transport: Send + Sync + std::fmt::Debug {
+    /// POST the body to the url, giving up after the timeout
+    fn post<'a>(&'a self, url: &'a str, body: String, timeout: Duration) -> TransportFuture<'a>;
+
+    /// GET the url, giving up after the timeout
+    fn get<'a>(&'a self, url: &'a str, timeout: Duration) -> TransportFuture<'a>;
+}
+
+/// # HttpTransport
+/// The Transport to the real servers, over HTTP with reqwest
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: Client,
+}
+
+impl HttpTransport {
+    pub fn new(connect_timeout: Duration) -> Result<Self, EnvoyError> {
+        let client = Client::builder().connect_timeout(connect_timeout).build()?;
+        return Ok(Self { client });
+    }
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(value: reqwest::Error) -> Self {
+        if value.is_timeout() {
+            return Self::Timeout;
+        } else if value.is_connect() {
+            return Self::Connect(value.to_string());
+        }
+        return Self::Request(value.to_string());
+    }
+}
+
+impl Transport for HttpTransport {
+    fn post<'a>(&'a self, url: &'a str, body: String, timeout: Duration) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(url)
+                .header("ContentType", "text/xml")
+                .timeout(timeout)
+                .body(body)
+                .send()
+                .await?;
+            Ok(response.text().await?)
+        })
+    }
+
+    fn get<'a>(&'a self, url: &'a str, timeout: Duration) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let response = self.client.get(url).timeout(timeout).send().await?;
+            Ok(response.text().await?)
+        })
+    }
+}
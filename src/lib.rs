@@ -0,0 +1,5 @@
+pub mod command;
+pub mod envoy;
+pub mod mock;
+pub mod session;
+pub mod ui;
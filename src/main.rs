@@ -1,9 +1,9 @@
-mod command;
-mod envoy;
-mod ui;
-
+use attpc_envoy::session::run_log::{RunLog, RunLogLayer};
+use attpc_envoy::ui::app::EnvoyApp;
+use attpc_envoy::ui::log_console::{LogBuffer, LogBufferLayer, LOG_CAPACITY};
 use tokio::runtime::Builder;
-use ui::app::EnvoyApp;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 
 fn main() {
     //Create the async runtime
@@ -14,29 +14,38 @@ fn main() {
         .build()
         .expect("Could not startup async runtime!");
 
-    //Create our logging/tracing system.
-    let subscriber = tracing_subscriber::fmt()
-        .compact()
-        .with_file(true)
-        .with_line_number(true)
-        .with_thread_ids(true)
-        .with_target(false)
-        .finish();
+    //Create our logging/tracing system. Events go to stdout, to the log console of the UI, and to the log of the current run.
+    let log_buffer = LogBuffer::new(LOG_CAPACITY);
+    let run_log = RunLog::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .compact()
+                .with_file(true)
+                .with_line_number(true)
+                .with_thread_ids(true)
+                .with_target(false),
+        )
+        .with(LogBufferLayer::new(&log_buffer))
+        .with(RunLogLayer::new(&run_log));
     tracing::subscriber::set_global_default(subscriber)
         .expect("Could not initialize the tracing system!");
 
     tracing::info!("Tracing initialized!");
 
     //Start our application
-    let mut native_options = eframe::NativeOptions::default();
-    native_options.viewport = eframe::egui::ViewportBuilder::default()
-        .with_title("AT-TPC Envoy")
-        .with_inner_size(eframe::epaint::vec2(1400.0, 1225.0));
-    native_options.follow_system_theme = false;
+    let native_options = eframe::NativeOptions {
+        viewport: eframe::egui::ViewportBuilder::default()
+            .with_title("AT-TPC Envoy")
+            .with_inner_size(eframe::epaint::vec2(1400.0, 1225.0)),
+        follow_system_theme: false,
+        ..Default::default()
+    };
     match eframe::run_native(
         "ATTPC Envoy",
         native_options,
-        Box::new(|cc| Box::new(EnvoyApp::new(cc, runtime))),
+        Box::new(|cc| Box::new(EnvoyApp::new(cc, runtime, log_buffer, run_log))),
     ) {
         Ok(()) => (),
         Err(e) => tracing::error!("Eframe error: {}", e),
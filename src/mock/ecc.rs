@@ -0,0 +1,282 @@
+use crate::envoy::ecc_operation::{ECCOperation, ECCStatus};
+use crate::envoy::soap::{parse_ecc_request, ECCRequest};
+use crate::envoy::topology::ModuleRole;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const GET_STATE_OP: &str = "GetState";
+const DEFAULT_LATENCY_MS: u64 = 500;
+
+const SOAP_REPLY_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://schemas.xmlsoap.org/soap/envelope/" xmlns:SOAP-ENC="http://schemas.xmlsoap.org/soap/encoding/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:ns="urn:ecc"><SOAP-ENV:Body>"#;
+const SOAP_REPLY_FOOTER: &str = r#"</SOAP-ENV:Body></SOAP-ENV:Envelope>"#;
+
+/// # MockECCOptions
+/// Behavior of a mock ECC server. Latencies and injected error codes are keyed by operation name
+/// (Describe, Prepare, ...). Operations without a latency take DEFAULT_LATENCY_MS.
+#[derive(Debug, Clone, Default)]
+pub struct MockECCOptions {
+    pub latency: HashMap<String, Duration>,
+    pub errors: HashMap<String, i32>,
+    pub role: Option<ModuleRole>,
+}
+
+impl MockECCOptions {
+    fn get_latency(&self, operation: &str) -> Duration {
+        match self.latency.get(operation) {
+            Some(latency) => *latency,
+            None => Duration::from_millis(DEFAULT_LATENCY_MS),
+        }
+    }
+}
+
+/// The state of the GET system behind the mock server
+#[derive(Debug)]
+struct MockECCState {
+    status: ECCStatus,
+    in_flight: Option<ECCOperation>,
+}
+
+/// What the mock server has to say about a request
+enum MockReply {
+    Status(i32, String, i32),
+    Operation(i32, String, String),
+    Fault(String),
+}
+
+/// # MockECCServer
+/// A stand-in for the ECC server of a single CoBo or MuTaNT. It speaks the same SOAP dialect as
+/// the real server and follows the GET state machine (Idle -> Described -> Prepared -> Ready -> Running),
+/// reporting state codes in the form ECCStatus::from(i32) expects. Transition bodies are checked
+/// to be well formed, and malformed requests are answered with a SOAP fault.
+#[derive(Debug, Clone)]
+pub struct MockECCServer {
+    id: i32,
+    address: SocketAddr,
+    options: MockECCOptions,
+    state: Arc<Mutex<MockECCState>>,
+}
+
+impl MockECCServer {
+    pub fn new(id: i32, address: SocketAddr, options: MockECCOptions) -> Self {
+        Self {
+            id,
+            address,
+            options,
+            state: Arc::new(Mutex::new(MockECCState {
+                status: ECCStatus::Idle,
+                in_flight: None,
+            })),
+        }
+    }
+
+    /// Serve SOAP requests forever
+    pub async fn serve(self) -> Result<(), hyper::Error> {
+        let address = self.address;
+        let server = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { server.handle(req).await }
+                }))
+            }
+        });
+        tracing::info!("Mock ECC server listening on {}", address);
+        Server::try_bind(&address)?.serve(make_service).await
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if req.method() != Method::POST {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Body::empty())
+                .expect("Could not build response"));
+        }
+
+        let reply = match hyper::body::to_bytes(req.into_body()).await {
+            Ok(bytes) => match String::from_utf8(bytes.to_vec()) {
+                Ok(text) => self.process(&text).await,
+                Err(_) => MockReply::Fault(String::from("Request body is not UTF-8")),
+            },
+            Err(e) => MockReply::Fault(format!("Could not read request body: {e}")),
+        };
+
+        let (status, body) = match reply {
+            MockReply::Status(code, message, state) => (
+                StatusCode::OK,
+                format!(
+                    "{SOAP_REPLY_HEADER}<ns:GetStateResponse><ErrorCode>{code}</ErrorCode><ErrorMessage>{}</ErrorMessage><State>{state}</State><Transition>0</Transition></ns:GetStateResponse>{SOAP_REPLY_FOOTER}",
+                    escape(&message)
+                ),
+            ),
+            MockReply::Operation(code, message, text) => (
+                StatusCode::OK,
+                format!(
+                    "{SOAP_REPLY_HEADER}<ns:EccResponse><ErrorCode>{code}</ErrorCode><ErrorMessage>{}</ErrorMessage><Text>{}</Text></ns:EccResponse>{SOAP_REPLY_FOOTER}",
+                    escape(&message),
+                    escape(&text)
+                ),
+            ),
+            MockReply::Fault(message) => {
+                tracing::warn!("Mock ECC {} replying with fault: {}", self.id, message);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!(
+                        "{SOAP_REPLY_HEADER}<SOAP-ENV:Fault><faultcode>SOAP-ENV:Client</faultcode><faultstring>{}</faultstring></SOAP-ENV:Fault>{SOAP_REPLY_FOOTER}",
+                        escape(&message)
+                    ),
+                )
+            }
+        };
+
+        Ok(Response::builder()
+            .status(status)
+            .header("Content-Type", "text/xml; charset=utf-8")
+            .body(Body::from(body))
+            .expect("Could not build response"))
+    }
+
+    async fn process(&self, text: &str) -> MockReply {
+        let (name, request) = match parse_ecc_request(text) {
+            Ok(parsed) => parsed,
+            Err(e) => return MockReply::Fault(format!("Could not parse request: {e}")),
+        };
+
+        if name == GET_STATE_OP {
+            let state = self.state.lock().await;
+            return MockReply::Status(0, String::new(), state.status.clone().into());
+        }
+
+        let operation = match ECCOperation::try_from(name.clone()) {
+            Ok(ECCOperation::Invalid) | Err(_) => {
+                return MockReply::Fault(format!(
+                    "Method '{name}' not implemented: method name or namespace not recognized"
+                ))
+            }
+            Ok(op) => op,
+        };
+
+        if let Err(message) = self.check_request(&request) {
+            return MockReply::Fault(format!("Malformed {name} request: {message}"));
+        }
+
+        self.transition(operation).await
+    }
+
+    /// Run a transition: go Busy, wait out the latency, then land in the next state (or report the injected error)
+    async fn transition(&self, operation: ECCOperation) -> MockReply {
+        let name = operation.to_string();
+        let (previous, next) = {
+            let mut state = self.state.lock().await;
+            if let Some(in_flight) = &state.in_flight {
+                return MockReply::Operation(
+                    1,
+                    format!("Cannot {name}, {in_flight} is already in progress"),
+                    String::new(),
+                );
+            }
+            let next = match operation.get_next_status(&state.status) {
+                Some(next) => next,
+                None => {
+                    return MockReply::Operation(
+                        1,
+                        format!("Cannot {name} from state {}", state.status),
+                        String::new(),
+                    )
+                }
+            };
+            let previous = state.status.clone();
+            state.status = ECCStatus::Busy;
+            state.in_flight = Some(operation.clone());
+            (previous, next)
+        };
+
+        tracing::info!("Mock ECC {} starting {}", self.id, name);
+        tokio::time::sleep(self.options.get_latency(&name)).await;
+
+        let mut state = self.state.lock().await;
+        state.in_flight = None;
+        if let Some(code) = self.options.errors.get(&name) {
+            state.status = previous;
+            tracing::info!("Mock ECC {} failing {} with code {}", self.id, name, code);
+            return MockReply::Operation(
+                *code,
+                format!("Injected failure of {name}"),
+                String::new(),
+            );
+        }
+        state.status = next;
+        tracing::info!("Mock ECC {} is now {}", self.id, state.status);
+        MockReply::Operation(0, String::new(), format!("{name} done"))
+    }
+
+    /// Check that the ConfigId and DataLinkSet built by ECCConfig are well formed
+    fn check_request(&self, request: &ECCRequest) -> Result<(), String> {
+        let config = match &request.config {
+            Some(c) => &c.config_id,
+            None => return Err(String::from("missing configID")),
+        };
+        for kind in ["describe", "prepare", "configure"] {
+            let sub_configs: Vec<_> = config
+                .sub_configs
+                .iter()
+                .filter(|s| s.kind == kind)
+                .collect();
+            if sub_configs.len() != 1 {
+                return Err(format!(
+                    "expected one SubConfigId of type {kind}, found {}",
+                    sub_configs.len()
+                ));
+            }
+            if sub_configs[0].name.trim().is_empty() {
+                return Err(format!("SubConfigId of type {kind} is empty"));
+            }
+        }
+        if config.sub_configs.len() != 3 {
+            return Err(format!(
+                "expected 3 SubConfigIds, found {}",
+                config.sub_configs.len()
+            ));
+        }
+
+        let links = match &request.data_links {
+            Some(t) => &t.link_set.links,
+            None => return Err(String::from("missing DataLinkSet table")),
+        };
+        if links.is_empty() {
+            return Err(String::from("DataLinkSet has no DataLink"));
+        }
+        for link in links {
+            let expected_sender = match self.options.role {
+                Some(ModuleRole::Mutant) => Some(String::from("Mutant[master]")),
+                Some(ModuleRole::CoBo) => Some(format!("CoBo[{}]", self.id)),
+                None => None,
+            };
+            match expected_sender {
+                Some(sender) if sender != link.sender.id => {
+                    return Err(format!(
+                        "DataSender id {} does not match {}",
+                        link.sender.id, sender
+                    ))
+                }
+                _ => (),
+            }
+            if link.router.ip_address.is_empty() || link.router.name.is_empty() {
+                return Err(String::from("DataRouter is missing an ipAddress or name"));
+            }
+            if link.router.port == 0 || link.router.protocol.is_empty() {
+                return Err(String::from("DataRouter is missing a port or type"));
+            }
+        }
+        Ok(())
+    }
+}
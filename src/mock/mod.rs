@@ -0,0 +1,2 @@
+pub mod ecc;
+pub mod surveyor;
@@ -0,0 +1,349 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+const CONTROL_PREFIX: &str = "/control/";
+const DF_HEADER: &str =
+    "Filesystem    512-blocks      Used Available Capacity iused      ifree %iused  Mounted on";
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// # MockSurveyorOptions
+/// Behavior of a mock Surveyor. Data is written at `rate` bytes per second into graw files which are rolled
+/// over once they reach `file_size`. The disk holds `disk_size` bytes, `disk_used` of which are taken by other data.
+/// If a `directory` is given the graw files are real (sparse) files in that directory, so that they can be moved
+/// by the move_graw.sh script; otherwise they only exist in memory.
+#[derive(Debug, Clone)]
+pub struct MockSurveyorOptions {
+    pub rate: u64,
+    pub file_size: u64,
+    pub disk_size: u64,
+    pub disk_used: u64,
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for MockSurveyorOptions {
+    fn default() -> Self {
+        Self {
+            rate: 10_000_000,
+            file_size: 1_073_741_824,
+            disk_size: 1_000_000_000_000,
+            disk_used: 0,
+            directory: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockGrawFile {
+    name: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// The state of the data router disk behind the mock Surveyor
+#[derive(Debug)]
+struct MockSurveyorState {
+    online: bool,
+    writing: bool,
+    rate: u64,
+    files: Vec<MockGrawFile>,
+    file_counter: u64,
+    last_update: Instant,
+}
+
+/// # MockSurveyorServer
+/// A stand-in for the Surveyor of a single data router. GET requests are answered with the line oriented
+/// surveyor.html format read by SurveyorEnvoy: the state, the data location, the df header and entry of the disk,
+/// and an `ls -l` style line for each graw file. The simulation is driven by POST requests to /control/:
+///
+/// - offline / online: the router reports state 0 / 1
+/// - pause / resume: stop / restart writing data
+/// - move: remove all graw files, as if they were moved to a run directory
+/// - rate?mbps=X: set the data rate in MB/s
+#[derive(Debug, Clone)]
+pub struct MockSurveyorServer {
+    id: i32,
+    address: SocketAddr,
+    location: String,
+    options: MockSurveyorOptions,
+    state: Arc<Mutex<MockSurveyorState>>,
+}
+
+impl MockSurveyorServer {
+    pub fn new(
+        id: i32,
+        address: SocketAddr,
+        options: MockSurveyorOptions,
+    ) -> std::io::Result<Self> {
+        let location = match &options.directory {
+            Some(dir) => {
+                let path = dir.join(format!("cobo{id}"));
+                std::fs::create_dir_all(&path)?;
+                path.canonicalize()?.to_string_lossy().to_string()
+            }
+            None => format!("/Volumes/mock/cobo{id}"),
+        };
+        Ok(Self {
+            id,
+            address,
+            location,
+            state: Arc::new(Mutex::new(MockSurveyorState {
+                online: true,
+                writing: true,
+                rate: options.rate,
+                files: vec![],
+                file_counter: 0,
+                last_update: Instant::now(),
+            })),
+            options,
+        })
+    }
+
+    /// Serve Surveyor requests forever
+    pub async fn serve(self) -> Result<(), hyper::Error> {
+        let address = self.address;
+        let server = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { server.handle(req).await }
+                }))
+            }
+        });
+        tracing::info!("Mock Surveyor listening on {}", address);
+        Server::try_bind(&address)?.serve(make_service).await
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let path = req.uri().path().to_string();
+        let (status, body) = match (req.method(), path.strip_prefix(CONTROL_PREFIX)) {
+            (&Method::POST, Some(action)) => self.control(action, req.uri().query()).await,
+            (&Method::GET, None) => (StatusCode::OK, self.render().await),
+            _ => (StatusCode::METHOD_NOT_ALLOWED, String::new()),
+        };
+        Ok(Response::builder()
+            .status(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(Body::from(body))
+            .expect("Could not build response"))
+    }
+
+    async fn control(&self, action: &str, query: Option<&str>) -> (StatusCode, String) {
+        let mut state = self.state.lock().await;
+        self.advance(&mut state);
+        match action {
+            "offline" => state.online = false,
+            "online" => state.online = true,
+            "pause" => state.writing = false,
+            "resume" => state.writing = true,
+            "move" => {
+                if self.options.directory.is_some() {
+                    for file in state.files.iter() {
+                        let path = PathBuf::from(&self.location).join(&file.name);
+                        if let Err(e) = std::fs::remove_file(&path) {
+                            tracing::warn!(
+                                "Mock Surveyor {} could not remove {}: {}",
+                                self.id,
+                                path.display(),
+                                e
+                            );
+                        }
+                    }
+                }
+                state.files.clear();
+            }
+            "rate" => {
+                let mbps = query
+                    .and_then(|q| q.split('&').find_map(|kv| kv.strip_prefix("mbps=")))
+                    .and_then(|v| v.parse::<f64>().ok());
+                match mbps {
+                    Some(mbps) if mbps >= 0.0 => state.rate = (mbps * 1.0e6) as u64,
+                    _ => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            String::from("Expected rate?mbps=X\n"),
+                        )
+                    }
+                }
+            }
+            _ => return (StatusCode::NOT_FOUND, format!("Unknown control {action}\n")),
+        }
+        tracing::info!("Mock Surveyor {} control: {}", self.id, action);
+        (StatusCode::OK, format!("{action} ok\n"))
+    }
+
+    /// Write the data accumulated since the last update, rolling over files and stopping when the disk is full
+    fn advance(&self, state: &mut MockSurveyorState) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_update);
+        state.last_update = now;
+        self.sync_directory(state);
+        if !state.online || !state.writing {
+            return;
+        }
+
+        let used = self.options.disk_used + state.files.iter().map(|f| f.size).sum::<u64>();
+        let free = self.options.disk_size.saturating_sub(used);
+        let mut remaining = ((state.rate as f64) * elapsed.as_secs_f64()) as u64;
+        remaining = remaining.min(free);
+        while remaining > 0 {
+            let needs_new = match state.files.last() {
+                Some(f) => f.size >= self.options.file_size,
+                None => true,
+            };
+            if needs_new {
+                let mut name = String::new();
+                while name.is_empty() || state.files.iter().any(|f| f.name == name) {
+                    name = format!("CoBo_AsAd{}_{:04}.graw", self.id, state.file_counter);
+                    state.file_counter += 1;
+                }
+                state.files.push(MockGrawFile {
+                    name,
+                    size: 0,
+                    modified: SystemTime::now(),
+                });
+            }
+            let file = state
+                .files
+                .last_mut()
+                .expect("There is always a file to write to");
+            let chunk = remaining.min(self.options.file_size - file.size);
+            file.size += chunk;
+            file.modified = SystemTime::now();
+            remaining -= chunk;
+        }
+        self.write_directory(state);
+    }
+
+    /// In directory mode the directory is the truth: pick up files removed (moved) or resized by someone else
+    fn sync_directory(&self, state: &mut MockSurveyorState) {
+        if self.options.directory.is_none() {
+            return;
+        }
+        let entries = match std::fs::read_dir(&self.location) {
+            Ok(e) => e,
+            Err(e) => {
+                tracing::warn!(
+                    "Mock Surveyor {} could not read {}: {}",
+                    self.id,
+                    self.location,
+                    e
+                );
+                return;
+            }
+        };
+        let mut files = vec![];
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.metadata() {
+                Ok(meta) if meta.is_file() && name.ends_with(".graw") => files.push(MockGrawFile {
+                    name,
+                    size: meta.len(),
+                    modified: meta.modified().unwrap_or(UNIX_EPOCH),
+                }),
+                _ => (),
+            }
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        state.files = files;
+    }
+
+    fn write_directory(&self, state: &MockSurveyorState) {
+        if self.options.directory.is_none() {
+            return;
+        }
+        for file in state.files.iter() {
+            let path = PathBuf::from(&self.location).join(&file.name);
+            let result = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_len(file.size));
+            if let Err(e) = result {
+                tracing::warn!(
+                    "Mock Surveyor {} could not write {}: {}",
+                    self.id,
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    async fn render(&self) -> String {
+        let mut state = self.state.lock().await;
+        self.advance(&mut state);
+        if !state.online {
+            return String::from("0\n");
+        }
+
+        let graw_bytes: u64 = state.files.iter().map(|f| f.size).sum();
+        let used = (self.options.disk_used + graw_bytes).min(self.options.disk_size);
+        let total_blocks = self.options.disk_size / 512;
+        let used_blocks = used / 512;
+        let capacity = if self.options.disk_size > 0 {
+            ((used as f64) / (self.options.disk_size as f64) * 100.0).round() as u64
+        } else {
+            100
+        };
+
+        let mut lines = vec![
+            String::from("1"),
+            self.location.clone(),
+            String::from(DF_HEADER),
+            format!(
+                "/dev/disk{}s1  {total_blocks} {used_blocks} {} {capacity}% 0 0 0% /Volumes/mock",
+                self.id + 2,
+                total_blocks - used_blocks
+            ),
+        ];
+        for file in state.files.iter() {
+            lines.push(format!(
+                "-rw-r--r--  1 attpc  staff  {} {} {}",
+                file.size,
+                format_ls_time(file.modified),
+                file.name
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Format a time the way `ls -l` does for recent files (e.g. Oct 18 08:31), in UTC
+fn format_ls_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let days = (secs / 86400) as i64;
+    let hour = (secs % 86400) / 3600;
+    let minute = (secs % 3600) / 60;
+
+    //Convert days since the epoch to a civil date (H. Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+    format!(
+        "{} {:>2} {:02}:{:02}",
+        MONTHS[(month - 1) as usize],
+        day,
+        hour,
+        minute
+    )
+}
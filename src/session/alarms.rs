@@ -0,0 +1,472 @@
+use super::error::AlarmError;
+use super::status_manager::StatusManager;
+use crate::envoy::connection_status::ConnectionStatus;
+use crate::envoy::ecc_operation::ECCStatus;
+use crate::envoy::supervisor::EnvoyKind;
+use crate::envoy::surveyor_state::SurveyorState;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The rules used when the config does not name a rules file
+const DEFAULT_RULES: &str = include_str!("../../alarms/default.yml");
+
+/// Number of finished alarms kept in the history
+const ALARM_HISTORY_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl std::fmt::Display for AlarmSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Info => write!(f, "Info"),
+            Self::Warning => write!(f, "Warning"),
+            Self::Critical => write!(f, "Critical"),
+        }
+    }
+}
+
+/// What an alarm rule checks, for every unmasked module
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmCondition {
+    /// The ECC server is not Running during a run
+    EccNotRunning,
+    /// The Surveyor is offline or cannot be reached
+    SurveyorOffline,
+    /// The data rate (1 min average) is below this many MB/s during a run
+    RateBelow { mbps: f64 },
+    /// The data router disk is more than this percent used
+    DiskAbove { percent: f64 },
+    /// The last ECC operation failed
+    OperationError,
+}
+
+impl AlarmCondition {
+    /// The envoy whose data the condition is checked against. Alarms are logged against it, so that they are shown
+    /// on the right row of the status tables.
+    pub fn get_envoy_kind(&self) -> EnvoyKind {
+        match self {
+            Self::EccNotRunning => EnvoyKind::ECCStatus,
+            Self::OperationError => EnvoyKind::ECCTransition,
+            Self::SurveyorOffline | Self::RateBelow { .. } | Self::DiskAbove { .. } => {
+                EnvoyKind::Surveyor
+            }
+        }
+    }
+}
+
+/// # AlarmRule
+/// A condition, and how an alarm for it is raised and cleared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmRule {
+    pub name: String,
+    pub severity: AlarmSeverity,
+    /// The condition must hold this long before the alarm is raised
+    #[serde(default)]
+    pub hold_off_secs: u64,
+    /// A latched alarm stays up once the condition clears, until it is acknowledged
+    #[serde(default)]
+    pub latch: bool,
+    /// Written as the condition name, or as a map of the name to its threshold (i.e. `rate_below: {mbps: 0.1}`)
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub condition: AlarmCondition,
+}
+
+/// # AlarmRules
+/// The rules file, read from YAML
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmRules {
+    pub rules: Vec<AlarmRule>,
+}
+
+impl AlarmRules {
+    pub fn read(path: &Path) -> Result<Self, AlarmError> {
+        let yaml_str = std::fs::read_to_string(path)?;
+        return Self::parse(&yaml_str);
+    }
+
+    /// Parse rules from YAML. Rule names must be unique.
+    pub fn parse(yaml_str: &str) -> Result<Self, AlarmError> {
+        let rules = serde_yaml::from_str::<AlarmRules>(yaml_str)?;
+        for (idx, rule) in rules.rules.iter().enumerate() {
+            if rules.rules[..idx].iter().any(|r| r.name == rule.name) {
+                return Err(AlarmError::DuplicateRule(rule.name.clone()));
+            }
+        }
+        return Ok(rules);
+    }
+}
+
+impl Default for AlarmRules {
+    fn default() -> Self {
+        Self::parse(DEFAULT_RULES).expect("The default alarm rules are valid")
+    }
+}
+
+/// # Alarm
+/// An alarm raised by a rule for a module. Active until it is cleared, after which it is kept in the history.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alarm {
+    pub id: u64,
+    pub rule: String,
+    pub module: i32,
+    /// The envoy whose data raised the alarm
+    pub envoy: EnvoyKind,
+    pub severity: AlarmSeverity,
+    pub message: String,
+    /// The run in progress when the alarm was raised
+    pub run_number: Option<i32>,
+    pub raised_at: SystemTime,
+    pub acknowledged_at: Option<SystemTime>,
+    pub cleared_at: Option<SystemTime>,
+    /// Whether the condition still holds
+    pub condition_present: bool,
+}
+
+/// A change in an alarm, reported to the session
+#[derive(Debug, Clone)]
+pub enum AlarmChange {
+    Raised(Alarm),
+    Acknowledged(Alarm),
+    Cleared(Alarm),
+}
+
+/// What the engine needs to know besides the status
+#[derive(Debug, Clone)]
+pub struct AlarmContext {
+    /// A run is in progress and no sequence is changing the state of the system
+    pub in_run: bool,
+    pub run_number: i32,
+}
+
+/// # AlarmEngine
+/// Evaluates the alarm rules against the StatusManager every time the session is polled. A condition which holds for
+/// the hold-off time raises an alarm. An alarm is cleared once its condition no longer holds, and, if the rule latches,
+/// once it has been acknowledged. Acknowledging an alarm whose condition still holds keeps it up but marks it as seen.
+///
+/// Raising, acknowledging, and clearing are logged against the module (so they end up in the run log), and finished
+/// alarms are kept in a history with the run they were raised in.
+#[derive(Debug)]
+pub struct AlarmEngine {
+    rules: Vec<AlarmRule>,
+    pending: HashMap<(usize, i32), Instant>,
+    active: Vec<Alarm>,
+    history: VecDeque<Alarm>,
+    next_id: u64,
+}
+
+impl AlarmEngine {
+    pub fn new(rules: AlarmRules) -> Self {
+        return Self {
+            rules: rules.rules,
+            pending: HashMap::new(),
+            active: vec![],
+            history: VecDeque::new(),
+            next_id: 0,
+        };
+    }
+
+    /// Replace the rules. Active alarms of rules which are kept stay up until they are cleared as usual; those of rules
+    /// which are gone can never be evaluated again, so they are cleared now. Returns what changed.
+    pub fn set_rules(&mut self, rules: AlarmRules) -> Vec<AlarmChange> {
+        self.rules = rules.rules;
+        self.pending.clear();
+        let mut changes = vec![];
+        let mut idx = 0;
+        while idx < self.active.len() {
+            if self.rules.iter().any(|r| r.name == self.active[idx].rule) {
+                idx += 1;
+                continue;
+            }
+            let alarm = self.active.remove(idx);
+            changes.push(Self::finish(&mut self.history, alarm));
+        }
+        return changes;
+    }
+
+    pub fn get_rules(&self) -> &[AlarmRule] {
+        &self.rules
+    }
+
+    /// Check every rule against the status, and return what changed
+    pub fn evaluate(
+        &mut self,
+        now: Instant,
+        status: &StatusManager,
+        context: &AlarmContext,
+    ) -> Vec<AlarmChange> {
+        let mut changes = vec![];
+        for (rule_idx, rule) in self.rules.iter().enumerate() {
+            let found = check_condition(&rule.condition, status, context);
+            //Conditions which no longer hold
+            self.pending
+                .retain(|(r, id), _| *r != rule_idx || found.iter().any(|(f, _)| f == id));
+            let mut idx = 0;
+            while idx < self.active.len() {
+                let alarm = &mut self.active[idx];
+                if alarm.rule != rule.name || found.iter().any(|(id, _)| *id == alarm.module) {
+                    idx += 1;
+                    continue;
+                }
+                alarm.condition_present = false;
+                if rule.latch && alarm.acknowledged_at.is_none() {
+                    idx += 1;
+                    continue;
+                }
+                let alarm = self.active.remove(idx);
+                changes.push(Self::finish(&mut self.history, alarm));
+            }
+
+            //Conditions which hold
+            for (module, message) in found {
+                if let Some(alarm) = self
+                    .active
+                    .iter_mut()
+                    .find(|a| a.rule == rule.name && a.module == module)
+                {
+                    alarm.condition_present = true;
+                    alarm.message = message;
+                    continue;
+                }
+                let since = *self.pending.entry((rule_idx, module)).or_insert(now);
+                if now.duration_since(since) < Duration::from_secs(rule.hold_off_secs) {
+                    continue;
+                }
+                self.pending.remove(&(rule_idx, module));
+                let alarm = Alarm {
+                    id: self.next_id,
+                    rule: rule.name.clone(),
+                    module,
+                    envoy: rule.condition.get_envoy_kind(),
+                    severity: rule.severity,
+                    message,
+                    run_number: match context.in_run {
+                        true => Some(context.run_number),
+                        false => None,
+                    },
+                    raised_at: SystemTime::now(),
+                    acknowledged_at: None,
+                    cleared_at: None,
+                    condition_present: true,
+                };
+                self.next_id += 1;
+                log_raised(&alarm);
+                self.active.push(alarm.clone());
+                changes.push(AlarmChange::Raised(alarm));
+            }
+        }
+        return changes;
+    }
+
+    /// Acknowledge an active alarm. If its condition has cleared it is cleared too.
+    pub fn acknowledge(&mut self, id: u64) -> Result<Vec<AlarmChange>, AlarmError> {
+        let idx = self
+            .active
+            .iter()
+            .position(|a| a.id == id)
+            .ok_or(AlarmError::NoAlarm(id))?;
+        let alarm = &mut self.active[idx];
+        if alarm.acknowledged_at.is_some() {
+            return Ok(vec![]);
+        }
+        alarm.acknowledged_at = Some(SystemTime::now());
+        tracing::info!(
+            module = alarm.module,
+            envoy = %alarm.envoy,
+            alarm = alarm.rule,
+            "Alarm {} acknowledged: {}",
+            alarm.id,
+            alarm.message
+        );
+        let mut changes = vec![AlarmChange::Acknowledged(alarm.clone())];
+        if !alarm.condition_present {
+            let alarm = self.active.remove(idx);
+            changes.push(Self::finish(&mut self.history, alarm));
+        }
+        return Ok(changes);
+    }
+
+    /// Acknowledge every active alarm
+    pub fn acknowledge_all(&mut self) -> Vec<AlarmChange> {
+        let ids: Vec<u64> = self.active.iter().map(|a| a.id).collect();
+        let mut changes = vec![];
+        for id in ids {
+            if let Ok(mut c) = self.acknowledge(id) {
+                changes.append(&mut c);
+            }
+        }
+        return changes;
+    }
+
+    fn finish(history: &mut VecDeque<Alarm>, mut alarm: Alarm) -> AlarmChange {
+        alarm.cleared_at = Some(SystemTime::now());
+        alarm.condition_present = false;
+        tracing::info!(
+            module = alarm.module,
+            envoy = %alarm.envoy,
+            alarm = alarm.rule,
+            "Alarm {} cleared: {}",
+            alarm.id,
+            alarm.message
+        );
+        if history.len() == ALARM_HISTORY_CAPACITY {
+            history.pop_front();
+        }
+        history.push_back(alarm.clone());
+        return AlarmChange::Cleared(alarm);
+    }
+
+    /// The active alarms, most severe first and then oldest first
+    pub fn get_active(&self) -> Vec<&Alarm> {
+        let mut active: Vec<&Alarm> = self.active.iter().collect();
+        active.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.id.cmp(&b.id)));
+        return active;
+    }
+
+    /// The finished alarms, oldest first
+    pub fn get_history(&self) -> &VecDeque<Alarm> {
+        &self.history
+    }
+}
+
+fn log_raised(alarm: &Alarm) {
+    match alarm.severity {
+        AlarmSeverity::Info => tracing::info!(
+            module = alarm.module,
+            envoy = %alarm.envoy,
+            alarm = alarm.rule,
+            "Alarm {} raised: {}",
+            alarm.id,
+            alarm.message
+        ),
+        AlarmSeverity::Warning => tracing::warn!(
+            module = alarm.module,
+            envoy = %alarm.envoy,
+            alarm = alarm.rule,
+            "Alarm {} raised: {}",
+            alarm.id,
+            alarm.message
+        ),
+        AlarmSeverity::Critical => tracing::error!(
+            module = alarm.module,
+            envoy = %alarm.envoy,
+            alarm = alarm.rule,
+            "Alarm {} raised: {}",
+            alarm.id,
+            alarm.message
+        ),
+    }
+}
+
+/// The unmasked modules for which a condition holds, with a description of each
+fn check_condition(
+    condition: &AlarmCondition,
+    status: &StatusManager,
+    context: &AlarmContext,
+) -> Vec<(i32, String)> {
+    let mut found = vec![];
+    match condition {
+        AlarmCondition::EccNotRunning => {
+            if !context.in_run {
+                return found;
+            }
+            for (id, response) in status.get_ecc_status_response().iter().enumerate() {
+                if status.is_masked(id) || response.connection == ConnectionStatus::NoContact {
+                    continue;
+                }
+                let state = ECCStatus::from(response.state);
+                if state != ECCStatus::Running {
+                    found.push((id as i32, format!("ECC {id} is {state} during the run")));
+                }
+            }
+        }
+        AlarmCondition::SurveyorOffline => {
+            let surveyors = status
+                .get_surveyor_ids()
+                .iter()
+                .zip(status.get_surveyor_status_response().iter());
+            for (id, response) in surveyors {
+                if status.is_masked(*id as usize) {
+                    continue;
+                }
+                match &response.connection {
+                    ConnectionStatus::NoContact => (),
+                    ConnectionStatus::Connected => {
+                        if response.state != 1 {
+                            found.push((
+                                *id,
+                                format!(
+                                    "Data router {id} is {}",
+                                    SurveyorState::from(response.state)
+                                ),
+                            ));
+                        }
+                    }
+                    connection => {
+                        found.push((*id, format!("Data router {id} is {connection}")));
+                    }
+                }
+            }
+        }
+        AlarmCondition::RateBelow { mbps } => {
+            if !context.in_run {
+                return found;
+            }
+            for sample in status.get_router_samples() {
+                if sample.rate < *mbps {
+                    found.push((
+                        sample.id,
+                        format!(
+                            "Data router {} is writing {:.3} MB/s, below {} MB/s",
+                            sample.id, sample.rate, mbps
+                        ),
+                    ));
+                }
+            }
+        }
+        AlarmCondition::DiskAbove { percent } => {
+            let surveyors = status
+                .get_surveyor_ids()
+                .iter()
+                .zip(status.get_surveyor_status_response().iter());
+            for (id, response) in surveyors {
+                if status.is_masked(*id as usize) {
+                    continue;
+                }
+                let used = match response
+                    .percent_used
+                    .strip_suffix('%')
+                    .and_then(|p| p.parse::<f64>().ok())
+                {
+                    Some(used) => used,
+                    None => continue,
+                };
+                if used > *percent {
+                    found.push((
+                        *id,
+                        format!("Data router {id} disk is {used}% used, above {percent}%"),
+                    ));
+                }
+            }
+        }
+        AlarmCondition::OperationError => {
+            for id in 0..status.get_ecc_status_response().len() {
+                if status.is_masked(id) {
+                    continue;
+                }
+                if let Some(error) = status.get_ecc_error(id) {
+                    found.push((id as i32, format!("ECC {id}: {error}")));
+                }
+            }
+        }
+    }
+    return found;
+}
@@ -0,0 +1,407 @@
+use super::alarms::Alarm;
+use super::config::Config;
+use super::error::{AlarmError, SessionError};
+use super::events::{EventPublisher, SessionEvent};
+use super::metrics::Metrics;
+use crate::envoy::connection_status::ConnectionStatus;
+use crate::envoy::surveyor_envoy::SurveyorResponse;
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+/// How long the server waits for the session to act on a command. The session is polled at least once a second.
+const API_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Period of the comments sent on an idle event stream, so that closed connections are noticed
+const EVENT_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// The actions which can be requested through the API. Each maps onto the Session method used by the
+/// matching button in the UI, so requests go through the same checks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiCommand {
+    StartRun,
+    StopRun,
+    AbortSequence,
+    ProgressSystem,
+    RegressSystem,
+    ProgressModule(usize),
+    RegressModule(usize),
+    AcknowledgeAlarm(u64),
+    AcknowledgeAllAlarms,
+}
+
+impl std::fmt::Display for ApiCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StartRun => write!(f, "Start Run"),
+            Self::StopRun => write!(f, "Stop Run"),
+            Self::AbortSequence => write!(f, "Abort"),
+            Self::ProgressSystem => write!(f, "Progress System"),
+            Self::RegressSystem => write!(f, "Regress System"),
+            Self::ProgressModule(id) => write!(f, "Progress Module {id}"),
+            Self::RegressModule(id) => write!(f, "Regress Module {id}"),
+            Self::AcknowledgeAlarm(id) => write!(f, "Acknowledge Alarm {id}"),
+            Self::AcknowledgeAllAlarms => write!(f, "Acknowledge All Alarms"),
+        }
+    }
+}
+
+/// A command from the API server, with the channel on which the session replies
+#[derive(Debug)]
+pub struct ApiRequest {
+    pub command: ApiCommand,
+    pub reply: oneshot::Sender<Result<String, SessionError>>,
+}
+
+/// Status of a module's ECC server as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiECCStatus {
+    pub id: i32,
+    pub role: String,
+    pub masked: bool,
+    pub state: String,
+    pub state_code: i32,
+    pub connection: ConnectionStatus,
+    pub last_error: Option<String>,
+    pub pending_operation: Option<String>,
+}
+
+/// Status of a module's Surveyor as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiSurveyorStatus {
+    pub id: i32,
+    pub masked: bool,
+    pub state: String,
+    pub response: SurveyorResponse,
+    /// Seconds until the disk is full, None if it is not filling or there is nothing to forecast from
+    pub time_to_full_secs: Option<u64>,
+    /// Ok, Warning, or Critical, None if there is nothing to forecast from
+    pub disk_alarm: Option<String>,
+    /// Ok, Stalled, or Low Rate during a run, None otherwise
+    pub data_flow: Option<String>,
+}
+
+/// State of the run as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiRunState {
+    pub connected: bool,
+    pub simulated: bool,
+    pub running: bool,
+    pub run_number: i32,
+    pub run_duration_secs: Option<u64>,
+    pub sequence_running: bool,
+    pub run_step: String,
+}
+
+/// The active alarms and the history of finished ones, oldest first
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApiAlarms {
+    pub active: Vec<Alarm>,
+    pub history: Vec<Alarm>,
+}
+
+impl ApiAlarms {
+    /// Only the alarms raised during a run
+    fn for_run(&self, run_number: i32) -> Self {
+        let in_run = |a: &&Alarm| a.run_number == Some(run_number);
+        Self {
+            active: self.active.iter().filter(in_run).cloned().collect(),
+            history: self.history.iter().filter(in_run).cloned().collect(),
+        }
+    }
+}
+
+/// # ApiState
+/// Everything the API can report, published by the session every time it is polled
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiState {
+    pub system_ecc: String,
+    pub system_surveyor: String,
+    pub ecc: Vec<ApiECCStatus>,
+    pub surveyors: Vec<ApiSurveyorStatus>,
+    pub run: ApiRunState,
+    pub alarms: ApiAlarms,
+    pub config: Config,
+    #[serde(skip)]
+    pub metrics: Metrics,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiReply {
+    ok: bool,
+    message: String,
+}
+
+/// # ApiServer
+/// An HTTP server on the session's tokio runtime which serves the state of the session as JSON, and forwards
+/// run control commands to the session:
+///
+/// - GET `/status`, `/run`, `/config`
+/// - GET `/alarms`, the active alarms and the alarm history, only those of run N with `?run=N`
+/// - GET `/events`, a Server-Sent Events stream of SessionEvents, beginning with a snapshot of the status
+/// - GET `/metrics`, Prometheus metrics
+/// - POST `/run/start`, `/run/stop`, `/run/abort`
+/// - POST `/system/progress`, `/system/regress`
+/// - POST `/modules/<id>/progress`, `/modules/<id>/regress`
+/// - POST `/alarms/<id>/acknowledge`, `/alarms/acknowledge` (all of them)
+///
+/// Commands are answered once the session has acted on them. Commands which are not allowed in the current
+/// state of the system are answered with 409 Conflict.
+#[derive(Debug)]
+pub struct ApiServer {
+    state: watch::Receiver<ApiState>,
+    requests: mpsc::Sender<ApiRequest>,
+    events: broadcast::Sender<SessionEvent>,
+}
+
+impl ApiServer {
+    pub fn new(
+        state: watch::Receiver<ApiState>,
+        requests: mpsc::Sender<ApiRequest>,
+        events: broadcast::Sender<SessionEvent>,
+    ) -> Self {
+        return Self {
+            state,
+            requests,
+            events,
+        };
+    }
+
+    /// Bind the server to the address. Must be called from within the tokio runtime.
+    pub fn bind(
+        self,
+        address: &SocketAddr,
+    ) -> Result<impl std::future::Future<Output = ()>, hyper::Error> {
+        let builder = Server::try_bind(address)?;
+        let server = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { server.handle(req).await }
+                }))
+            }
+        });
+        let address = *address;
+        let serve = builder.serve(make_service);
+        return Ok(async move {
+            tracing::info!("Control API listening on {}", address);
+            if let Err(e) = serve.await {
+                tracing::error!("Control API stopped: {}", e);
+            }
+        });
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let path: Vec<&str> = req
+            .uri()
+            .path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+        let (status, body) = match (req.method(), path.as_slice()) {
+            (&Method::GET, ["status"]) => Self::to_json(&self.get_status()),
+            (&Method::GET, ["events"]) => return Ok(self.stream_events()),
+            (&Method::GET, ["metrics"]) => {
+                let state = self.state.borrow();
+                return Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "text/plain; version=0.0.4")
+                    .body(Body::from(state.metrics.render(&state)))
+                    .expect("Could not build response"));
+            }
+            (&Method::GET, ["run"]) => Self::to_json(&self.state.borrow().run),
+            (&Method::GET, ["config"]) => Self::to_json(&self.state.borrow().config),
+            (&Method::GET, ["alarms"]) => match Self::run_query(req.uri().query()) {
+                Ok(Some(run)) => Self::to_json(&self.state.borrow().alarms.for_run(run)),
+                Ok(None) => Self::to_json(&self.state.borrow().alarms),
+                Err(()) => Self::reply(
+                    StatusCode::BAD_REQUEST,
+                    String::from("run must be a run number"),
+                ),
+            },
+            (&Method::POST, ["alarms", "acknowledge"]) => {
+                self.command(ApiCommand::AcknowledgeAllAlarms).await
+            }
+            (&Method::POST, ["alarms", id, "acknowledge"]) => match id.parse::<u64>() {
+                Ok(id) => self.command(ApiCommand::AcknowledgeAlarm(id)).await,
+                Err(_) => Self::reply(StatusCode::NOT_FOUND, String::from("No such endpoint")),
+            },
+            (&Method::POST, ["run", "start"]) => self.command(ApiCommand::StartRun).await,
+            (&Method::POST, ["run", "stop"]) => self.command(ApiCommand::StopRun).await,
+            (&Method::POST, ["run", "abort"]) => self.command(ApiCommand::AbortSequence).await,
+            (&Method::POST, ["system", "progress"]) => {
+                self.command(ApiCommand::ProgressSystem).await
+            }
+            (&Method::POST, ["system", "regress"]) => self.command(ApiCommand::RegressSystem).await,
+            (&Method::POST, ["modules", id, action]) => match (id.parse::<usize>(), *action) {
+                (Ok(id), "progress") => self.command(ApiCommand::ProgressModule(id)).await,
+                (Ok(id), "regress") => self.command(ApiCommand::RegressModule(id)).await,
+                _ => Self::reply(StatusCode::NOT_FOUND, String::from("No such endpoint")),
+            },
+            (&Method::GET, _) | (&Method::POST, _) => {
+                Self::reply(StatusCode::NOT_FOUND, String::from("No such endpoint"))
+            }
+            _ => Self::reply(
+                StatusCode::METHOD_NOT_ALLOWED,
+                String::from("Only GET and POST are supported"),
+            ),
+        };
+        Ok(Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .expect("Could not build response"))
+    }
+
+    /// The run asked for by a `run=N` query, if any
+    fn run_query(query: Option<&str>) -> Result<Option<i32>, ()> {
+        let query = match query {
+            Some(q) => q,
+            None => return Ok(None),
+        };
+        for pair in query.split('&') {
+            if let Some(run) = pair.strip_prefix("run=") {
+                return run.parse::<i32>().map(Some).map_err(|_| ());
+            }
+        }
+        return Ok(None);
+    }
+
+    fn get_status(&self) -> serde_json::Value {
+        let state = self.state.borrow();
+        serde_json::json!({
+            "system_ecc": state.system_ecc,
+            "system_surveyor": state.system_surveyor,
+            "ecc": state.ecc,
+            "surveyors": state.surveyors,
+        })
+    }
+
+    /// Start a Server-Sent Events stream. The stream is fed by its own task, which ends when the client goes away
+    /// or the API is stopped.
+    fn stream_events(&self) -> Response<Body> {
+        let (mut body_tx, body) = Body::channel();
+        let mut events = self.events.subscribe();
+        let snapshot = Self::to_json(&self.get_status()).1;
+        tokio::spawn(async move {
+            if body_tx
+                .send_data(Self::format_event("snapshot", &snapshot))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            let mut keepalive = tokio::time::interval(EVENT_KEEPALIVE);
+            loop {
+                let chunk = tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(e) => match serde_json::to_string(&e) {
+                            Ok(json) => Self::format_event(e.get_name(), &json),
+                            Err(e) => {
+                                tracing::error!("Could not serialize an event: {}", e);
+                                continue;
+                            }
+                        },
+                        Err(RecvError::Lagged(missed)) => {
+                            Self::format_event("lagged", &format!("{{\"missed\":{missed}}}"))
+                        }
+                        Err(RecvError::Closed) => return,
+                    },
+                    _ = keepalive.tick() => Bytes::from_static(b": keepalive\n\n"),
+                };
+                if body_tx.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .body(body)
+            .expect("Could not build response")
+    }
+
+    fn format_event(name: &str, data: &str) -> Bytes {
+        Bytes::from(format!("event: {name}\ndata: {data}\n\n"))
+    }
+
+    /// Hand a command to the session and wait for it to be acted on
+    async fn command(&self, command: ApiCommand) -> (StatusCode, String) {
+        tracing::info!("Control API requested {}", command);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request = ApiRequest {
+            command,
+            reply: reply_tx,
+        };
+        if self.requests.send(request).await.is_err() {
+            return Self::reply(
+                StatusCode::SERVICE_UNAVAILABLE,
+                String::from("The session is gone"),
+            );
+        }
+        match tokio::time::timeout(API_REPLY_TIMEOUT, reply_rx).await {
+            Ok(Ok(Ok(message))) => Self::reply(StatusCode::OK, message),
+            Ok(Ok(Err(e))) => Self::reply(Self::error_status(&e), e.to_string()),
+            Ok(Err(_)) | Err(_) => Self::reply(
+                StatusCode::SERVICE_UNAVAILABLE,
+                String::from("The session did not answer"),
+            ),
+        }
+    }
+
+    fn error_status(error: &SessionError) -> StatusCode {
+        match error {
+            SessionError::NoModule(_) => StatusCode::NOT_FOUND,
+            SessionError::AlarmError(AlarmError::NoAlarm(_)) => StatusCode::NOT_FOUND,
+            SessionError::NotConnected
+            | SessionError::Connected
+            | SessionError::SequenceRunning
+            | SessionError::NoSequenceRunning
+            | SessionError::InvalidState(_, _)
+            | SessionError::ModuleMasked(_)
+            | SessionError::SubmitError(_)
+            | SessionError::Replaying => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn reply(status: StatusCode, message: String) -> (StatusCode, String) {
+        let ok = status.is_success();
+        let (_, body) = Self::to_json(&ApiReply { ok, message });
+        return (status, body);
+    }
+
+    fn to_json<T: Serialize>(value: &T) -> (StatusCode, String) {
+        match serde_json::to_string(value) {
+            Ok(json) => (StatusCode::OK, json),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("{{\"ok\":false,\"message\":\"Could not serialize: {e}\"}}"),
+            ),
+        }
+    }
+}
+
+/// # ApiHandle
+/// The session's end of a running ApiServer
+#[derive(Debug)]
+pub struct ApiHandle {
+    pub address: SocketAddr,
+    pub state: watch::Sender<ApiState>,
+    /// Whether something in the session changed since the state was last published
+    pub is_state_stale: bool,
+    pub state_published_at: Instant,
+    pub requests: mpsc::Receiver<ApiRequest>,
+    pub events: EventPublisher,
+    pub metrics: Metrics,
+    pub task: tokio::task::JoinHandle<()>,
+}
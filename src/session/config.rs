@@ -0,0 +1,266 @@
+use super::data_monitor::DataMonitorOptions;
+use super::disk_forecast::DiskForecastOptions;
+use super::error::SessionError;
+use super::run_log::LogRetention;
+use crate::envoy::simulation::SimulationOptions;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default retention of the run logs
+const DEFAULT_LOG_MAX_AGE_DAYS: u64 = 90;
+const DEFAULT_LOG_MAX_TOTAL_MB: u64 = 2048;
+
+const HEADER_STR: &str = "Run,Duration(s),Note,Gas,Beam,Energy(MeV/U),Pressure(Torr),B-Field(T),V_THGEM(V),V_MM(V),V_Cathode(kV),E-Drift(V),E-Trans(V),Masked Modules\n";
+/// The last column of the table before the masked modules were recorded
+const OLD_HEADER_END: &str = "E-Trans(V)";
+
+/// # Config
+/// (De)Serializable application configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(skip)]
+    pub config_path: PathBuf,
+
+    pub experiment: String,
+    pub run_number: i32,
+    pub description: String,
+    pub pressure: f32,
+    pub v_thgem: f32,
+    pub v_mm: f32,
+    pub e_drift: f32,
+    pub v_cathode: f32,
+    pub e_trans: f32,
+    pub gas: String,
+    pub beam: String,
+    pub energy: f32,
+    pub magnetic_field: f32,
+
+    /// Detector topology file. If not given, the full AT-TPC is assumed.
+    #[serde(default)]
+    pub topology_path: Option<PathBuf>,
+
+    /// Alarm rules file. If not given, the default rules are used.
+    #[serde(default)]
+    pub alarm_rules_path: Option<PathBuf>,
+
+    /// Ids of modules which are masked out of the system (no envoys, no transitions)
+    #[serde(default)]
+    pub masked_modules: Vec<i32>,
+
+    /// When the current run was started (seconds since the Unix epoch), if a run is in progress.
+    /// Saved so that a run started by one session can be timed when it is stopped by another.
+    #[serde(default)]
+    pub run_started_at: Option<u64>,
+
+    /// Address for the HTTP control API. The API is only served if this is given.
+    #[serde(default)]
+    pub api_address: Option<SocketAddr>,
+
+    /// Run logs older than this are deleted when a new run log is opened
+    #[serde(default = "default_log_max_age_days")]
+    pub log_max_age_days: u64,
+
+    /// The run logs of the experiment are deleted, oldest first, to keep them under this size
+    #[serde(default = "default_log_max_total_mb")]
+    pub log_max_total_mb: u64,
+
+    /// Record every message from the embassy during a run, for replay
+    #[serde(default = "default_record_runs")]
+    pub record_runs: bool,
+
+    /// How the simulated DAQ behaves when connecting in simulation mode
+    #[serde(default)]
+    pub simulation: SimulationOptions,
+
+    /// When the data router disks count as full, the warning thresholds, and whether to stop the run before then
+    #[serde(default)]
+    pub disk_forecast: DiskForecastOptions,
+
+    /// When a data router counts as stalled or too slow during a run
+    #[serde(default)]
+    pub data_monitor: DataMonitorOptions,
+}
+
+fn default_log_max_age_days() -> u64 {
+    DEFAULT_LOG_MAX_AGE_DAYS
+}
+
+fn default_log_max_total_mb() -> u64 {
+    DEFAULT_LOG_MAX_TOTAL_MB
+}
+
+fn default_record_runs() -> bool {
+    true
+}
+
+impl Config {
+    pub fn new() -> Self {
+        return Config {
+            config_path: PathBuf::from("example.yml"),
+            experiment: String::from("Exp"),
+            run_number: 0,
+            description: String::from("Write here"),
+            pressure: 0.0,
+            v_thgem: 0.0,
+            v_mm: 0.0,
+            e_drift: 0.0,
+            v_cathode: 0.0,
+            e_trans: 0.0,
+            gas: String::from("H2"),
+            beam: String::from("16C"),
+            energy: 0.0,
+            magnetic_field: 0.0,
+            topology_path: None,
+            alarm_rules_path: None,
+            masked_modules: vec![],
+            run_started_at: None,
+            api_address: None,
+            log_max_age_days: DEFAULT_LOG_MAX_AGE_DAYS,
+            log_max_total_mb: DEFAULT_LOG_MAX_TOTAL_MB,
+            record_runs: true,
+            simulation: SimulationOptions::default(),
+            disk_forecast: DiskForecastOptions::default(),
+            data_monitor: DataMonitorOptions::default(),
+        };
+    }
+
+    /// Read a config from a YAML file
+    pub fn read(path: &Path) -> Result<Self, SessionError> {
+        let yaml_str = std::fs::read_to_string(path)?;
+        let mut config = serde_yaml::from_str::<Config>(&yaml_str)?;
+        config.config_path = path.to_path_buf();
+        return Ok(config);
+    }
+
+    /// Write the config to the YAML file at config_path
+    pub fn write(&self) -> Result<(), SessionError> {
+        let yaml_str = serde_yaml::to_string::<Config>(self)?;
+        let mut file = std::fs::File::create(&self.config_path)?;
+        file.write_all(yaml_str.as_bytes())?;
+        return Ok(());
+    }
+
+    /// Mark the run as started now
+    pub fn start_run_clock(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+        self.run_started_at = Some(now.as_secs());
+    }
+
+    /// How long the current run has been going, if a run is in progress
+    pub fn get_run_duration(&self) -> Option<Duration> {
+        let started = UNIX_EPOCH + Duration::from_secs(self.run_started_at?);
+        Some(
+            SystemTime::now()
+                .duration_since(started)
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+
+    /// How long run logs are kept and how much space they may take
+    pub fn get_log_retention(&self) -> LogRetention {
+        LogRetention {
+            max_age: Duration::from_secs(self.log_max_age_days * 24 * 3600),
+            max_total_bytes: self.log_max_total_mb * 1024 * 1024,
+        }
+    }
+
+    fn get_config_table(&self) -> PathBuf {
+        let table_dir = PathBuf::from("tables/");
+        if !table_dir.exists() {
+            match std::fs::create_dir(&table_dir) {
+                Ok(()) => (),
+                Err(e) => tracing::error!(
+                    "Could not create table directory due to: {}. The config table will not be saved!",
+                    e
+                ),
+            }
+        }
+
+        let table_path = table_dir.join(format!("{}.csv", self.experiment));
+        if !table_path.exists() {
+            if let Ok(mut file) = std::fs::File::create(&table_path) {
+                match file.write_all(HEADER_STR.as_bytes()) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        tracing::error!("Could not write header to config table: {}", e);
+                    }
+                }
+            }
+        } else {
+            Self::upgrade_config_table(&table_path);
+        }
+
+        return table_path;
+    }
+
+    /// Tables started before the masked modules were recorded are missing the last column. Add it to the header,
+    /// and leave it empty in the old rows, so that every row matches the header.
+    fn upgrade_config_table(table_path: &Path) {
+        let table = match std::fs::read_to_string(table_path) {
+            Ok(t) => t,
+            Err(e) => {
+                tracing::error!("Could not read config table: {}", e);
+                return;
+            }
+        };
+        let mut lines = table.lines();
+        match lines.next() {
+            Some(header) if header.trim_end().ends_with(OLD_HEADER_END) => (),
+            _ => return,
+        }
+        let mut upgraded = String::from(HEADER_STR);
+        for row in lines {
+            upgraded.push_str(row);
+            upgraded.push_str(",\n");
+        }
+        match std::fs::write(table_path, upgraded) {
+            Ok(()) => tracing::info!(
+                "Added the Masked Modules column to config table {}",
+                table_path.display()
+            ),
+            Err(e) => tracing::error!("Could not update the header of the config table: {}", e),
+        }
+    }
+
+    pub fn write_table(&self, ellapsed_time: std::time::Duration) {
+        let path = self.get_config_table();
+        if let Ok(mut file) = std::fs::OpenOptions::new().append(true).open(path) {
+            //Masked ids are space separated to keep the csv format
+            let masked = self
+                .masked_modules
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(" ");
+            let row = format!(
+                "{},{},{},{},{},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{}\n",
+                self.run_number,
+                ellapsed_time.as_secs(),
+                self.description,
+                self.gas,
+                self.beam,
+                self.energy,
+                self.pressure,
+                self.magnetic_field,
+                self.v_thgem,
+                self.v_mm,
+                self.v_cathode,
+                self.e_drift,
+                self.e_trans,
+                masked
+            );
+            match file.write_all(row.as_bytes()) {
+                Ok(_) => (),
+                Err(e) => {
+                    tracing::error!("Could not write row to config table: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
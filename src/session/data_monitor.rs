@@ -0,0 +1,206 @@
+use crate::envoy::supervisor::EnvoyKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Default thresholds of the data monitor
+const DEFAULT_STALL_SECS: u64 = 30;
+const DEFAULT_LOW_RATE_FRACTION: f64 = 0.5;
+const DEFAULT_MIN_MEDIAN_RATE: f64 = 0.1;
+
+/// # DataMonitorOptions
+/// When a data router counts as stalled or as writing too slowly compared to the others
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataMonitorOptions {
+    /// A router whose bytes used has not grown for this many seconds during a run is stalled
+    pub stall_secs: u64,
+    /// A router writing less than this fraction of the median rate of the routers is flagged
+    pub low_rate_fraction: f64,
+    /// Rates are only compared once the median is at least this many MB/s (i.e. not at the very start of a run)
+    pub min_median_rate: f64,
+}
+
+impl Default for DataMonitorOptions {
+    fn default() -> Self {
+        Self {
+            stall_secs: DEFAULT_STALL_SECS,
+            low_rate_fraction: DEFAULT_LOW_RATE_FRACTION,
+            min_median_rate: DEFAULT_MIN_MEDIAN_RATE,
+        }
+    }
+}
+
+/// What a data router looks like to the DataMonitor: its latest reading and its averaged rate (MB/s)
+#[derive(Debug, Clone)]
+pub struct RouterSample {
+    pub id: i32,
+    pub bytes_used: u64,
+    pub rate: f64,
+    pub measured_at: SystemTime,
+}
+
+/// How the data is flowing to a data router during a run
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataFlow {
+    Ok,
+    /// Nothing has been written for this long
+    Stalled(Duration),
+    /// The averaged rate (MB/s) is far below the median of the routers
+    LowRate {
+        rate: f64,
+        median: f64,
+    },
+}
+
+impl DataFlow {
+    fn is_same_kind(&self, other: &DataFlow) -> bool {
+        match (self, other) {
+            (Self::Ok, Self::Ok) => true,
+            (Self::Stalled(_), Self::Stalled(_)) => true,
+            (Self::LowRate { .. }, Self::LowRate { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for DataFlow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "Ok"),
+            Self::Stalled(time) => write!(f, "Stalled {}s", time.as_secs()),
+            Self::LowRate { .. } => write!(f, "Low Rate"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RouterFlow {
+    last_bytes: u64,
+    last_growth: SystemTime,
+    flow: DataFlow,
+}
+
+/// # DataMonitor
+/// Watches the data routers during a run. A router whose bytes used stops growing usually means a hung CoBo or a dead
+/// data link; a router writing far below the median rate of the routers usually means a bad AsAd. Changes are logged
+/// (and so end up in the run log) as warnings against the module's Surveyor.
+///
+/// Time is taken from when the Surveyors were read, so that a replay is judged the same as the run was. Everything
+/// is forgotten when the system is no longer running.
+#[derive(Debug, Default)]
+pub struct DataMonitor {
+    routers: HashMap<i32, RouterFlow>,
+    median: Option<f64>,
+}
+
+impl DataMonitor {
+    pub fn new() -> Self {
+        return Self {
+            routers: HashMap::new(),
+            median: None,
+        };
+    }
+
+    /// Judge the latest readings of the routers. Only the routers in the run (unmasked and online) should be given.
+    pub fn update(
+        &mut self,
+        is_running: bool,
+        samples: &[RouterSample],
+        options: &DataMonitorOptions,
+    ) {
+        if !is_running {
+            self.routers.clear();
+            self.median = None;
+            return;
+        }
+
+        let mut rates: Vec<f64> = samples.iter().map(|s| s.rate).collect();
+        self.median = median(&mut rates);
+
+        for sample in samples {
+            let router = self.routers.entry(sample.id).or_insert(RouterFlow {
+                last_bytes: sample.bytes_used,
+                last_growth: sample.measured_at,
+                flow: DataFlow::Ok,
+            });
+            if sample.bytes_used > router.last_bytes {
+                router.last_growth = sample.measured_at;
+            }
+            router.last_bytes = sample.bytes_used;
+
+            let stalled_for = sample
+                .measured_at
+                .duration_since(router.last_growth)
+                .unwrap_or(Duration::ZERO);
+            let flow = if stalled_for >= Duration::from_secs(options.stall_secs) {
+                DataFlow::Stalled(stalled_for)
+            } else {
+                match self.median {
+                    Some(median)
+                        if median >= options.min_median_rate
+                            && sample.rate < median * options.low_rate_fraction =>
+                    {
+                        DataFlow::LowRate {
+                            rate: sample.rate,
+                            median,
+                        }
+                    }
+                    _ => DataFlow::Ok,
+                }
+            };
+            if !flow.is_same_kind(&router.flow) {
+                Self::log_change(sample.id, &flow);
+            }
+            router.flow = flow;
+        }
+    }
+
+    fn log_change(id: i32, flow: &DataFlow) {
+        match flow {
+            DataFlow::Ok => tracing::info!(
+                module = id,
+                envoy = %EnvoyKind::Surveyor,
+                "Data router {} is writing data normally again",
+                id
+            ),
+            DataFlow::Stalled(time) => tracing::warn!(
+                module = id,
+                envoy = %EnvoyKind::Surveyor,
+                "Data router {} has not written any data for {} s, check its CoBo and data link",
+                id,
+                time.as_secs()
+            ),
+            DataFlow::LowRate { rate, median } => tracing::warn!(
+                module = id,
+                envoy = %EnvoyKind::Surveyor,
+                "Data router {} is writing {:.3} MB/s, far below the median of {:.3} MB/s, check its AsAds",
+                id,
+                rate,
+                median
+            ),
+        }
+    }
+
+    /// How the data is flowing to a router, if it is being watched
+    pub fn get_flow(&self, id: i32) -> Option<&DataFlow> {
+        self.routers.get(&id).map(|r| &r.flow)
+    }
+
+    /// The median rate of the routers in MB/s, if any are being watched
+    pub fn get_median(&self) -> Option<f64> {
+        self.median
+    }
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        return Some((values[mid - 1] + values[mid]) / 2.0);
+    }
+    return Some(values[mid]);
+}
@@ -0,0 +1,199 @@
+use crate::envoy::surveyor_envoy::SurveyorResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+/// The data rate used for a forecast is averaged over this long, as the rate from one poll to the next is noisy
+const FORECAST_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default thresholds of the disk forecast
+const DEFAULT_FULL_PERCENT: f64 = 95.0;
+const DEFAULT_WARNING_MINUTES: f64 = 60.0;
+const DEFAULT_CRITICAL_MINUTES: f64 = 15.0;
+
+/// # DiskForecastOptions
+/// When the data router disks count as full, how far ahead of that to warn, and whether to stop the run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskForecastOptions {
+    /// The fill level (percent of the disk used) which is treated as full
+    pub full_percent: f64,
+    /// A router which will be full in less than this many minutes is a warning
+    pub warning_minutes: f64,
+    /// A router which will be full in less than this many minutes (or already is) is critical
+    pub critical_minutes: f64,
+    /// Stop the run as soon as any unmasked router is critical
+    pub auto_stop: bool,
+}
+
+impl Default for DiskForecastOptions {
+    fn default() -> Self {
+        Self {
+            full_percent: DEFAULT_FULL_PERCENT,
+            warning_minutes: DEFAULT_WARNING_MINUTES,
+            critical_minutes: DEFAULT_CRITICAL_MINUTES,
+            auto_stop: false,
+        }
+    }
+}
+
+/// How soon a data router disk will be full, relative to the thresholds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiskAlarmLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl std::fmt::Display for DiskAlarmLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "Ok"),
+            Self::Warning => write!(f, "Warning"),
+            Self::Critical => write!(f, "Critical"),
+        }
+    }
+}
+
+/// # DiskForecast
+/// The estimate of when a data router disk will be full
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskForecast {
+    /// Bytes left before the disk reaches the full level
+    pub bytes_left: u64,
+    /// The averaged data rate in MB/s
+    pub rate: f64,
+    /// How long until the disk is full, None if it is not filling
+    pub time_to_full: Option<Duration>,
+    pub level: DiskAlarmLevel,
+}
+
+impl DiskForecast {
+    /// Forecast from the disk numbers of a Surveyor and an averaged data rate (MB/s). Without a percent used there is
+    /// nothing to forecast from.
+    pub fn new(
+        response: &SurveyorResponse,
+        rate: f64,
+        options: &DiskForecastOptions,
+    ) -> Option<Self> {
+        let percent_used = response
+            .percent_used
+            .strip_suffix('%')
+            .and_then(|p| p.parse::<f64>().ok())?;
+        let percent_left = (options.full_percent - percent_used).max(0.0);
+        let bytes_left = ((response.disk_space as f64) * percent_left / 100.0) as u64;
+        let time_to_full = if bytes_left == 0 {
+            Some(Duration::ZERO)
+        } else if rate > 0.0 {
+            Some(Duration::from_secs_f64((bytes_left as f64) * 1.0e-6 / rate))
+        } else {
+            None
+        };
+        let level = match time_to_full {
+            Some(t) if t.as_secs_f64() < options.critical_minutes * 60.0 => {
+                DiskAlarmLevel::Critical
+            }
+            Some(t) if t.as_secs_f64() < options.warning_minutes * 60.0 => DiskAlarmLevel::Warning,
+            _ => DiskAlarmLevel::Ok,
+        };
+        return Some(Self {
+            bytes_left,
+            rate,
+            time_to_full,
+            level,
+        });
+    }
+}
+
+/// # DiskForecaster
+/// Keeps the recent data rates of every data router, by module id, to forecast when its disk will be full.
+/// The rates are averaged over the last minute, each weighted by the interval it was measured over.
+#[derive(Debug, Default)]
+pub struct DiskForecaster {
+    rates: HashMap<i32, VecDeque<(SystemTime, f64)>>,
+}
+
+impl DiskForecaster {
+    pub fn new() -> Self {
+        return Self {
+            rates: HashMap::new(),
+        };
+    }
+
+    /// Add the rate of a Surveyor response. Responses where the Surveyor could not be read are left out.
+    pub fn add_response(&mut self, id: i32, response: &SurveyorResponse) {
+        let measured_at = match response.measured_at {
+            Some(time) => time,
+            None => return,
+        };
+        let rates = self.rates.entry(id).or_default();
+        if let Some((last, _)) = rates.back() {
+            if measured_at < *last {
+                rates.clear();
+            }
+        }
+        rates.push_back((measured_at, response.data_rate));
+        //Keep one rate older than the window, it marks where the first rate in the window began
+        while rates.len() > 2 {
+            match measured_at.duration_since(rates[1].0) {
+                Ok(age) if age > FORECAST_RATE_WINDOW => rates.pop_front(),
+                _ => break,
+            };
+        }
+    }
+
+    /// The averaged rate of a data router in MB/s, if any have been measured. Each rate is weighted by how much of
+    /// the window its interval covers, so an interval which began before the window only counts from the window start.
+    pub fn get_rate(&self, id: i32) -> Option<f64> {
+        let rates = self.rates.get(&id)?;
+        let (last, last_rate) = rates.back()?;
+        let mut sum = 0.0;
+        let mut weight = 0.0;
+        for (previous, current) in rates.iter().zip(rates.iter().skip(1)) {
+            let interval = current
+                .0
+                .duration_since(previous.0)
+                .unwrap_or(Duration::ZERO);
+            let age = last.duration_since(current.0).unwrap_or(Duration::ZERO);
+            if age >= FORECAST_RATE_WINDOW {
+                continue;
+            }
+            let covered = interval.min(FORECAST_RATE_WINDOW - age).as_secs_f64();
+            sum += current.1 * covered;
+            weight += covered;
+        }
+        if weight > 0.0 {
+            return Some(sum / weight);
+        }
+        return Some(*last_rate);
+    }
+
+    /// Forecast for a data router, from its latest response
+    pub fn forecast(
+        &self,
+        id: i32,
+        response: &SurveyorResponse,
+        options: &DiskForecastOptions,
+    ) -> Option<DiskForecast> {
+        DiskForecast::new(response, self.get_rate(id)?, options)
+    }
+
+    pub fn reset(&mut self) {
+        self.rates.clear();
+    }
+}
+
+/// Format a time to full for display, i.e. 2h 05m, 12m 30s, or 45s. None is shown as never.
+pub fn format_time_to_full(time_to_full: Option<Duration>) -> String {
+    let secs = match time_to_full {
+        Some(t) => t.as_secs(),
+        None => return String::from("Never"),
+    };
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, (secs / 60) % 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,157 @@
+use crate::envoy::ecc_operation::ECCStatus;
+use crate::envoy::error::{EmbassyError, TopologyError};
+
+#[derive(Debug)]
+pub enum SessionError {
+    ConfigIOError(std::io::Error),
+    ConfigParseError(serde_yaml::Error),
+    TopologyError(TopologyError),
+    NotConnected,
+    Connected,
+    SequenceRunning,
+    NoSequenceRunning,
+    InvalidState(String, ECCStatus),
+    NoModule(usize),
+    ModuleMasked(usize),
+    SubmitError(EmbassyError),
+    ApiBindError(hyper::Error),
+    RecordingError(RecordingError),
+    Replaying,
+    AlarmError(AlarmError),
+}
+
+impl From<std::io::Error> for SessionError {
+    fn from(value: std::io::Error) -> Self {
+        Self::ConfigIOError(value)
+    }
+}
+
+impl From<serde_yaml::Error> for SessionError {
+    fn from(value: serde_yaml::Error) -> Self {
+        Self::ConfigParseError(value)
+    }
+}
+
+impl From<TopologyError> for SessionError {
+    fn from(value: TopologyError) -> Self {
+        Self::TopologyError(value)
+    }
+}
+
+impl From<EmbassyError> for SessionError {
+    fn from(value: EmbassyError) -> Self {
+        Self::SubmitError(value)
+    }
+}
+
+impl From<RecordingError> for SessionError {
+    fn from(value: RecordingError) -> Self {
+        Self::RecordingError(value)
+    }
+}
+
+impl From<AlarmError> for SessionError {
+    fn from(value: AlarmError) -> Self {
+        Self::AlarmError(value)
+    }
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConfigIOError(e) => write!(f, "Could not read or write the config: {e}"),
+            Self::ConfigParseError(e) => write!(f, "Could not parse the config: {e}"),
+            Self::TopologyError(e) => write!(f, "{e}"),
+            Self::NotConnected => write!(f, "The session is not connected to the AT-TPC!"),
+            Self::Connected => write!(f, "This cannot be done while connected to the AT-TPC!"),
+            Self::SequenceRunning => write!(f, "Another sequence is already running!"),
+            Self::NoSequenceRunning => write!(f, "No sequence is running!"),
+            Self::InvalidState(action, status) => {
+                write!(f, "Cannot {action} from status {status}!")
+            }
+            Self::NoModule(id) => write!(f, "There is no module {id} in the topology!"),
+            Self::ModuleMasked(id) => write!(f, "Module {id} is masked!"),
+            Self::SubmitError(e) => write!(f, "{e}"),
+            Self::ApiBindError(e) => write!(f, "Could not start the control API: {e}"),
+            Self::RecordingError(e) => write!(f, "{e}"),
+            Self::Replaying => write!(f, "This cannot be done while replaying a recording!"),
+            Self::AlarmError(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    IOError(std::io::Error),
+    ParseError(serde_json::Error),
+    TopologyError(TopologyError),
+    Empty,
+    BadVersion(u32),
+}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IOError(value)
+    }
+}
+
+impl From<serde_json::Error> for RecordingError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::ParseError(value)
+    }
+}
+
+impl From<TopologyError> for RecordingError {
+    fn from(value: TopologyError) -> Self {
+        Self::TopologyError(value)
+    }
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IOError(e) => write!(f, "Could not read or write the recording: {e}"),
+            Self::ParseError(e) => write!(f, "Could not parse the recording: {e}"),
+            Self::TopologyError(e) => write!(f, "The recording has a bad topology: {e}"),
+            Self::Empty => write!(f, "The recording is empty!"),
+            Self::BadVersion(v) => write!(f, "Recording format version {v} is not supported!"),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+#[derive(Debug)]
+pub enum AlarmError {
+    IOError(std::io::Error),
+    ParseError(serde_yaml::Error),
+    DuplicateRule(String),
+    NoAlarm(u64),
+}
+
+impl From<std::io::Error> for AlarmError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IOError(value)
+    }
+}
+
+impl From<serde_yaml::Error> for AlarmError {
+    fn from(value: serde_yaml::Error) -> Self {
+        Self::ParseError(value)
+    }
+}
+
+impl std::fmt::Display for AlarmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IOError(e) => write!(f, "Could not read the alarm rules: {e}"),
+            Self::ParseError(e) => write!(f, "Could not parse the alarm rules: {e}"),
+            Self::DuplicateRule(name) => write!(f, "Alarm rule {name} is defined more than once!"),
+            Self::NoAlarm(id) => write!(f, "There is no active alarm {id}!"),
+        }
+    }
+}
+
+impl std::error::Error for AlarmError {}
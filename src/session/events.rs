@@ -0,0 +1,179 @@
+use super::alarms::{Alarm, AlarmChange};
+use crate::envoy::connection_status::ConnectionStatus;
+use crate::envoy::ecc_operation::ECCStatus;
+use crate::envoy::message::{EmbassyMessage, MessageTiming};
+use crate::envoy::surveyor_envoy::SurveyorResponse;
+use crate::envoy::surveyor_state::SurveyorState;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+use tokio::sync::broadcast;
+
+/// Number of events a subscriber can fall behind before it starts missing them
+const EVENT_CAPACITY: usize = 256;
+
+/// # SessionEvent
+/// Something which happened in the session, pushed to every subscriber of the control API event stream.
+/// Timestamps are milliseconds since the Unix epoch, taken when the request behind the event was sent.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// The state reported by an ECC server changed, or the server could (or could no longer) be reached
+    EccState {
+        id: i32,
+        state: String,
+        connection: ConnectionStatus,
+        timestamp: u128,
+    },
+    /// A Surveyor reported its status
+    Surveyor {
+        id: i32,
+        state: String,
+        response: SurveyorResponse,
+        timestamp: u128,
+    },
+    /// An ECC operation was answered (or failed)
+    OperationResult {
+        id: i32,
+        op_id: u64,
+        operation: String,
+        success: bool,
+        error_code: i32,
+        error_message: String,
+        elapsed_ms: u128,
+        timestamp: u128,
+    },
+    RunStarted {
+        run_number: i32,
+    },
+    RunStopped {
+        run_number: i32,
+        duration_secs: u64,
+    },
+    /// A Progress, Start, or Stop sequence did not complete
+    SequenceFailed {
+        sequence: String,
+        error: String,
+    },
+    /// An alarm was raised, acknowledged, or cleared
+    Alarm {
+        change: String,
+        alarm: Alarm,
+    },
+}
+
+impl SessionEvent {
+    /// The name of the event in the stream
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::EccState { .. } => "ecc_state",
+            Self::Surveyor { .. } => "surveyor",
+            Self::OperationResult { .. } => "operation_result",
+            Self::RunStarted { .. } => "run_started",
+            Self::RunStopped { .. } => "run_stopped",
+            Self::SequenceFailed { .. } => "sequence_failed",
+            Self::Alarm { .. } => "alarm",
+        }
+    }
+}
+
+impl From<AlarmChange> for SessionEvent {
+    fn from(value: AlarmChange) -> Self {
+        let (change, alarm) = match value {
+            AlarmChange::Raised(alarm) => ("raised", alarm),
+            AlarmChange::Acknowledged(alarm) => ("acknowledged", alarm),
+            AlarmChange::Cleared(alarm) => ("cleared", alarm),
+        };
+        Self::Alarm {
+            change: String::from(change),
+            alarm,
+        }
+    }
+}
+
+fn timestamp_of(timing: &MessageTiming) -> u128 {
+    match timing.sent.duration_since(UNIX_EPOCH) {
+        Ok(t) => t.as_millis(),
+        Err(_) => 0,
+    }
+}
+
+/// # EventPublisher
+/// Turns the messages handled by the session into SessionEvents, and fans them out to any number of subscribers.
+/// Publishing never waits on the subscribers; a subscriber which falls too far behind misses events instead.
+#[derive(Debug)]
+pub struct EventPublisher {
+    sender: broadcast::Sender<SessionEvent>,
+    ecc_states: HashMap<i32, (i32, ConnectionStatus)>,
+}
+
+impl EventPublisher {
+    pub fn new() -> Self {
+        return Self {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+            ecc_states: HashMap::new(),
+        };
+    }
+
+    /// A sender from which new subscriptions can be made
+    pub fn get_sender(&self) -> broadcast::Sender<SessionEvent> {
+        self.sender.clone()
+    }
+
+    pub fn publish(&self, event: SessionEvent) {
+        //An error only means there are no subscribers right now
+        let _ = self.sender.send(event);
+    }
+
+    /// Publish the events for a batch of messages from the embassy. ECC status is only published when it changes.
+    pub fn handle_messages(&mut self, messages: &[EmbassyMessage]) {
+        for message in messages {
+            match message {
+                EmbassyMessage::ECCStatus {
+                    id,
+                    response,
+                    timing,
+                } => {
+                    let current = (response.state, response.connection.clone());
+                    if self.ecc_states.get(id) == Some(&current) {
+                        continue;
+                    }
+                    self.ecc_states.insert(*id, current);
+                    self.publish(SessionEvent::EccState {
+                        id: *id,
+                        state: ECCStatus::from(response.state).to_string(),
+                        connection: response.connection.clone(),
+                        timestamp: timestamp_of(timing),
+                    });
+                }
+                EmbassyMessage::Surveyor {
+                    id,
+                    response,
+                    timing,
+                } => self.publish(SessionEvent::Surveyor {
+                    id: *id,
+                    state: SurveyorState::from(response.state).to_string(),
+                    response: response.clone(),
+                    timestamp: timestamp_of(timing),
+                }),
+                EmbassyMessage::ECCOperationResponse {
+                    id,
+                    op_id,
+                    operation,
+                    response,
+                    timing,
+                } => self.publish(SessionEvent::OperationResult {
+                    id: *id,
+                    op_id: op_id.0,
+                    operation: operation.to_string(),
+                    success: response.error_code == 0,
+                    error_code: response.error_code,
+                    error_message: response.error_message.clone(),
+                    elapsed_ms: timing.elapsed.as_millis(),
+                    timestamp: timestamp_of(timing),
+                }),
+                _ => (),
+            }
+        }
+    }
+}
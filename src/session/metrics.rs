@@ -0,0 +1,259 @@
+use super::api::ApiState;
+use crate::envoy::message::EmbassyMessage;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the request latency histogram buckets. ECC operations can take minutes,
+/// so the buckets run well past the usual web service range.
+const LATENCY_BUCKETS: [f64; 15] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// A Prometheus style cumulative histogram
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if secs <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {bucket}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+/// # Metrics
+/// Counters and histograms accumulated from the messages handled by the session, exported by the control API at
+/// `/metrics` along with gauges taken from the ApiState. Counts start over when the API is started.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    operation_successes: BTreeMap<String, u64>,
+    operation_failures: BTreeMap<(String, i32), u64>,
+    ecc_status_latency: Histogram,
+    ecc_operation_latency: Histogram,
+    surveyor_latency: Histogram,
+}
+
+impl Metrics {
+    pub fn handle_messages(&mut self, messages: &[EmbassyMessage]) {
+        for message in messages {
+            match message {
+                EmbassyMessage::ECCOperationResponse {
+                    operation,
+                    response,
+                    timing,
+                    ..
+                } => {
+                    if response.error_code == 0 {
+                        *self
+                            .operation_successes
+                            .entry(operation.to_string())
+                            .or_insert(0) += 1;
+                    } else {
+                        *self
+                            .operation_failures
+                            .entry((operation.to_string(), response.error_code))
+                            .or_insert(0) += 1;
+                    }
+                    self.ecc_operation_latency.observe(timing.elapsed);
+                }
+                EmbassyMessage::ECCStatus { timing, .. } => {
+                    self.ecc_status_latency.observe(timing.elapsed)
+                }
+                EmbassyMessage::Surveyor { timing, .. } => {
+                    self.surveyor_latency.observe(timing.elapsed)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Render the metrics and the gauges of the state in the Prometheus text exposition format
+    pub fn render(&self, state: &ApiState) -> String {
+        let mut out = String::new();
+
+        Self::header(
+            &mut out,
+            "attpc_ecc_state",
+            "gauge",
+            "ECC state code, -1 if the server cannot be reached",
+        );
+        for ecc in state.ecc.iter().filter(|e| !e.masked) {
+            let state_code = match ecc.connection.is_failure() {
+                true => -1,
+                false => ecc.state_code,
+            };
+            let _ = writeln!(
+                out,
+                "attpc_ecc_state{{module=\"{}\",role=\"{}\"}} {}",
+                ecc.id, ecc.role, state_code
+            );
+        }
+
+        let surveyors: Vec<_> = state.surveyors.iter().filter(|s| !s.masked).collect();
+        Self::header(
+            &mut out,
+            "attpc_surveyor_state",
+            "gauge",
+            "Surveyor state code",
+        );
+        for s in surveyors.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_surveyor_state{{module=\"{}\"}} {}",
+                s.id, s.response.state
+            );
+        }
+        Self::header(
+            &mut out,
+            "attpc_surveyor_bytes_used",
+            "gauge",
+            "Bytes of .graw files on the data router",
+        );
+        for s in surveyors.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_surveyor_bytes_used{{module=\"{}\"}} {}",
+                s.id, s.response.bytes_used
+            );
+        }
+        Self::header(
+            &mut out,
+            "attpc_surveyor_files",
+            "gauge",
+            "Number of .graw files on the data router",
+        );
+        for s in surveyors.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_surveyor_files{{module=\"{}\"}} {}",
+                s.id, s.response.files
+            );
+        }
+        Self::header(
+            &mut out,
+            "attpc_surveyor_data_rate_mbps",
+            "gauge",
+            "Data rate to the data router in MB/s",
+        );
+        for s in surveyors.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_surveyor_data_rate_mbps{{module=\"{}\"}} {}",
+                s.id, s.response.data_rate
+            );
+        }
+        Self::header(
+            &mut out,
+            "attpc_surveyor_disk_space_bytes",
+            "gauge",
+            "Size of the data router disk",
+        );
+        for s in surveyors.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_surveyor_disk_space_bytes{{module=\"{}\"}} {}",
+                s.id, s.response.disk_space
+            );
+        }
+        Self::header(
+            &mut out,
+            "attpc_surveyor_disk_used_percent",
+            "gauge",
+            "Percent of the data router disk used",
+        );
+        for s in surveyors.iter() {
+            if let Ok(percent) = s.response.percent_used.trim_end_matches('%').parse::<f64>() {
+                let _ = writeln!(
+                    out,
+                    "attpc_surveyor_disk_used_percent{{module=\"{}\"}} {}",
+                    s.id, percent
+                );
+            }
+        }
+
+        Self::header(
+            &mut out,
+            "attpc_ecc_operation_successes_total",
+            "counter",
+            "ECC operations which succeeded",
+        );
+        for (operation, count) in self.operation_successes.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_ecc_operation_successes_total{{operation=\"{operation}\"}} {count}"
+            );
+        }
+        Self::header(
+            &mut out,
+            "attpc_ecc_operation_failures_total",
+            "counter",
+            "ECC operations which failed, by error code",
+        );
+        for ((operation, code), count) in self.operation_failures.iter() {
+            let _ = writeln!(
+                out,
+                "attpc_ecc_operation_failures_total{{operation=\"{operation}\",error_code=\"{code}\"}} {count}"
+            );
+        }
+
+        Self::header(
+            &mut out,
+            "attpc_envoy_request_duration_seconds",
+            "histogram",
+            "Time taken by the envoy requests to the ECC servers and Surveyors",
+        );
+        let name = "attpc_envoy_request_duration_seconds";
+        self.ecc_status_latency
+            .render(&mut out, name, "envoy=\"ecc_status\"");
+        self.ecc_operation_latency
+            .render(&mut out, name, "envoy=\"ecc_operation\"");
+        self.surveyor_latency
+            .render(&mut out, name, "envoy=\"surveyor\"");
+
+        Self::header(&mut out, "attpc_run_number", "gauge", "Current run number");
+        let _ = writeln!(out, "attpc_run_number {}", state.run.run_number);
+        Self::header(
+            &mut out,
+            "attpc_run_active",
+            "gauge",
+            "1 if the system is running",
+        );
+        let _ = writeln!(out, "attpc_run_active {}", state.run.running as u8);
+        Self::header(
+            &mut out,
+            "attpc_run_duration_seconds",
+            "gauge",
+            "Duration of the current run, 0 if there is none",
+        );
+        let _ = writeln!(
+            out,
+            "attpc_run_duration_seconds {}",
+            state.run.run_duration_secs.unwrap_or(0)
+        );
+        return out;
+    }
+
+    fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+    }
+}
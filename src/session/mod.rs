@@ -0,0 +1,12 @@
+pub mod alarms;
+pub mod api;
+pub mod config;
+pub mod data_monitor;
+pub mod disk_forecast;
+pub mod error;
+pub mod events;
+pub mod metrics;
+pub mod recording;
+pub mod run_log;
+pub mod session;
+pub mod status_manager;
@@ -0,0 +1,226 @@
+use super::error::RecordingError;
+use crate::envoy::message::EmbassyMessage;
+use crate::envoy::topology::Topology;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Recordings are written to a directory per experiment under this directory
+const RECORDING_DIRECTORY: &str = "recordings/";
+/// Version of the recording format, written in the header
+const RECORDING_VERSION: u32 = 1;
+
+/// The first line of a recording. The topology and mask are needed to replay the messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub experiment: String,
+    pub run_number: i32,
+    /// When the recording was started, in milliseconds since the Unix epoch
+    pub started: u64,
+    pub topology: Topology,
+    pub masked_modules: Vec<i32>,
+}
+
+/// A message in a recording, with the time since the recording was started at which the session received it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub offset_ms: u64,
+    pub message: EmbassyMessage,
+}
+
+/// # Recorder
+/// Writes every message polled from the embassy to a recording file, as lines of JSON following a RecordingHeader.
+/// Recordings are named `recordings/<experiment>/run_<N>_<unix seconds>.jsonl`.
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Create a new recording for a run
+    pub fn new(
+        experiment: &str,
+        run_number: i32,
+        topology: &Topology,
+        masked_modules: &[i32],
+    ) -> Result<Self, RecordingError> {
+        let directory = PathBuf::from(RECORDING_DIRECTORY).join(experiment);
+        std::fs::create_dir_all(&directory)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+        let path = directory.join(format!("run_{}_{}.jsonl", run_number, now.as_secs()));
+        let header = RecordingHeader {
+            version: RECORDING_VERSION,
+            experiment: experiment.to_string(),
+            run_number,
+            started: now.as_millis() as u64,
+            topology: topology.clone(),
+            masked_modules: masked_modules.to_vec(),
+        };
+        let mut writer = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        return Ok(Self {
+            path,
+            writer,
+            started: Instant::now(),
+        });
+    }
+
+    /// Append a batch of messages to the recording
+    pub fn record(&mut self, messages: &[EmbassyMessage]) -> Result<(), RecordingError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let offset_ms = self.started.elapsed().as_millis() as u64;
+        for message in messages {
+            let recorded = RecordedMessage {
+                offset_ms,
+                message: message.clone(),
+            };
+            serde_json::to_writer(&mut self.writer, &recorded)?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// # Recording
+/// A recording read back from its file
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub messages: Vec<RecordedMessage>,
+}
+
+impl Recording {
+    pub fn read(path: &Path) -> Result<Self, RecordingError> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let mut header: RecordingHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(RecordingError::Empty),
+        };
+        if header.version != RECORDING_VERSION {
+            return Err(RecordingError::BadVersion(header.version));
+        }
+        header.topology.validate()?;
+
+        let mut messages = vec![];
+        for line in lines {
+            let line = line?;
+            //A recording cut short by a crash can end on a partial line
+            match serde_json::from_str::<RecordedMessage>(&line) {
+                Ok(message) => messages.push(message),
+                Err(e) if e.is_eof() => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        return Ok(Self { header, messages });
+    }
+
+    /// Time from the start of the recording to the last message
+    pub fn get_duration(&self) -> Duration {
+        match self.messages.last() {
+            Some(m) => Duration::from_millis(m.offset_ms),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// # Replay
+/// Plays a Recording back in place of the embassy. Each poll returns the messages which were received up to the
+/// current position in the recording. The position advances with the wall clock, scaled by the speed, unless paused.
+#[derive(Debug)]
+pub struct Replay {
+    path: PathBuf,
+    recording: Recording,
+    next: usize,
+    position: Duration,
+    last_poll: Instant,
+    speed: f64,
+    is_paused: bool,
+}
+
+impl Replay {
+    pub fn new(path: &Path, recording: Recording, speed: f64) -> Self {
+        return Self {
+            path: path.to_path_buf(),
+            recording,
+            next: 0,
+            position: Duration::ZERO,
+            last_poll: Instant::now(),
+            speed,
+            is_paused: false,
+        };
+    }
+
+    /// Advance the replay and return the messages which are now due
+    pub fn poll(&mut self) -> Vec<EmbassyMessage> {
+        let now = Instant::now();
+        if !self.is_paused {
+            self.position += (now - self.last_poll).mul_f64(self.speed);
+        }
+        self.last_poll = now;
+
+        let position_ms = self.position.as_millis() as u64;
+        let mut messages = vec![];
+        while let Some(recorded) = self.recording.messages.get(self.next) {
+            if recorded.offset_ms > position_ms {
+                break;
+            }
+            messages.push(recorded.message.clone());
+            self.next += 1;
+        }
+        return messages;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_paused(&mut self, is_paused: bool) {
+        self.is_paused = is_paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// All of the messages have been replayed
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.messages.len()
+    }
+
+    /// How far into the recording the replay is
+    pub fn get_position(&self) -> Duration {
+        self.position.min(self.recording.get_duration())
+    }
+
+    pub fn get_duration(&self) -> Duration {
+        self.recording.get_duration()
+    }
+
+    pub fn get_header(&self) -> &RecordingHeader {
+        &self.recording.header
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
@@ -0,0 +1,316 @@
+use serde_json::{Map, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Run logs are written to a directory per experiment under this directory
+const LOG_DIRECTORY: &str = "logs/";
+/// A run log which grows past this size is continued in a new segment
+const MAX_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Retention policy for the run logs of an experiment
+#[derive(Debug, Clone, Copy)]
+pub struct LogRetention {
+    pub max_age: Duration,
+    pub max_total_bytes: u64,
+}
+
+/// The file currently being written. Segments of a run are named run_<N>.jsonl, run_<N>.1.jsonl, run_<N>.2.jsonl, ...
+#[derive(Debug)]
+struct RunLogFile {
+    directory: PathBuf,
+    run_number: i32,
+    segment: u32,
+    path: PathBuf,
+    writer: BufWriter<File>,
+    bytes: u64,
+}
+
+impl RunLogFile {
+    fn segment_path(directory: &Path, run_number: i32, segment: u32) -> PathBuf {
+        match segment {
+            0 => directory.join(format!("run_{run_number}.jsonl")),
+            s => directory.join(format!("run_{run_number}.{s}.jsonl")),
+        }
+    }
+
+    /// Open a segment of a run log, appending if it already exists
+    fn open(directory: &Path, run_number: i32, segment: u32) -> Result<Self, std::io::Error> {
+        let path = Self::segment_path(directory, run_number, segment);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let bytes = file.metadata()?.len();
+        return Ok(Self {
+            directory: directory.to_path_buf(),
+            run_number,
+            segment,
+            path,
+            writer: BufWriter::new(file),
+            bytes,
+        });
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), std::io::Error> {
+        if self.bytes >= MAX_SEGMENT_BYTES {
+            self.writer.flush()?;
+            *self = Self::open(&self.directory, self.run_number, self.segment + 1)?;
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.bytes += line.len() as u64 + 1;
+        //Flush every line so that the log is complete even if the application dies
+        self.writer.flush()
+    }
+}
+
+/// # RunLog
+/// The per-run log file sink. While a run log is open, every event seen by the RunLogLayer is appended to it
+/// as a line of JSON. Logs are written to `logs/<experiment>/run_<N>.jsonl`, continuing in a new segment
+/// if a file gets too large. Reopening the log of a run appends to it, so a run which is started and stopped
+/// by different sessions still has a single log.
+#[derive(Debug, Clone, Default)]
+pub struct RunLog {
+    file: Arc<Mutex<Option<RunLogFile>>>,
+}
+
+impl RunLog {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<RunLogFile>> {
+        match self.file.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Open the log for a run, pruning the experiment's old logs first. Does nothing if the run's log is already open.
+    /// Returns the path of the file being written.
+    pub fn open(
+        &self,
+        experiment: &str,
+        run_number: i32,
+        retention: &LogRetention,
+    ) -> Result<PathBuf, std::io::Error> {
+        let directory = PathBuf::from(LOG_DIRECTORY).join(experiment);
+        if let Some(file) = self.lock().as_ref() {
+            if file.directory == directory && file.run_number == run_number {
+                return Ok(file.path.clone());
+            }
+        }
+        self.close();
+
+        std::fs::create_dir_all(&directory)?;
+        let pruned = prune_logs(&directory, run_number, retention)?;
+        let mut segment = 0;
+        while RunLogFile::segment_path(&directory, run_number, segment + 1).exists() {
+            segment += 1;
+        }
+        let file = RunLogFile::open(&directory, run_number, segment)?;
+        let path = file.path.clone();
+        *self.lock() = Some(file);
+        //Only log once the lock is released, as the layer takes it too
+        if pruned > 0 {
+            tracing::info!(
+                "Pruned {} old run logs from {}",
+                pruned,
+                directory.display()
+            );
+        }
+        tracing::info!("Logging run {} to {}", run_number, path.display());
+        return Ok(path);
+    }
+
+    /// Close the current run log, if any
+    pub fn close(&self) {
+        if let Some(path) = self.get_path() {
+            tracing::info!("Closing run log {}", path.display());
+        }
+        let file = self.lock().take();
+        if let Some(mut file) = file {
+            let _ = file.writer.flush();
+        }
+    }
+
+    /// The path of the current run log, if one is open
+    pub fn get_path(&self) -> Option<PathBuf> {
+        self.lock().as_ref().map(|f| f.path.clone())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    /// Append a line to the current run log. Errors cannot be logged (they would come straight back here),
+    /// so they are reported on stderr.
+    fn write_line(&self, line: &str) {
+        if let Some(file) = self.lock().as_mut() {
+            if let Err(e) = file.write_line(line) {
+                eprintln!("Could not write to run log {}: {}", file.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Delete the logs in the directory which are older than the maximum age, then the oldest logs until the rest fit in
+/// the maximum size. The logs of the given run are kept. Returns the number of files deleted.
+fn prune_logs(
+    directory: &Path,
+    keep_run: i32,
+    retention: &LogRetention,
+) -> Result<usize, std::io::Error> {
+    let keep_prefix = format!("run_{keep_run}.");
+    let mut logs: Vec<(PathBuf, SystemTime, u64)> = vec![];
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.ends_with(".jsonl") || name.starts_with(&keep_prefix) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        logs.push((
+            entry.path(),
+            metadata.modified().unwrap_or(UNIX_EPOCH),
+            metadata.len(),
+        ));
+    }
+    logs.sort_by_key(|(_, modified, _)| *modified);
+
+    let mut total: u64 = logs.iter().map(|(_, _, size)| size).sum();
+    let mut pruned = 0;
+    for (path, modified, size) in logs {
+        let is_expired = match modified.elapsed() {
+            Ok(age) => age > retention.max_age,
+            Err(_) => false,
+        };
+        if !is_expired && total <= retention.max_total_bytes {
+            continue;
+        }
+        std::fs::remove_file(&path)?;
+        total -= size;
+        pruned += 1;
+    }
+    return Ok(pruned);
+}
+
+/// Collects the fields of an event or span as JSON values
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl<'a> Visit for JsonVisitor<'a> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), Value::from(format!("{value:?}")));
+    }
+}
+
+/// The fields of a span, kept so that they can be written with the events inside it
+struct SpanJson(Map<String, Value>);
+
+/// # RunLogLayer
+/// A tracing layer which writes every event to the open RunLog as JSON lines of the form
+///
+/// `{"timestamp":<ms since the Unix epoch>,"level":"INFO","target":...,"file":...,"line":...,"fields":{"message":...},"spans":[{"name":"envoy","module":3,"envoy":"Surveyor"}]}`
+///
+/// Events are dropped while no run log is open.
+pub struct RunLogLayer {
+    log: RunLog,
+}
+
+impl RunLogLayer {
+    pub fn new(log: &RunLog) -> Self {
+        return Self { log: log.clone() };
+    }
+}
+
+impl<S> Layer<S> for RunLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Map::new();
+        fields.insert(String::from("name"), Value::from(attrs.metadata().name()));
+        attrs.record(&mut JsonVisitor(&mut fields));
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanJson(fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanJson>() {
+                values.record(&mut JsonVisitor(&mut fields.0));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !self.log.is_open() {
+            return;
+        }
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+        let mut spans = vec![];
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(json) = span.extensions().get::<SpanJson>() {
+                    spans.push(Value::Object(json.0.clone()));
+                }
+            }
+        }
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(t) => t.as_millis() as u64,
+            Err(_) => 0,
+        };
+
+        let mut record = Map::new();
+        record.insert(String::from("timestamp"), Value::from(timestamp));
+        record.insert(
+            String::from("level"),
+            Value::from(metadata.level().as_str()),
+        );
+        record.insert(String::from("target"), Value::from(metadata.target()));
+        if let Some(file) = metadata.file() {
+            record.insert(String::from("file"), Value::from(file));
+        }
+        if let Some(line) = metadata.line() {
+            record.insert(String::from("line"), Value::from(line));
+        }
+        record.insert(String::from("fields"), Value::Object(fields));
+        if !spans.is_empty() {
+            record.insert(String::from("spans"), Value::Array(spans));
+        }
+        self.log.write_line(&Value::Object(record).to_string());
+    }
+}
@@ -0,0 +1,1087 @@
+use super::alarms::{AlarmContext, AlarmEngine, AlarmRules};
+use super::api::{
+    ApiAlarms, ApiCommand, ApiECCStatus, ApiHandle, ApiRunState, ApiServer, ApiState,
+    ApiSurveyorStatus,
+};
+use super::config::Config;
+use super::data_monitor::{DataFlow, DataMonitor};
+use super::disk_forecast::DiskForecast;
+use super::error::SessionError;
+use super::events::{EventPublisher, SessionEvent};
+use super::metrics::Metrics;
+use super::recording::{Recorder, Recording, Replay};
+use super::run_log::RunLog;
+use super::status_manager::StatusManager;
+use crate::envoy::constants::STATUS_POLL_SECS;
+use crate::envoy::ecc_operation::{ECCOperation, ECCStatus};
+use crate::envoy::embassy::{connect_embassy, Embassy};
+use crate::envoy::error::RunControlError;
+use crate::envoy::message::EmbassyMessage;
+use crate::envoy::run_controller::{
+    RunController, RunHandle, RunProgress, RunSequence, StatusSnapshot,
+};
+use crate::envoy::simulation::Simulation;
+use crate::envoy::surveyor_state::SurveyorState;
+use crate::envoy::switchboard::PendingOperation;
+use crate::envoy::topology::Topology;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+
+/// Number of API commands which can wait for the session to be polled
+const API_REQUEST_CAPACITY: usize = 16;
+
+/// Longest time the state published to the control API can go without being refreshed
+const API_STATE_REFRESH: Duration = Duration::from_secs(STATUS_POLL_SECS);
+
+/// # Session
+/// Everything needed to operate the AT-TPC, independent of how it is presented: the config and topology,
+/// the tokio runtime, the embassy and its envoys, the StatusManager, and the RunController for multi-step sequences.
+/// The GUI (EnvoyApp) and the command line client both drive a Session.
+///
+/// If given a RunLog, the session keeps a log file open for the current run: from the start of the Start sequence until
+/// the Stop sequence completes (or the Start sequence fails). Over the same span every message from the embassy is
+/// recorded, if the config asks for it.
+///
+/// The session can also connect to a Simulation of the AT-TPC, in which case simulated envoys stand in for the real
+/// ones and the run sequences' shell commands are simulated too. This allows everything to be exercised offline.
+///
+/// Instead of connecting, the session can replay a recording. The recorded messages are fed to the StatusManager (and
+/// returned from poll) as if they came from the embassy, but nothing can be sent to the AT-TPC.
+///
+/// If the config asks for it, the session stops the run once the disk of any unmasked data router is critical (full, or
+/// forecast to be full within the critical time). This is only tried once per run; if the stop fails it is left to
+/// the shifter.
+///
+/// While the system is running, a DataMonitor watches the data routers for stalls and rates far below the others.
+///
+/// The alarm rules of the config are evaluated against the status on every poll by an AlarmEngine. Alarms are
+/// acknowledged through the session.
+///
+/// The session must be polled regularly (i.e. every frame) so that the status is kept up to date and published
+/// to any running sequence.
+#[derive(Debug)]
+pub struct Session {
+    pub config: Config,
+    topology: Topology,
+    runtime: tokio::runtime::Runtime,
+    embassy: Option<Embassy>,
+    envoy_handles: Option<Vec<tokio::task::JoinHandle<()>>>,
+    status: StatusManager,
+    status_tx: watch::Sender<StatusSnapshot>,
+    run_control: Option<RunHandle>,
+    run_step: String,
+    sequence_outcome: Option<(RunSequence, Result<(), RunControlError>)>,
+    api: Option<ApiHandle>,
+    run_log: Option<RunLog>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    simulation: Option<Simulation>,
+    disk_stop_tried: bool,
+    data_monitor: DataMonitor,
+    alarms: AlarmEngine,
+}
+
+impl Session {
+    pub fn new(runtime: tokio::runtime::Runtime) -> Self {
+        let topology = Topology::default();
+        return Self {
+            config: Config::new(),
+            runtime,
+            embassy: None,
+            envoy_handles: None,
+            status: StatusManager::new(&topology),
+            status_tx: watch::channel(StatusSnapshot::default()).0,
+            run_control: None,
+            run_step: String::new(),
+            sequence_outcome: None,
+            api: None,
+            run_log: None,
+            recorder: None,
+            replay: None,
+            simulation: None,
+            disk_stop_tried: false,
+            data_monitor: DataMonitor::new(),
+            alarms: AlarmEngine::new(AlarmRules::default()),
+            topology,
+        };
+    }
+
+    /// Set the sink for the per-run logs. The sink must also be given to the tracing subscriber (via a RunLogLayer).
+    pub fn set_run_log(&mut self, run_log: RunLog) {
+        self.run_log = Some(run_log);
+    }
+
+    /// Read in a config from a YAML file at the filepath, and load its topology. If the config has a run in progress,
+    /// logging to that run's log resumes. The config holds the run and the mask, so it can only be replaced when
+    /// disconnected.
+    pub fn read_config(&mut self, filepath: PathBuf) -> Result<(), SessionError> {
+        if self.embassy.is_some() {
+            return Err(SessionError::Connected);
+        }
+        if self.replay.is_some() {
+            return Err(SessionError::Replaying);
+        }
+        self.config = Config::read(&filepath)?;
+        match self.config.run_started_at {
+            Some(_) => self.open_run_log(),
+            None => self.close_run_log(),
+        }
+        if let Err(e) = self.load_alarm_rules() {
+            tracing::error!("{}. Falling back to the default alarm rules.", e);
+        }
+        self.load_topology()
+    }
+
+    /// Load the alarm rules given by the config. If there is no rules file the default rules are used. If the rules
+    /// file is bad the default rules are used as well, but the error is still returned. Active alarms of rules which
+    /// are no longer given are cleared.
+    pub fn load_alarm_rules(&mut self) -> Result<(), SessionError> {
+        let result = match &self.config.alarm_rules_path {
+            Some(path) => AlarmRules::read(path),
+            None => Ok(AlarmRules::default()),
+        };
+        let (rules, result) = match result {
+            Ok(rules) => {
+                tracing::info!("Loaded {} alarm rules", rules.rules.len());
+                (rules, Ok(()))
+            }
+            Err(e) => (AlarmRules::default(), Err(e.into())),
+        };
+        for change in self.alarms.set_rules(rules) {
+            self.publish_event(change.into());
+        }
+        return result;
+    }
+
+    /// Load the topology given by the config. If there is no topology file the full AT-TPC is used. If the topology file
+    /// is bad the full AT-TPC is used as well, but the error is still returned. The status manager is rebuilt to match the
+    /// new topology, so this can only be done when disconnected.
+    pub fn load_topology(&mut self) -> Result<(), SessionError> {
+        if self.embassy.is_some() {
+            return Err(SessionError::Connected);
+        }
+        if self.replay.is_some() {
+            return Err(SessionError::Replaying);
+        }
+        let result = match &self.config.topology_path {
+            Some(path) => Topology::read(path),
+            None => Ok(Topology::default()),
+        };
+        let error = match result {
+            Ok(t) => {
+                self.topology = t;
+                None
+            }
+            Err(e) => {
+                self.topology = Topology::default();
+                Some(e)
+            }
+        };
+        self.status = StatusManager::new(&self.topology);
+        self.status.set_masked(&self.config.masked_modules);
+        tracing::info!(
+            "Loaded topology {} with {} modules",
+            self.topology.name,
+            self.topology.number_of_modules()
+        );
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the current config to its YAML file
+    pub fn write_config(&self) -> Result<(), SessionError> {
+        self.config.write()
+    }
+
+    /// Mask or unmask a module. Only allowed while disconnected, as masked modules do not get envoys.
+    pub fn toggle_mask(&mut self, id: i32) -> Result<(), SessionError> {
+        if self.embassy.is_some() {
+            return Err(SessionError::Connected);
+        }
+        if self.replay.is_some() {
+            return Err(SessionError::Replaying);
+        }
+        if let Some(pos) = self.config.masked_modules.iter().position(|m| *m == id) {
+            self.config.masked_modules.remove(pos);
+            tracing::info!("Module {} unmasked", id);
+        } else {
+            self.config.masked_modules.push(id);
+            self.config.masked_modules.sort();
+            tracing::info!("Module {} masked", id);
+        }
+        self.status.set_masked(&self.config.masked_modules);
+        Ok(())
+    }
+
+    /// Create all of the envoys, the embassy, and start the async tasks
+    pub fn connect(&mut self) -> Result<(), SessionError> {
+        return self.connect_with(None);
+    }
+
+    /// Connect to a new Simulation of the topology instead of the AT-TPC. The simulation is configured by the
+    /// config's simulation options, beam and energy, and is thrown away at disconnect.
+    pub fn connect_simulated(&mut self) -> Result<(), SessionError> {
+        let simulation = Simulation::new(
+            &self.topology,
+            &self.config.simulation,
+            &self.config.beam,
+            self.config.energy,
+        );
+        return self.connect_with(Some(simulation));
+    }
+
+    fn connect_with(&mut self, simulation: Option<Simulation>) -> Result<(), SessionError> {
+        if self.embassy.is_some() || self.envoy_handles.is_some() {
+            return Err(SessionError::Connected);
+        }
+        if self.replay.is_some() {
+            return Err(SessionError::Replaying);
+        }
+        let (em, handles) = connect_embassy(
+            &mut self.runtime,
+            &self.config.experiment,
+            &self.topology,
+            &self.config.masked_modules,
+            simulation.as_ref(),
+        );
+        match simulation {
+            Some(_) => tracing::info!(
+                "Connected to the simulation with {} tasks spawned",
+                handles.len()
+            ),
+            None => tracing::info!("Connnected with {} tasks spawned", handles.len()),
+        }
+        self.simulation = simulation;
+        if !self.config.masked_modules.is_empty() {
+            tracing::warn!("Modules {:?} are masked", self.config.masked_modules);
+        }
+        self.embassy = Some(em);
+        self.envoy_handles = Some(handles);
+        self.status.begin_session();
+        Ok(())
+    }
+
+    /// Emit a cancel signal to all of the envoys and destroy the envoys and the embassy
+    /// This can cause a small blocking period while waiting for all of the tasks to join back.
+    pub fn disconnect(&mut self) -> Result<(), SessionError> {
+        if self.run_control.is_some() {
+            return Err(SessionError::SequenceRunning);
+        }
+        let mut embassy = self.embassy.take().ok_or(SessionError::NotConnected)?;
+        embassy.shutdown();
+        self.stop_recording();
+        let handles = self
+            .envoy_handles
+            .take()
+            .expect("Handles did not exist at disconnect?");
+        for handle in handles {
+            match self.runtime.block_on(handle) {
+                Ok(()) => (),
+                Err(e) => tracing::error!("Encountered an error whilst disconnecting: {}", e),
+            }
+        }
+        self.simulation = None;
+        tracing::info!("Disconnected the embassy");
+        self.status.reset();
+        tracing::info!("Status manager reset.");
+        Ok(())
+    }
+
+    /// Read and handle any messages the embassy recieved from the envoys, publish the new status, and handle
+    /// any progress reported by a running sequence. The messages are returned so that other observers (i.e. the
+    /// GraphManager) can read them too.
+    pub fn poll(&mut self) -> Vec<EmbassyMessage> {
+        let mut messages = vec![];
+        let mut is_polled = true;
+        if let Some(embassy) = self.embassy.as_mut() {
+            match embassy.poll_messages() {
+                Ok(m) => messages = m,
+                Err(e) => tracing::error!("Embassy ran into an error polling the envoys: {}", e),
+            };
+            self.record(&messages);
+        } else if let Some(replay) = self.replay.as_mut() {
+            messages = replay.poll();
+        } else {
+            is_polled = false;
+        }
+        if is_polled {
+            self.status.handle_messages(&messages);
+            self.status_tx.send_replace(self.status.snapshot());
+            if let Some(api) = self.api.as_mut() {
+                api.events.handle_messages(&messages);
+                api.metrics.handle_messages(&messages);
+                api.is_state_stale |= !messages.is_empty();
+            }
+        }
+        self.poll_run_control();
+        self.check_disk_forecast();
+        self.data_monitor.update(
+            self.status.is_system_running(),
+            &self.status.get_router_samples(),
+            &self.config.data_monitor,
+        );
+        self.evaluate_alarms();
+        self.poll_api();
+        return messages;
+    }
+
+    /// Stop the run if the config asks for it and a data router disk is critical
+    fn check_disk_forecast(&mut self) {
+        if !self.config.disk_forecast.auto_stop
+            || self.disk_stop_tried
+            || self.embassy.is_none()
+            || self.run_control.is_some()
+            || !self.status.is_system_running()
+        {
+            return;
+        }
+        let critical = self.status.get_critical_disks(&self.config.disk_forecast);
+        if critical.is_empty() {
+            return;
+        }
+        self.disk_stop_tried = true;
+        tracing::error!(
+            "Data router disks of modules {:?} are nearly full, stopping run {}",
+            critical,
+            self.config.run_number
+        );
+        if let Err(e) = self.stop_run() {
+            tracing::error!("Could not stop the run for the full disks: {}", e);
+        }
+    }
+
+    /// Evaluate the alarm rules against the current status, and publish any changes
+    fn evaluate_alarms(&mut self) {
+        let context = AlarmContext {
+            in_run: self.config.run_started_at.is_some() && self.run_control.is_none(),
+            run_number: self.config.run_number,
+        };
+        let changes = self.alarms.evaluate(Instant::now(), &self.status, &context);
+        for change in changes {
+            self.publish_event(change.into());
+        }
+    }
+
+    /// Acknowledge an active alarm
+    pub fn acknowledge_alarm(&mut self, id: u64) -> Result<(), SessionError> {
+        let changes = self.alarms.acknowledge(id)?;
+        for change in changes {
+            self.publish_event(change.into());
+        }
+        Ok(())
+    }
+
+    /// Acknowledge every active alarm
+    pub fn acknowledge_all_alarms(&mut self) {
+        let changes = self.alarms.acknowledge_all();
+        for change in changes {
+            self.publish_event(change.into());
+        }
+    }
+
+    pub fn get_alarms(&self) -> &AlarmEngine {
+        &self.alarms
+    }
+
+    /// How the data is flowing to a module's data router, if a run is being watched
+    pub fn get_data_flow(&self, id: i32) -> Option<&DataFlow> {
+        self.data_monitor.get_flow(id)
+    }
+
+    /// The median rate of the data routers in the run (MB/s), if a run is being watched
+    pub fn get_data_median_rate(&self) -> Option<f64> {
+        self.data_monitor.get_median()
+    }
+
+    /// Forecast when the disk of a module's data router will be full, using the thresholds of the config
+    pub fn get_disk_forecast(&self, id: i32) -> Option<DiskForecast> {
+        self.status
+            .get_disk_forecast(id, &self.config.disk_forecast)
+    }
+
+    /// Write messages to the recording, if one is being made. Recording stops at the first failure.
+    fn record(&mut self, messages: &[EmbassyMessage]) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(messages) {
+                tracing::error!(
+                    "Could not record to {}, recording stopped: {}",
+                    recorder.get_path().display(),
+                    e
+                );
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Start recording the messages from the embassy, if the config asks for it
+    fn start_recording(&mut self) {
+        if !self.config.record_runs || self.recorder.is_some() {
+            return;
+        }
+        match Recorder::new(
+            &self.config.experiment,
+            self.config.run_number,
+            &self.topology,
+            &self.config.masked_modules,
+        ) {
+            Ok(recorder) => {
+                tracing::info!("Recording to {}", recorder.get_path().display());
+                self.recorder = Some(recorder);
+            }
+            Err(e) => tracing::error!("Could not start recording: {}", e),
+        }
+    }
+
+    /// Where the run in progress is being recorded, if it is
+    pub fn get_recording_path(&self) -> Option<&Path> {
+        self.recorder.as_ref().map(|recorder| recorder.get_path())
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            tracing::info!("Recording saved to {}", recorder.get_path().display());
+        }
+    }
+
+    /// Replay a recording in place of connecting to the AT-TPC. The topology and mask of the recording are used
+    /// until the replay is stopped.
+    pub fn start_replay(&mut self, path: &Path, speed: f64) -> Result<(), SessionError> {
+        if self.embassy.is_some() {
+            return Err(SessionError::Connected);
+        }
+        let recording = Recording::read(path)?;
+        tracing::info!(
+            "Replaying run {} of {} ({} messages) from {}",
+            recording.header.run_number,
+            recording.header.experiment,
+            recording.messages.len(),
+            path.display()
+        );
+        self.topology = recording.header.topology.clone();
+        self.status = StatusManager::new(&self.topology);
+        self.status.set_masked(&recording.header.masked_modules);
+        self.replay = Some(Replay::new(path, recording, speed));
+        Ok(())
+    }
+
+    /// Stop replaying, and go back to the topology of the config
+    pub fn stop_replay(&mut self) -> Result<(), SessionError> {
+        if self.replay.take().is_none() {
+            return Ok(());
+        }
+        tracing::info!("Replay stopped");
+        self.load_topology()
+    }
+
+    pub fn get_replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+
+    pub fn get_replay_mut(&mut self) -> Option<&mut Replay> {
+        self.replay.as_mut()
+    }
+
+    /// Read and handle any progress reported by a running RunController
+    fn poll_run_control(&mut self) {
+        let reports = match self.run_control.as_mut() {
+            Some(handle) => handle.poll_progress(),
+            None => return,
+        };
+        if !reports.is_empty() {
+            self.mark_api_state_stale();
+        }
+        for report in reports {
+            match report {
+                RunProgress::Step(step) => self.run_step = step,
+                RunProgress::Busy(ids) => {
+                    for id in ids {
+                        self.status.set_ecc_busy(id as usize);
+                    }
+                    self.status_tx.send_replace(self.status.snapshot());
+                }
+                RunProgress::Done(sequence, result) => {
+                    self.run_control = None;
+                    self.finish_sequence(sequence, result);
+                }
+            }
+        }
+        //The controller task died without reporting (i.e. it panicked)
+        if self.run_control.as_ref().is_some_and(|h| h.is_finished()) {
+            let handle = self.run_control.take().expect("Checked above");
+            self.run_step = format!("{} ended unexpectedly", handle.get_sequence());
+            tracing::error!("{}", self.run_step);
+            self.sequence_outcome = Some((
+                handle.get_sequence().clone(),
+                Err(RunControlError::Disconnected),
+            ));
+        }
+    }
+
+    /// Hand a sequence off to a RunController, if one is not already running
+    fn spawn_sequence(&mut self, sequence: RunSequence) -> Result<(), SessionError> {
+        if self.run_control.is_some() {
+            return Err(SessionError::SequenceRunning);
+        }
+        let embassy = self.embassy.as_ref().ok_or(SessionError::NotConnected)?;
+        let cobo_ids: Vec<i32> = self
+            .topology
+            .cobo_ids()
+            .into_iter()
+            .filter(|id| !self.status.is_masked(*id as usize))
+            .collect();
+        self.status_tx.send_replace(self.status.snapshot());
+        self.run_step = format!("Beginning {}...", sequence);
+        self.sequence_outcome = None;
+        self.run_control = Some(RunController::spawn(
+            &self.runtime,
+            sequence,
+            &self.config.experiment,
+            self.config.run_number,
+            self.topology.mutant_id(),
+            cobo_ids,
+            embassy.get_switchboard(),
+            self.status_tx.subscribe(),
+            self.simulation.clone(),
+        ));
+        Ok(())
+    }
+
+    /// Handle the outcome of a sequence. Starting a run starts the run clock, and stopping a run
+    /// records the run to the table and advances the run number. The config is autosaved after either.
+    fn finish_sequence(&mut self, sequence: RunSequence, result: Result<(), RunControlError>) {
+        match &result {
+            Err(e) => {
+                self.run_step = format!("{} failed: {}", sequence, e);
+                self.publish_event(SessionEvent::SequenceFailed {
+                    sequence: sequence.to_string(),
+                    error: e.to_string(),
+                });
+                if let RunSequence::Start = sequence {
+                    self.stop_recording();
+                    self.close_run_log();
+                }
+            }
+            Ok(()) => {
+                self.run_step = format!("{} complete", sequence);
+                match sequence {
+                    RunSequence::Start => {
+                        tracing::info!("Run {} successfully started!", self.config.run_number);
+                        self.disk_stop_tried = false;
+                        self.config.start_run_clock();
+                        self.autosave_config();
+                        self.publish_event(SessionEvent::RunStarted {
+                            run_number: self.config.run_number,
+                        });
+                    }
+                    RunSequence::Stop => {
+                        tracing::info!("Run {} stopped!", self.config.run_number);
+                        self.stop_recording();
+                        self.close_run_log();
+                        tracing::info!("Saving config to table...");
+                        let duration = self.config.get_run_duration().unwrap_or(Duration::ZERO);
+                        self.config.write_table(duration);
+                        tracing::info!("Config saved to table.");
+
+                        self.publish_event(SessionEvent::RunStopped {
+                            run_number: self.config.run_number,
+                            duration_secs: duration.as_secs(),
+                        });
+
+                        self.config.run_started_at = None;
+                        self.config.run_number += 1;
+                        self.autosave_config();
+                    }
+                    RunSequence::Progress(_) => (),
+                }
+            }
+        }
+        self.sequence_outcome = Some((sequence, result));
+    }
+
+    /// Push an event to the subscribers of the control API, if it is running
+    fn publish_event(&mut self, event: SessionEvent) {
+        if let Some(api) = self.api.as_mut() {
+            api.events.publish(event);
+            api.is_state_stale = true;
+        }
+    }
+
+    /// Have the state of the control API published on the next poll, if it is running
+    fn mark_api_state_stale(&mut self) {
+        if let Some(api) = self.api.as_mut() {
+            api.is_state_stale = true;
+        }
+    }
+
+    /// Open the log of the current run, if there is a run log sink
+    fn open_run_log(&self) {
+        if let Some(run_log) = self.run_log.as_ref() {
+            if let Err(e) = run_log.open(
+                &self.config.experiment,
+                self.config.run_number,
+                &self.config.get_log_retention(),
+            ) {
+                tracing::error!(
+                    "Could not open the log for run {}: {}",
+                    self.config.run_number,
+                    e
+                );
+            }
+        }
+    }
+
+    fn is_run_log_open(&self) -> bool {
+        self.run_log
+            .as_ref()
+            .is_some_and(|run_log| run_log.is_open())
+    }
+
+    fn close_run_log(&self) {
+        if let Some(run_log) = self.run_log.as_ref() {
+            run_log.close();
+        }
+    }
+
+    fn autosave_config(&self) {
+        match self.write_config() {
+            Ok(()) => tracing::info!("Config autosaved to {}", self.config.config_path.display()),
+            Err(e) => tracing::error!("Could not autosave the config: {}", e),
+        }
+    }
+
+    /// Send a transition command to some of the ECC operation envoys. Transitions are either forward or backward
+    /// depending on the is_forward flag. What type of transition is determined by the current state of the envoy as last recorded
+    /// by the status envoy. Returns the number of operations submitted.
+    fn transition_ecc(&mut self, ids: Vec<usize>, is_forward: bool) -> usize {
+        let mut submitted = 0;
+        for id in ids {
+            if self.status.is_masked(id) {
+                continue;
+            }
+            match self.submit_transition(id, is_forward) {
+                Ok(()) => submitted += 1,
+                Err(SessionError::InvalidState(_, _)) => (),
+                Err(e) => tracing::error!(module = id, "Could not transition module {}: {}", id, e),
+            }
+        }
+        return submitted;
+    }
+
+    /// Submit the forward or backward transition of a module, as determined by its last recorded state
+    fn submit_transition(&mut self, id: usize, is_forward: bool) -> Result<(), SessionError> {
+        let embassy = self.embassy.as_mut().ok_or(SessionError::NotConnected)?;
+        let status = self.status.get_ecc_status(id);
+        let operation = if is_forward {
+            status.get_forward_operation()
+        } else {
+            status.get_backward_operation()
+        };
+        if let ECCOperation::Invalid = operation {
+            return Err(SessionError::InvalidState(
+                format!("transition module {id}"),
+                status,
+            ));
+        }
+        let op_id = embassy.submit_operation(operation, id as i32)?;
+        tracing::info!("Submitted operation {} to module {}", op_id, id);
+        self.status.set_ecc_busy(id);
+        Ok(())
+    }
+
+    /// Check that a single module can be operated on
+    fn check_module(&self, id: usize) -> Result<(), SessionError> {
+        if id >= self.topology.number_of_modules() {
+            return Err(SessionError::NoModule(id));
+        }
+        if self.embassy.is_none() {
+            return Err(SessionError::NotConnected);
+        }
+        if self.run_control.is_some() {
+            return Err(SessionError::SequenceRunning);
+        }
+        if self.status.is_masked(id) {
+            return Err(SessionError::ModuleMasked(id));
+        }
+        Ok(())
+    }
+
+    /// Transition a single module forward (Progress). The CoBos can only be described once the MuTaNT is prepared,
+    /// and the MuTaNT can only be configured once all of the CoBos are ready.
+    pub fn progress_module(&mut self, id: usize) -> Result<(), SessionError> {
+        self.check_module(id)?;
+        if !self.status.can_ecc_go_forward(id) {
+            return Err(SessionError::InvalidState(
+                format!("progress module {id}"),
+                self.status.get_ecc_status(id),
+            ));
+        }
+        self.submit_transition(id, true)
+    }
+
+    /// Transition a single module backward (Regress)
+    pub fn regress_module(&mut self, id: usize) -> Result<(), SessionError> {
+        self.check_module(id)?;
+        let status = self.status.get_ecc_status(id);
+        if !status.can_go_backward() {
+            return Err(SessionError::InvalidState(
+                format!("regress module {id}"),
+                status,
+            ));
+        }
+        self.submit_transition(id, false)
+    }
+
+    /// Transition all of the envoys forward (Progress)
+    /// This is slightly more complicated as order matters for two of the phases (Prepare and Configure),
+    /// so it is handed off to a RunController.
+    pub fn progress_system(&mut self) -> Result<(), SessionError> {
+        let system = self.status.get_system_ecc_status();
+        match system.get_forward_operation() {
+            ECCOperation::Invalid => Err(SessionError::InvalidState(
+                String::from("progress the system"),
+                system,
+            )),
+            op => self.spawn_sequence(RunSequence::Progress(op)),
+        }
+    }
+
+    /// Transition all of the envoys backward (Regress). Returns the number of operations submitted.
+    pub fn regress_system(&mut self) -> Result<usize, SessionError> {
+        if self.embassy.is_none() {
+            return Err(SessionError::NotConnected);
+        }
+        if self.run_control.is_some() {
+            return Err(SessionError::SequenceRunning);
+        }
+        let system = self.status.get_system_ecc_status();
+        if !system.can_go_backward() {
+            return Err(SessionError::InvalidState(
+                String::from("regress the system"),
+                system,
+            ));
+        }
+        let ids: Vec<usize> = self
+            .topology
+            .module_ids()
+            .iter()
+            .map(|id| *id as usize)
+            .collect();
+        Ok(self.transition_ecc(ids, false))
+    }
+
+    /// Begin a start run sequence. The RunController checks that the run number was not already used,
+    /// re-configures the MuTaNT to reset the timestamps, and then starts the CoBos followed by the MuTaNT.
+    pub fn start_run(&mut self) -> Result<(), SessionError> {
+        if !self.status.is_system_ready() {
+            return Err(SessionError::InvalidState(
+                String::from("start a run"),
+                self.status.get_system_ecc_status(),
+            ));
+        }
+        if self.embassy.is_none() {
+            return Err(SessionError::NotConnected);
+        }
+        //The ECCs are already Ready while a stop sequence moves the data, and that run's log must stay open
+        if self.run_control.is_some() {
+            return Err(SessionError::SequenceRunning);
+        }
+        let log_was_open = self.is_run_log_open();
+        let was_recording = self.recorder.is_some();
+        self.open_run_log();
+        self.start_recording();
+        tracing::info!("Starting run {} ...", self.config.run_number);
+        if !self.config.masked_modules.is_empty() {
+            tracing::warn!(
+                "Run {} is being taken with modules {:?} masked",
+                self.config.run_number,
+                self.config.masked_modules
+            );
+        }
+        let result = self.spawn_sequence(RunSequence::Start);
+        if result.is_err() {
+            if !was_recording {
+                self.stop_recording();
+            }
+            if !log_was_open {
+                self.close_run_log();
+            }
+        }
+        result
+    }
+
+    /// Begin a stop run sequence. The RunController stops the MuTaNT and then the CoBos, moves the data
+    /// to a run specific location, and backs up the ECC configuration files. The run is written to the table
+    /// once the sequence completes.
+    pub fn stop_run(&mut self) -> Result<(), SessionError> {
+        if !self.status.is_system_running() {
+            return Err(SessionError::InvalidState(
+                String::from("stop a run"),
+                self.status.get_system_ecc_status(),
+            ));
+        }
+        if self.embassy.is_none() {
+            return Err(SessionError::NotConnected);
+        }
+        if self.run_control.is_some() {
+            return Err(SessionError::SequenceRunning);
+        }
+        self.open_run_log();
+        tracing::info!("Stopping run {} ...", self.config.run_number);
+        self.spawn_sequence(RunSequence::Stop)
+    }
+
+    /// Ask the running sequence to stop at the next opportunity
+    pub fn abort_sequence(&self) -> Result<(), SessionError> {
+        let handle = self
+            .run_control
+            .as_ref()
+            .ok_or(SessionError::NoSequenceRunning)?;
+        tracing::warn!("Aborting {}", handle.get_sequence());
+        handle.abort();
+        Ok(())
+    }
+
+    /// Serve the control API at the address given by the config, if any. A server already running at another address
+    /// is stopped. Does nothing if the API is already served at the address.
+    pub fn start_api(&mut self) -> Result<(), SessionError> {
+        let address = match self.config.api_address {
+            Some(address) => address,
+            None => {
+                self.stop_api();
+                return Ok(());
+            }
+        };
+        if self.api.as_ref().is_some_and(|api| api.address == address) {
+            return Ok(());
+        }
+        self.stop_api();
+
+        let (state_tx, state_rx) = watch::channel(self.api_state());
+        let (request_tx, request_rx) = mpsc::channel(API_REQUEST_CAPACITY);
+        let events = EventPublisher::new();
+        let server = {
+            let _guard = self.runtime.enter();
+            ApiServer::new(state_rx, request_tx, events.get_sender())
+                .bind(&address)
+                .map_err(SessionError::ApiBindError)?
+        };
+        self.api = Some(ApiHandle {
+            address,
+            state: state_tx,
+            is_state_stale: false,
+            state_published_at: Instant::now(),
+            requests: request_rx,
+            events,
+            metrics: Metrics::default(),
+            task: self.runtime.spawn(server),
+        });
+        Ok(())
+    }
+
+    /// Stop serving the control API
+    pub fn stop_api(&mut self) {
+        if let Some(api) = self.api.take() {
+            api.task.abort();
+            tracing::info!("Control API at {} stopped", api.address);
+        }
+    }
+
+    /// The address the control API is served at, if it is running
+    pub fn get_api_address(&self) -> Option<SocketAddr> {
+        self.api.as_ref().map(|api| api.address)
+    }
+
+    /// Act on any commands received by the control API, and publish the current state to it. The state is only
+    /// rebuilt when something changed since it was last published, or when it has not been refreshed for a while
+    /// (the disk forecasts, data flow, and config can change without any event).
+    fn poll_api(&mut self) {
+        let mut requests = vec![];
+        match self.api.as_mut() {
+            Some(api) => {
+                while let Ok(request) = api.requests.try_recv() {
+                    requests.push(request);
+                }
+            }
+            None => return,
+        }
+        if !requests.is_empty() {
+            self.mark_api_state_stale();
+        }
+        for request in requests {
+            let result = self.execute_api_command(&request.command);
+            if let Err(e) = &result {
+                tracing::warn!("Control API request {} refused: {}", request.command, e);
+            }
+            let _ = request.reply.send(result);
+        }
+        let is_due = self.api.as_ref().is_some_and(|api| {
+            api.is_state_stale || api.state_published_at.elapsed() >= API_STATE_REFRESH
+        });
+        if !is_due {
+            return;
+        }
+        let state = self.api_state();
+        if let Some(api) = self.api.as_mut() {
+            api.state.send_replace(state);
+            api.is_state_stale = false;
+            api.state_published_at = Instant::now();
+        }
+    }
+
+    fn execute_api_command(&mut self, command: &ApiCommand) -> Result<String, SessionError> {
+        match command {
+            ApiCommand::StartRun => {
+                self.start_run()?;
+                Ok(format!("Starting run {}", self.config.run_number))
+            }
+            ApiCommand::StopRun => {
+                self.stop_run()?;
+                Ok(format!("Stopping run {}", self.config.run_number))
+            }
+            ApiCommand::AbortSequence => {
+                self.abort_sequence()?;
+                Ok(String::from("Abort requested"))
+            }
+            ApiCommand::ProgressSystem => {
+                self.progress_system()?;
+                Ok(String::from("Progressing the system"))
+            }
+            ApiCommand::RegressSystem => {
+                let submitted = self.regress_system()?;
+                Ok(format!("Submitted {submitted} operations"))
+            }
+            ApiCommand::ProgressModule(id) => {
+                self.progress_module(*id)?;
+                Ok(format!("Progressing module {id}"))
+            }
+            ApiCommand::RegressModule(id) => {
+                self.regress_module(*id)?;
+                Ok(format!("Regressing module {id}"))
+            }
+            ApiCommand::AcknowledgeAlarm(id) => {
+                self.acknowledge_alarm(*id)?;
+                Ok(format!("Acknowledged alarm {id}"))
+            }
+            ApiCommand::AcknowledgeAllAlarms => {
+                self.acknowledge_all_alarms();
+                Ok(String::from("Acknowledged all alarms"))
+            }
+        }
+    }
+
+    /// Everything the control API reports about the session
+    fn api_state(&self) -> ApiState {
+        let status = &self.status;
+        let ecc = status
+            .get_ecc_status_response()
+            .iter()
+            .enumerate()
+            .map(|(id, response)| ApiECCStatus {
+                id: id as i32,
+                role: match self.topology.get_module(id as i32) {
+                    Some(module) => module.role.to_string(),
+                    None => String::new(),
+                },
+                masked: status.is_masked(id),
+                state: ECCStatus::from(response.state).to_string(),
+                state_code: response.state,
+                connection: response.connection.clone(),
+                last_error: status.get_ecc_error(id).cloned(),
+                pending_operation: self
+                    .get_pending_operation(id as i32)
+                    .map(|p| format!("{} {}", p.operation, p.op_id)),
+            })
+            .collect();
+        let surveyors = status
+            .get_surveyor_ids()
+            .iter()
+            .zip(status.get_surveyor_status_response().iter())
+            .map(|(id, response)| {
+                let forecast = self.get_disk_forecast(*id);
+                ApiSurveyorStatus {
+                    id: *id,
+                    masked: status.is_masked(*id as usize),
+                    state: SurveyorState::from(response.state).to_string(),
+                    response: response.clone(),
+                    time_to_full_secs: forecast
+                        .as_ref()
+                        .and_then(|f| f.time_to_full)
+                        .map(|t| t.as_secs()),
+                    disk_alarm: forecast.map(|f| f.level.to_string()),
+                    data_flow: self.get_data_flow(*id).map(|f| f.to_string()),
+                }
+            })
+            .collect();
+        return ApiState {
+            system_ecc: status.get_system_ecc_status().to_string(),
+            system_surveyor: status.get_surveyor_system_status().to_string(),
+            ecc,
+            surveyors,
+            run: ApiRunState {
+                connected: self.is_connected(),
+                simulated: self.is_simulated(),
+                running: status.is_system_running(),
+                run_number: self.config.run_number,
+                run_duration_secs: self.config.get_run_duration().map(|d| d.as_secs()),
+                sequence_running: self.is_sequence_running(),
+                run_step: self.run_step.clone(),
+            },
+            alarms: ApiAlarms {
+                active: self.alarms.get_active().into_iter().cloned().collect(),
+                history: self.alarms.get_history().iter().cloned().collect(),
+            },
+            config: self.config.clone(),
+            metrics: match self.api.as_ref() {
+                Some(api) => api.metrics.clone(),
+                None => Metrics::default(),
+            },
+        };
+    }
+
+    /// The outcome of the last sequence which finished, if it has not been taken yet
+    pub fn take_sequence_outcome(&mut self) -> Option<(RunSequence, Result<(), RunControlError>)> {
+        self.sequence_outcome.take()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.embassy.is_some()
+    }
+
+    /// Connected to a Simulation rather than the AT-TPC
+    pub fn is_simulated(&self) -> bool {
+        self.simulation.is_some()
+    }
+
+    pub fn is_sequence_running(&self) -> bool {
+        self.run_control.is_some()
+    }
+
+    /// Whether any module has an ECC operation in flight
+    pub fn has_pending_operations(&self) -> bool {
+        match self.embassy.as_ref() {
+            Some(em) => em.has_pending_operations(),
+            None => false,
+        }
+    }
+
+    pub fn get_pending_operation(&self, id: i32) -> Option<PendingOperation> {
+        self.embassy.as_ref()?.get_pending_operation(id)
+    }
+
+    /// A description of the last step taken by the running (or last) sequence
+    pub fn get_run_step(&self) -> &str {
+        &self.run_step
+    }
+
+    pub fn get_topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    pub fn get_status(&self) -> &StatusManager {
+        &self.status
+    }
+}
@@ -0,0 +1,525 @@
+use super::data_monitor::RouterSample;
+use super::disk_forecast::{DiskAlarmLevel, DiskForecast, DiskForecastOptions, DiskForecaster};
+use crate::envoy::connection_status::ConnectionStatus;
+use crate::envoy::constants::{STALE_POLL_INTERVALS, STATUS_POLL_SECS};
+use crate::envoy::ecc_envoy::ECCStatusResponse;
+use crate::envoy::ecc_operation::ECCStatus;
+use crate::envoy::message::EmbassyMessage;
+use crate::envoy::run_controller::StatusSnapshot;
+use crate::envoy::supervisor::{EnvoyHealth, EnvoyKind, EnvoyTaskState};
+use crate::envoy::surveyor_envoy::SurveyorResponse;
+use crate::envoy::surveyor_state::SurveyorState;
+use crate::envoy::topology::Topology;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// # Status Manager
+/// Structure used to manage the status of all of the envoys. We need a centralized location
+/// because we also want to express the status of the entire system, not just the individuals.
+/// It has observer-like behavior where it reads a list of messages from the embassy and handles
+/// the information appropriately.
+///
+/// ECC status is indexed by module id. Surveyor status is stored in the order of surveyor_ids, as not every
+/// module has a data router (i.e. the MuTaNT). Masked modules are ignored when evaluating the system status.
+///
+/// For every module the time of the last update (any message from its status envoy) and of the last contact
+/// (an update where the server was actually reached) are kept. A module is stale if it has not had an update
+/// within STALE_POLL_INTERVALS poll intervals.
+///
+/// The health of every envoy task, as reported by its Supervisor, is kept by module id and envoy kind.
+///
+/// The recent data rates of the data routers are kept to forecast when their disks will be full.
+#[derive(Debug)]
+pub struct StatusManager {
+    ecc_status: Vec<ECCStatusResponse>,
+    surveyor_status: Vec<SurveyorResponse>,
+    ecc_holds: Vec<bool>,
+    ecc_errors: Vec<Option<String>>,
+    ecc_failures: Vec<u32>,
+    ecc_last_update: Vec<Option<Instant>>,
+    ecc_last_contact: Vec<Option<Instant>>,
+    surveyor_last_update: Vec<Option<Instant>>,
+    surveyor_last_contact: Vec<Option<Instant>>,
+    envoy_health: HashMap<(i32, EnvoyKind), EnvoyHealth>,
+    disk_forecaster: DiskForecaster,
+    masked: Vec<bool>,
+    mutant_id: usize,
+    cobo_ids: Vec<usize>,
+    surveyor_ids: Vec<i32>,
+}
+
+impl StatusManager {
+    pub fn new(topology: &Topology) -> Self {
+        let n_modules = topology.number_of_modules();
+        let surveyor_ids = topology.surveyor_ids();
+        let eccs = vec![ECCStatusResponse::default(); n_modules];
+        let surs = vec![SurveyorResponse::default(); surveyor_ids.len()];
+        let holds = vec![false; n_modules];
+        return Self {
+            ecc_status: eccs,
+            surveyor_status: surs,
+            ecc_holds: holds,
+            ecc_errors: vec![None; n_modules],
+            ecc_failures: vec![0; n_modules],
+            ecc_last_update: vec![None; n_modules],
+            ecc_last_contact: vec![None; n_modules],
+            surveyor_last_update: vec![None; surveyor_ids.len()],
+            surveyor_last_contact: vec![None; surveyor_ids.len()],
+            envoy_health: HashMap::new(),
+            disk_forecaster: DiskForecaster::new(),
+            masked: vec![false; n_modules],
+            mutant_id: topology.mutant_id() as usize,
+            cobo_ids: topology.cobo_ids().iter().map(|id| *id as usize).collect(),
+            surveyor_ids,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        for eccs in self.ecc_status.iter_mut() {
+            *eccs = ECCStatusResponse::default();
+        }
+
+        for surs in self.surveyor_status.iter_mut() {
+            *surs = SurveyorResponse::default();
+        }
+
+        for hold in self.ecc_holds.iter_mut() {
+            *hold = false;
+        }
+
+        for error in self.ecc_errors.iter_mut() {
+            *error = None;
+        }
+
+        for time in self
+            .ecc_last_update
+            .iter_mut()
+            .chain(self.ecc_last_contact.iter_mut())
+            .chain(self.surveyor_last_update.iter_mut())
+            .chain(self.surveyor_last_contact.iter_mut())
+        {
+            *time = None;
+        }
+
+        self.envoy_health.clear();
+        self.disk_forecaster.reset();
+    }
+
+    /// Start the clocks used to find stale modules. Call once the envoys are connected.
+    pub fn begin_session(&mut self) {
+        let now = Some(Instant::now());
+        for time in self
+            .ecc_last_update
+            .iter_mut()
+            .chain(self.surveyor_last_update.iter_mut())
+        {
+            *time = now;
+        }
+    }
+
+    /// Read messages from the embassy and look for ECC or Surveyor status respsonses.
+    /// Set the status of the given module to match the message. Any ECC operation response, successful or not,
+    /// releases the hold on the module; failures are kept so that the UI can show why.
+    pub fn handle_messages(&mut self, messages: &[EmbassyMessage]) {
+        for message in messages {
+            match message {
+                EmbassyMessage::ECCOperationResponse {
+                    id,
+                    op_id,
+                    operation,
+                    response,
+                    ..
+                } => {
+                    let idx = *id as usize;
+                    if response.error_code != 0 {
+                        tracing::error!(
+                            module = id,
+                            envoy = %EnvoyKind::ECCTransition,
+                            "ECC Operation {} {} failed with error code {} for module id {}: {}",
+                            operation,
+                            op_id,
+                            response.error_code,
+                            id,
+                            response.error_message
+                        );
+                        self.ecc_errors[idx] = Some(format!(
+                            "{} failed ({}): {}",
+                            operation, response.error_code, response.error_message
+                        ));
+                        self.ecc_failures[idx] += 1;
+                    } else {
+                        tracing::info!(
+                            "ECC Operation {} {} completed for module id {}",
+                            operation,
+                            op_id,
+                            id
+                        );
+                        self.ecc_errors[idx] = None;
+                    }
+                    self.ecc_holds[idx] = false;
+                }
+                EmbassyMessage::ECCStatus { id, response, .. } => {
+                    if response.error_code != 0 {
+                        tracing::error!(
+                            module = id,
+                            envoy = %EnvoyKind::ECCStatus,
+                            "ECC Status failed with error code {} for module id {}: {}",
+                            response.error_code,
+                            id,
+                            response.error_message
+                        )
+                    }
+
+                    let idx = *id as usize;
+                    self.ecc_last_update[idx] = Some(Instant::now());
+                    if !response.connection.is_failure() {
+                        self.ecc_last_contact[idx] = Some(Instant::now());
+                    }
+                    if !self.ecc_holds[idx] {
+                        self.ecc_status[idx] = response.clone();
+                    }
+                }
+                EmbassyMessage::Surveyor { id, response, .. } => {
+                    if let Some(idx) = self.surveyor_ids.iter().position(|sid| sid == id) {
+                        self.surveyor_last_update[idx] = Some(Instant::now());
+                        if !response.connection.is_failure() {
+                            self.surveyor_last_contact[idx] = Some(Instant::now());
+                        }
+                        self.surveyor_status[idx] = response.clone();
+                        self.disk_forecaster.add_response(*id, response);
+                    }
+                }
+                EmbassyMessage::EnvoyHealth { id, health } => {
+                    self.handle_envoy_health(*id, health.clone());
+                }
+                _ => {
+                    tracing::warn!("Some how recieved a message of kind {} which is not a valid recieving kind!", message.get_kind());
+                }
+            }
+        }
+    }
+
+    /// Record the health of an envoy task. If a transition envoy died while the module was held for an operation,
+    /// the operation is lost: release the hold and count it as a failure.
+    fn handle_envoy_health(&mut self, module_id: i32, health: EnvoyHealth) {
+        let idx = module_id as usize;
+        if health.kind == EnvoyKind::ECCTransition
+            && health.state == EnvoyTaskState::Restarting
+            && self.ecc_holds.get(idx).copied().unwrap_or(false)
+        {
+            self.ecc_errors[idx] = Some(format!(
+                "Transition envoy died: {}",
+                health.last_error.clone().unwrap_or_default()
+            ));
+            self.ecc_failures[idx] += 1;
+            self.ecc_holds[idx] = false;
+        }
+        self.envoy_health.insert((module_id, health.kind), health);
+    }
+
+    /// The health of every envoy task, ordered by module id and then envoy kind
+    pub fn get_envoy_health(&self) -> Vec<(i32, &EnvoyHealth)> {
+        let mut health: Vec<(i32, &EnvoyHealth)> = self
+            .envoy_health
+            .iter()
+            .map(|((id, _), h)| (*id, h))
+            .collect();
+        health.sort_by_key(|(id, h)| (*id, h.kind as u8));
+        return health;
+    }
+
+    /// Total number of envoy restarts this session
+    pub fn get_total_restarts(&self) -> u32 {
+        self.envoy_health.values().map(|h| h.restarts).sum()
+    }
+
+    pub fn get_ecc_status_response(&self) -> &[ECCStatusResponse] {
+        &self.ecc_status
+    }
+
+    /// Retrieve the system status. System status matches the envoy status if all
+    /// envoys have the same status. If not, the system status is Inconsistent.
+    pub fn get_system_ecc_status(&self) -> ECCStatus {
+        let mut active = self
+            .ecc_status
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !self.masked[*id])
+            .map(|(_, status)| status.state);
+        let sys_status = match active.next() {
+            Some(state) => state,
+            None => return ECCStatus::Offline,
+        };
+        for state in active {
+            if sys_status != state {
+                return ECCStatus::Inconsistent;
+            }
+        }
+        return ECCStatus::from(sys_status);
+    }
+
+    pub fn is_system_ready(&self) -> bool {
+        let sys_stat = self.get_system_ecc_status();
+        match sys_stat {
+            ECCStatus::Ready => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_system_running(&self) -> bool {
+        let sys_stat = self.get_system_ecc_status();
+        match sys_stat {
+            ECCStatus::Running => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_all_but_mutant_ready(&self) -> bool {
+        self.is_all_but_mutant(ECCStatus::Ready)
+    }
+
+    /// Check if every CoBo (all modules except the MuTaNT) is at the expected status
+    fn is_all_but_mutant(&self, expected: ECCStatus) -> bool {
+        for id in self.cobo_ids.iter() {
+            if self.masked[*id] {
+                continue;
+            }
+            if self.get_ecc_status(*id) != expected {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    pub fn get_surveyor_status_response(&self) -> &[SurveyorResponse] {
+        &self.surveyor_status
+    }
+
+    /// Module ids matching the entries of get_surveyor_status_response
+    pub fn get_surveyor_ids(&self) -> &[i32] {
+        &self.surveyor_ids
+    }
+
+    /// Surveyor status of only the modules which are not masked. This is what should be handed
+    /// to commands which operate on the data routers.
+    pub fn get_active_surveyor_status_response(&self) -> Vec<SurveyorResponse> {
+        self.surveyor_ids
+            .iter()
+            .zip(self.surveyor_status.iter())
+            .filter(|(id, _)| !self.masked[**id as usize])
+            .map(|(_, status)| status.clone())
+            .collect()
+    }
+
+    /// Whether every unmasked ECC server and Surveyor has answered (or failed to answer) at least one status query
+    pub fn has_reported_all(&self) -> bool {
+        let eccs = self
+            .ecc_status
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !self.masked[*id])
+            .all(|(_, status)| status.connection != ConnectionStatus::NoContact);
+        let surveyors = self
+            .surveyor_ids
+            .iter()
+            .zip(self.surveyor_status.iter())
+            .filter(|(id, _)| !self.masked[**id as usize])
+            .all(|(_, status)| status.connection != ConnectionStatus::NoContact);
+        return eccs && surveyors;
+    }
+
+    /// Ids of the unmasked modules whose ECC server or Surveyor could not be reached by the last status query,
+    /// or has never been heard from
+    pub fn get_unreachable_modules(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self
+            .ecc_status
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !self.masked[*id])
+            .filter(|(_, status)| {
+                status.connection.is_failure() || status.connection == ConnectionStatus::NoContact
+            })
+            .map(|(id, _)| id as i32)
+            .collect();
+        for (id, status) in self.surveyor_ids.iter().zip(self.surveyor_status.iter()) {
+            if self.masked[*id as usize] || ids.contains(id) {
+                continue;
+            }
+            if status.connection.is_failure() || status.connection == ConnectionStatus::NoContact {
+                ids.push(*id);
+            }
+        }
+        ids.sort();
+        return ids;
+    }
+
+    /// A copy of the current status to hand off to the RunController
+    pub fn snapshot(&self) -> StatusSnapshot {
+        StatusSnapshot {
+            ecc: (0..self.ecc_status.len())
+                .map(|id| self.get_ecc_status(id))
+                .collect(),
+            failures: self.ecc_failures.clone(),
+            surveyors: self.get_active_surveyor_status_response(),
+        }
+    }
+
+    /// Set which modules are masked. The MuTaNT can never be masked.
+    pub fn set_masked(&mut self, masked_ids: &[i32]) {
+        for (id, mask) in self.masked.iter_mut().enumerate() {
+            *mask = id != self.mutant_id && masked_ids.contains(&(id as i32));
+        }
+    }
+
+    pub fn is_masked(&self, id: usize) -> bool {
+        match self.masked.get(id) {
+            Some(mask) => *mask,
+            None => false,
+        }
+    }
+
+    pub fn get_ecc_status(&self, id: usize) -> ECCStatus {
+        return ECCStatus::from(self.ecc_status[id].state);
+    }
+
+    pub fn set_ecc_busy(&mut self, id: usize) {
+        if id >= self.ecc_status.len() {
+            return;
+        }
+
+        self.ecc_status[id].state = ECCStatus::Busy.into();
+        self.ecc_holds[id] = true;
+        self.ecc_errors[id] = None;
+    }
+
+    /// The reason the last operation on a module failed, if it did
+    pub fn get_ecc_error(&self, id: usize) -> Option<&String> {
+        match self.ecc_errors.get(id) {
+            Some(error) => error.as_ref(),
+            None => None,
+        }
+    }
+
+    pub fn can_ecc_go_forward(&self, id: usize) -> bool {
+        if self.is_masked(id) {
+            return false;
+        }
+        let status = self.get_ecc_status(id);
+        if status == ECCStatus::Described && id != (self.mutant_id) {
+            match self.get_ecc_status(self.mutant_id) {
+                ECCStatus::Prepared => return true,
+                ECCStatus::Ready => return true,
+                _ => return false,
+            }
+        } else if status == ECCStatus::Prepared && id == (self.mutant_id) {
+            return self.is_all_but_mutant_ready();
+        } else {
+            return status.can_go_forward();
+        }
+    }
+
+    /// Retrieve the system status. System status matches the envoy status if all
+    /// envoys have the same status. If not, the system status is Inconsistent.
+    pub fn get_surveyor_system_status(&self) -> SurveyorState {
+        let mut active = self
+            .surveyor_ids
+            .iter()
+            .zip(self.surveyor_status.iter())
+            .filter(|(id, _)| !self.masked[**id as usize])
+            .map(|(_, status)| status.state);
+        let sys_status = match active.next() {
+            Some(state) => state,
+            None => return SurveyorState::Offline,
+        };
+        for state in active {
+            if sys_status != state {
+                return SurveyorState::Inconsistent;
+            }
+        }
+        return SurveyorState::from(sys_status);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_surveyor_status(&self, id: i32) -> SurveyorState {
+        match self.surveyor_ids.iter().position(|sid| *sid == id) {
+            Some(idx) => SurveyorState::from(self.surveyor_status[idx].state),
+            None => SurveyorState::Invalid,
+        }
+    }
+
+    /// Time since the ECC server of a module was last reached, if it ever was
+    pub fn get_ecc_last_contact(&self, id: usize) -> Option<Duration> {
+        self.ecc_last_contact.get(id)?.map(|t| t.elapsed())
+    }
+
+    pub fn is_ecc_stale(&self, id: usize) -> bool {
+        match self.ecc_last_update.get(id) {
+            Some(Some(time)) => !self.is_masked(id) && Self::is_stale(time),
+            _ => false,
+        }
+    }
+
+    /// Time since the Surveyor of a module was last reached, if it ever was
+    pub fn get_surveyor_last_contact(&self, id: i32) -> Option<Duration> {
+        let idx = self.surveyor_ids.iter().position(|sid| *sid == id)?;
+        self.surveyor_last_contact[idx].map(|t| t.elapsed())
+    }
+
+    pub fn is_surveyor_stale(&self, id: i32) -> bool {
+        match self.surveyor_ids.iter().position(|sid| *sid == id) {
+            Some(idx) => match self.surveyor_last_update[idx] {
+                Some(time) => !self.is_masked(id as usize) && Self::is_stale(&time),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Forecast when the disk of a module's data router will be full, if there is enough to go on
+    pub fn get_disk_forecast(
+        &self,
+        id: i32,
+        options: &DiskForecastOptions,
+    ) -> Option<DiskForecast> {
+        let idx = self.surveyor_ids.iter().position(|sid| *sid == id)?;
+        self.disk_forecaster
+            .forecast(id, &self.surveyor_status[idx], options)
+    }
+
+    /// Ids of the unmasked modules whose data router disk is critical: full, or forecast to be soon
+    pub fn get_critical_disks(&self, options: &DiskForecastOptions) -> Vec<i32> {
+        self.surveyor_ids
+            .iter()
+            .filter(|id| !self.is_masked(**id as usize))
+            .filter(|id| match self.get_disk_forecast(**id, options) {
+                Some(forecast) => forecast.level == DiskAlarmLevel::Critical,
+                None => false,
+            })
+            .copied()
+            .collect()
+    }
+
+    /// The latest readings of the unmasked, online data routers, with their rates averaged as for the disk forecast
+    pub fn get_router_samples(&self) -> Vec<RouterSample> {
+        self.surveyor_ids
+            .iter()
+            .zip(self.surveyor_status.iter())
+            .filter(|(id, status)| !self.is_masked(**id as usize) && status.state == 1)
+            .filter_map(|(id, status)| {
+                Some(RouterSample {
+                    id: *id,
+                    bytes_used: status.bytes_used,
+                    rate: self
+                        .disk_forecaster
+                        .get_rate(*id)
+                        .unwrap_or(status.data_rate),
+                    measured_at: status.measured_at?,
+                })
+            })
+            .collect()
+    }
+
+    fn is_stale(last_update: &Instant) -> bool {
+        last_update.elapsed() > Duration::from_secs(STATUS_POLL_SECS * STALE_POLL_INTERVALS)
+    }
+}
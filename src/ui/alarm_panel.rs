@@ -0,0 +1,142 @@
+use super::log_console::format_clock_time;
+use crate::session::alarms::{Alarm, AlarmEngine, AlarmSeverity};
+use eframe::egui::{Color32, RichText};
+
+/// Banner backgrounds for the most severe unacknowledged alarm
+const CRITICAL_BANNER_FILL: Color32 = Color32::from_rgb(90, 10, 10);
+const WARNING_BANNER_FILL: Color32 = Color32::from_rgb(80, 60, 0);
+
+fn alarm_state(alarm: &Alarm) -> &'static str {
+    match (alarm.acknowledged_at.is_some(), alarm.condition_present) {
+        (false, true) => "Active",
+        (false, false) => "Cleared, not acknowledged",
+        (true, _) => "Acknowledged",
+    }
+}
+
+/// # AlarmPanel
+/// The alarm banner, shown across the top of the window while any alarm is active, and the Alarm History window.
+/// The banner is coloured by the most severe alarm which has not been acknowledged.
+#[derive(Debug, Default)]
+pub struct AlarmPanel {
+    pub is_history_open: bool,
+}
+
+impl AlarmPanel {
+    pub fn new() -> Self {
+        return Self {
+            is_history_open: false,
+        };
+    }
+
+    /// Show the banner if there are active alarms. Returns the ids of the alarms the user acknowledged.
+    pub fn show_banner(&mut self, ctx: &eframe::egui::Context, alarms: &AlarmEngine) -> Vec<u64> {
+        let active = alarms.get_active();
+        let mut acknowledged = vec![];
+        if active.is_empty() {
+            return acknowledged;
+        }
+        let unacknowledged: Vec<&&Alarm> = active
+            .iter()
+            .filter(|a| a.acknowledged_at.is_none())
+            .collect();
+        let mut frame = eframe::egui::Frame::side_top_panel(&ctx.style());
+        match unacknowledged.iter().map(|a| a.severity).max() {
+            Some(AlarmSeverity::Critical) => frame = frame.fill(CRITICAL_BANNER_FILL),
+            Some(AlarmSeverity::Warning) => frame = frame.fill(WARNING_BANNER_FILL),
+            _ => (),
+        }
+        eframe::egui::TopBottomPanel::top("Alarm_Banner")
+            .frame(frame)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "Alarms: {} active, {} unacknowledged",
+                            active.len(),
+                            unacknowledged.len()
+                        ))
+                        .color(Color32::WHITE)
+                        .size(16.0),
+                    );
+                    if ui
+                        .add_enabled(
+                            !unacknowledged.is_empty(),
+                            eframe::egui::Button::new("Acknowledge All"),
+                        )
+                        .clicked()
+                    {
+                        acknowledged.extend(unacknowledged.iter().map(|a| a.id));
+                    }
+                    if ui.button("History").clicked() {
+                        self.is_history_open = true;
+                    }
+                });
+                for alarm in active.iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(format_clock_time(&alarm.raised_at)).monospace());
+                        ui.label(
+                            RichText::new(format!("{:8}", alarm.severity.to_string()))
+                                .monospace()
+                                .color(&alarm.severity),
+                        );
+                        ui.label(RichText::new(&alarm.message).color(Color32::WHITE))
+                            .on_hover_text(format!("Rule {}, alarm {}", alarm.rule, alarm.id));
+                        ui.label(RichText::new(alarm_state(alarm)).italics());
+                        if alarm.acknowledged_at.is_none() && ui.button("Acknowledge").clicked() {
+                            acknowledged.push(alarm.id);
+                        }
+                    });
+                }
+            });
+        return acknowledged;
+    }
+
+    /// Show the finished alarms, most recent first
+    pub fn show_history(&mut self, ctx: &eframe::egui::Context, alarms: &AlarmEngine) {
+        let mut is_open = self.is_history_open;
+        eframe::egui::Window::new("Alarm History")
+            .open(&mut is_open)
+            .default_size([900.0, 300.0])
+            .show(ctx, |ui| {
+                let history = alarms.get_history();
+                if history.is_empty() {
+                    ui.label("No alarms have cleared yet");
+                    return;
+                }
+                eframe::egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        eframe::egui::Grid::new("Alarm_History_Grid")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for heading in
+                                    ["Raised", "Cleared", "Severity", "Run", "Rule", "Message"]
+                                {
+                                    ui.label(RichText::new(heading).strong());
+                                }
+                                ui.end_row();
+                                for alarm in history.iter().rev() {
+                                    ui.label(format_clock_time(&alarm.raised_at));
+                                    ui.label(match &alarm.cleared_at {
+                                        Some(time) => format_clock_time(time),
+                                        None => String::new(),
+                                    });
+                                    ui.label(
+                                        RichText::new(alarm.severity.to_string())
+                                            .color(&alarm.severity),
+                                    );
+                                    ui.label(match alarm.run_number {
+                                        Some(run) => run.to_string(),
+                                        None => String::from("-"),
+                                    });
+                                    ui.label(&alarm.rule);
+                                    ui.label(&alarm.message);
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        self.is_history_open = is_open;
+    }
+}
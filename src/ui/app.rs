@@ -1,461 +1,319 @@
-use super::config::Config;
+use super::alarm_panel::AlarmPanel;
 use super::graph_manager::GraphManager;
-use super::status_manager::StatusManager;
-use crate::command::command::{execute, CommandName, CommandStatus};
-use crate::envoy::constants::{MUTANT_ID, NUMBER_OF_MODULES};
-use crate::envoy::ecc_operation::{ECCOperation, ECCStatus};
-use crate::envoy::embassy::{connect_embassy, Embassy};
-use crate::envoy::message::EmbassyMessage;
+use super::log_console::{LogBuffer, LogConsole, LogEntry};
+use super::rate_graph::{format_graph_time, RateSmoothing};
+use crate::envoy::connection_status::ConnectionStatus;
+use crate::envoy::ecc_operation::ECCStatus;
 use crate::envoy::surveyor_state::{SurveyorDiskStatus, SurveyorState};
+use crate::envoy::topology::ModuleRole;
+use crate::session::data_monitor::DataFlow;
+use crate::session::disk_forecast::format_time_to_full;
+use crate::session::error::SessionError;
+use crate::session::run_log::RunLog;
+use crate::session::session::Session;
 
 use eframe::egui::widgets::Button;
 use eframe::egui::widgets::DragValue;
 use eframe::egui::{Color32, RichText};
-use std::fs::File;
-use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 const DEFAULT_TEXT_COLOR: Color32 = Color32::LIGHT_GRAY;
+const STALE_TEXT_COLOR: Color32 = Color32::YELLOW;
+/// Speeds offered for replaying a recording
+const REPLAY_SPEEDS: [f64; 5] = [1.0, 2.0, 5.0, 10.0, 60.0];
+
+/// Format a duration as mm:ss for the replay controls
+fn format_minutes(duration: Duration) -> String {
+    format!(
+        "{:02}:{:02}",
+        duration.as_secs() / 60,
+        duration.as_secs() % 60
+    )
+}
+
+/// Format the time since a server was last reached for the status tables
+fn format_last_contact(elapsed: Option<Duration>) -> String {
+    match elapsed {
+        Some(e) if e.as_secs() < 60 => format!("{}s ago", e.as_secs()),
+        Some(e) => format!("{}m {}s ago", e.as_secs() / 60, e.as_secs() % 60),
+        None => String::from("Never"),
+    }
+}
+
+/// Show the status of a module in one of the status tables. A connection failure is shown in place of
+/// the state reported by the server (with the error on hover), and stale modules are flagged.
+fn status_label(
+    ui: &mut eframe::egui::Ui,
+    state_text: String,
+    state_color: Color32,
+    connection: &ConnectionStatus,
+    is_stale: bool,
+) {
+    ui.horizontal(|ui| {
+        match connection.get_detail() {
+            Some(detail) => {
+                ui.label(RichText::new(format!("{}", connection)).color(connection))
+                    .on_hover_text(detail);
+            }
+            None => {
+                ui.label(RichText::new(state_text).color(state_color));
+            }
+        }
+        if is_stale {
+            ui.label(RichText::new("(stale)").color(STALE_TEXT_COLOR))
+                .on_hover_text("No status update has arrived recently");
+        }
+    });
+}
+
+/// Show the name of a module in one of the status tables. Modules with a recent error in the log are highlighted,
+/// with the error (and anything else worth knowing) on hover.
+fn module_label(
+    ui: &mut eframe::egui::Ui,
+    name: String,
+    error: Option<LogEntry>,
+    hover: Option<String>,
+) {
+    let color = match error {
+        Some(_) => Color32::LIGHT_RED,
+        None => Color32::LIGHT_GREEN,
+    };
+    let label = ui.label(RichText::new(name).color(color));
+    let hover = match (error, hover) {
+        (Some(e), Some(h)) => Some(format!("{}\nLast error: {}", h, e.message)),
+        (Some(e), None) => Some(format!("Last error: {}", e.message)),
+        (None, h) => h,
+    };
+    if let Some(hover) = hover {
+        label.on_hover_text(hover);
+    }
+}
 
 /// # EnvoyApp
-/// EnvoyApp implements the eframe::App trait, and holds most of the controlling functionality of the program,
-/// including the tokio runtime and the embassy hub.
+/// EnvoyApp implements the eframe::App trait. It drives a Session, which holds the tokio runtime, the embassy hub
+/// and the run sequencing, and keeps the parts which only matter to the GUI (i.e. the rate graphs, log console, and
+/// alarm banner).
 #[derive(Debug)]
 pub struct EnvoyApp {
-    config: Config,
-    runtime: tokio::runtime::Runtime,
-    embassy: Option<Embassy>,
-    envoy_handles: Option<Vec<tokio::task::JoinHandle<()>>>,
-    status: StatusManager,
+    session: Session,
     graphs: GraphManager,
+    log_console: LogConsole,
+    alarm_panel: AlarmPanel,
     max_graph_points: usize,
-    run_start_time: Instant,
     run_duration: Duration,
 }
 
 impl EnvoyApp {
-    /// Create an app from a tokio runtime and eframe context
-    pub fn new(cc: &eframe::CreationContext<'_>, runtime: tokio::runtime::Runtime) -> Self {
+    /// Create an app from a tokio runtime and eframe context. The log console shows the events recorded in the buffer,
+    /// and the session writes the per-run logs to the run log.
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        runtime: tokio::runtime::Runtime,
+        log_buffer: LogBuffer,
+        run_log: RunLog,
+    ) -> Self {
         let mut visuals = eframe::egui::Visuals::dark();
         visuals.override_text_color = Some(DEFAULT_TEXT_COLOR);
         cc.egui_ctx.set_visuals(visuals);
+        let mut session = Session::new(runtime);
+        session.set_run_log(run_log);
         EnvoyApp {
-            config: Config::new(),
-            runtime,
-            embassy: None,
-            envoy_handles: None,
-            status: StatusManager::new(),
-            graphs: GraphManager::new(10),
+            graphs: GraphManager::new(session.get_topology(), 10),
+            session,
+            log_console: LogConsole::new(log_buffer),
+            alarm_panel: AlarmPanel::new(),
             max_graph_points: 10,
-            run_start_time: Instant::now(),
             run_duration: Duration::from_secs(0),
         }
     }
 
     /// Read in a config from a YAML file at the filepath
     fn read_config(&mut self, filepath: PathBuf) {
-        if let Ok(mut file) = File::open(&filepath) {
-            let mut yaml_str = String::new();
-            match file.read_to_string(&mut yaml_str) {
-                Ok(_) => (),
-                Err(e) => {
-                    tracing::error!("Could not read yaml file: {}", e);
-                    return;
-                }
-            }
-            self.config = match serde_yaml::from_str::<Config>(&yaml_str) {
-                Ok(c) => c,
-                Err(e) => {
-                    tracing::error!("Could not deserialize config: {}", e);
-                    return;
-                }
-            };
-            self.config.config_path = filepath;
-        } else {
-            tracing::error!("Could not open the selected file!");
-        }
-    }
-
-    /// Write the current config to a YAML file at the filepath
-    fn write_config(&self) {
-        if let Ok(mut file) = File::create(&self.config.config_path) {
-            let yaml_str = match serde_yaml::to_string::<Config>(&self.config) {
-                Ok(yaml) => yaml,
-                Err(e) => {
-                    tracing::error!("Could not convert config to yaml: {}", e);
-                    return;
-                }
-            };
-            match file.write(yaml_str.as_bytes()) {
-                Ok(_) => (),
-                Err(e) => {
-                    tracing::error!("Could not write yaml file: {}", e);
-                    return;
-                }
-            }
+        let result = self.session.read_config(filepath);
+        let is_read = match &result {
+            Err(SessionError::ConfigIOError(_)) | Err(SessionError::ConfigParseError(_)) => false,
+            _ => true,
+        };
+        self.handle_topology_result(result);
+        if is_read {
+            Self::log_error(self.session.start_api());
         }
     }
 
-    /// Create all of the envoys, the embassy, and start the async tasks
-    fn connect(&mut self) {
-        if self.embassy.is_none() && self.envoy_handles.is_none() {
-            let (em, handles) = connect_embassy(&mut self.runtime, &self.config.experiment);
-            tracing::info!("Connnected with {} tasks spawned", handles.len());
-            self.embassy = Some(em);
-            self.envoy_handles = Some(handles);
-        }
+    /// Load the topology given by the config. The graph manager is rebuilt to match the new topology.
+    fn load_topology(&mut self) {
+        let result = self.session.load_topology();
+        self.handle_topology_result(result);
     }
 
-    /// Emit a cancel signal to all of the envoys and destroy the envoys and the embassy
-    /// This can cause a small blocking period while waiting for all of the tasks to join back.
-    fn disconnect(&mut self) {
-        if self.embassy.is_some() {
-            let mut embassy = self.embassy.take().expect("Literally cant happen");
-            embassy.shutdown();
-            let handles = self
-                .envoy_handles
-                .take()
-                .expect("Handles did not exist at disconnect?");
-            for handle in handles {
-                match self.runtime.block_on(handle) {
-                    Ok(()) => (),
-                    Err(e) => tracing::error!("Encountered an error whilst disconnecting: {}", e),
-                }
+    fn handle_topology_result(&mut self, result: Result<(), SessionError>) {
+        match result {
+            Ok(()) => (),
+            Err(SessionError::TopologyError(e)) => {
+                tracing::error!("{}. Falling back to the default AT-TPC topology.", e)
             }
-            tracing::info!("Disconnected the embassy");
-            self.status.reset();
-            tracing::info!("Status manager reset.")
-        }
-    }
-
-    /// Read and handle any messages the embassy recieved from the envoys. Messages are sent
-    /// to observer like structures (the StatusManager and GraphManager)
-    fn poll_embassy(&mut self) {
-        if let Some(embassy) = self.embassy.as_mut() {
-            match embassy.poll_messages() {
-                Ok(messages) => {
-                    match self.status.handle_messages(&messages) {
-                        Ok(_) => (),
-                        Err(e) => tracing::error!(
-                            "StatusManager ran into an error handling messages: {}",
-                            e
-                        ),
-                    };
-                    match self.graphs.handle_messages(&messages) {
-                        Ok(_) => (),
-                        Err(e) => tracing::error!(
-                            "GraphManager ran into an error handling messages: {}",
-                            e
-                        ),
-                    }
-                }
-                Err(e) => tracing::error!("Embassy ran into an error polling the envoys: {}", e),
-            };
-        }
-    }
-
-    /// Send a transition command to some of the ECC operation envoys. Transitions are either forward or backward
-    /// depending on the is_forward flag. What type of transition is determined by the current state of the envoy as last recorded
-    /// by the status envoy.
-    fn transition_ecc(&mut self, ids: Vec<usize>, is_forward: bool) {
-        if ids.len() == 0 {
-            return;
-        }
-
-        if self.embassy.is_none() {
-            tracing::error!("Some how trying to operate on ECC whilst disconnected!");
-            return;
-        }
-        for id in ids {
-            let status = &self.status.get_ecc_status(id);
-            let operation: ECCOperation;
-            if is_forward {
-                operation = status.get_forward_operation();
-            } else {
-                operation = status.get_backward_operation();
+            Err(SessionError::Connected) => {
+                tracing::warn!(
+                    "Cannot change the topology while connected! Disconnect and re-open the config."
+                );
+                return;
             }
-            match operation {
-                ECCOperation::Invalid => (),
-                _ => {
-                    match self
-                        .embassy
-                        .as_mut()
-                        .unwrap()
-                        .submit_message(EmbassyMessage::compose_ecc_op(operation.into(), id as i32))
-                    {
-                        Ok(()) => (),
-                        Err(e) => tracing::error!("Embassy had an error sending a message: {}", e),
-                    }
-                }
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
             }
-            self.status.set_ecc_busy(id);
         }
+        self.graphs = GraphManager::new(self.session.get_topology(), self.max_graph_points);
     }
 
-    /// Transition all of the envoys forward (Progress)
-    /// This is slightly more complicated as order matters for two of the phases (Prepare and Configure)
-    fn forward_transition_all(&mut self) {
-        let system = self.status.get_system_ecc_status();
-        let all_ids_but_mutant: Vec<usize> = (0..((NUMBER_OF_MODULES - 1) as usize)).collect();
-        let ids: Vec<usize> = (0..(NUMBER_OF_MODULES as usize)).collect();
-        match system.get_forward_operation() {
-            //Describe operation: order doesn't matter
-            ECCOperation::Describe => self.transition_ecc(ids, true),
-            //Prepare operation: mutant first, then cobos
-            ECCOperation::Prepare => {
-                self.transition_ecc(vec![MUTANT_ID as usize], true);
-                loop {
-                    self.poll_embassy();
-                    if self.status.is_mutant_prepared() {
-                        break;
-                    }
-                }
-                self.transition_ecc(all_ids_but_mutant, true)
-            }
-            //Configure operation: cobos first, then mutant
-            ECCOperation::Configure => {
-                self.transition_ecc(all_ids_but_mutant, true);
-                loop {
-                    self.poll_embassy();
-                    if self.status.is_all_but_mutant_ready() {
-                        break;
-                    }
-                }
-                self.transition_ecc(vec![MUTANT_ID as usize], true)
-            }
-            _ => tracing::error!(
-                "Tried to do some illegal forward transition all: {}",
-                system.get_forward_operation()
-            ),
+    /// Write the current config to its YAML file
+    fn write_config(&self) {
+        if let Err(e) = self.session.write_config() {
+            tracing::error!("{}", e);
         }
     }
 
-    /// Transition all of the envoys backward (Regress)
-    fn backward_transition_all(&mut self) {
-        let ids: Vec<usize> = (0..(NUMBER_OF_MODULES as usize)).collect();
-        self.transition_ecc(ids, false)
-    }
-
-    /// Send a start run command to all of the envoys.
-    /// Note that several important things must happen here. First a command is sent to make sure that
-    /// the run number was not already used. Then, the CoBos must start, and only once all CoBos are running,
-    /// does the Mutant start. The rate graphs are also reset.
+    /// Start a run, resetting the rate graphs
     fn start_run(&mut self) {
-        //Order is all cobos, then mutant
-        let operation = ECCOperation::Start;
         self.graphs.reset_graphs();
-
-        //Check the run number status using the shell scripting engine
-        tracing::info!("Starting run {} ...", self.config.run_number);
-        tracing::info!("Checking if run number is ok...");
-        match execute(
-            CommandName::CheckRunExists,
-            self.status.get_surveyor_status_response(),
-            &self.config.experiment,
-            &self.config.run_number,
-        ) {
-            CommandStatus::Success => {
-                tracing::warn!("Tried to start a run with a run number that was already used! Either delete the extant data or change the run number!");
-                return;
-            }
-            CommandStatus::Failure => (),
-            CommandStatus::CouldNotExecute => return,
-        }
-        tracing::info!("Run number validated.");
-
-        tracing::info!("Re-configuring MuTaNT to reset timestamps...");
-        self.transition_ecc(vec![MUTANT_ID as usize], false);
-        loop {
-            self.poll_embassy();
-            if self.status.is_mutant_prepared() {
-                break;
-            }
-        }
-        self.transition_ecc(vec![MUTANT_ID as usize], true);
-        loop {
-            self.poll_embassy();
-            if self.status.is_mutant_ready() {
-                break;
-            }
-        }
-        tracing::info!("MuTaNT is re-configured. Proceeding.");
-
-        tracing::info!("Starting CoBos...");
-        //Start CoBos
-        for id in 0..(NUMBER_OF_MODULES - 1) {
-            match self
-                .embassy
-                .as_mut()
-                .unwrap()
-                .submit_message(EmbassyMessage::compose_ecc_op(operation.clone().into(), id))
-            {
-                Ok(()) => (),
-                Err(e) => {
-                    tracing::error!("Embassy had an error sending a start run message: {}", e)
-                }
-            }
-        }
-
-        //Wait for good CoBo status
-        loop {
-            self.poll_embassy();
-            if self.status.is_all_but_mutant_running() {
-                break;
-            }
-        }
-
-        tracing::info!("CoBos started.");
-
-        tracing::info!("Starting MuTaNT...");
-        //Start mutant
-        match self
-            .embassy
-            .as_mut()
-            .unwrap()
-            .submit_message(EmbassyMessage::compose_ecc_op(
-                operation.clone().into(),
-                MUTANT_ID,
-            )) {
-            Ok(()) => (),
-            Err(e) => tracing::error!("Embassy had an error sending a start run message: {}", e),
+        if let Err(e) = self.session.start_run() {
+            tracing::error!("{}", e);
         }
-        tracing::info!("MuTaNT started.");
-        tracing::info!("Run {} successfully started!", self.config.run_number);
-
-        //Update run start time
-        self.run_start_time = Instant::now();
     }
 
-    /// Send a stop run command to all of the envoys.
-    /// Note that several important things must happen here. First the Mutant is stopped. Then, only after the Mutant has stopped,
-    /// all of the Cobos are told to stop. After the stop command is issued, a command is sent to move all of the data to a run specific location,
-    /// as well as a command to back up the ECC configuration files.
-    fn stop_run(&mut self) {
-        //Order is mutant, all cobos
-        let operation = ECCOperation::Stop;
-
-        tracing::info!("Stopping run {} ...", self.config.run_number);
-        tracing::info!("Stopping the MuTaNT...");
-        //Stop the mutant
-        match self
-            .embassy
-            .as_mut()
-            .unwrap()
-            .submit_message(EmbassyMessage::compose_ecc_op(
-                operation.clone().into(),
-                MUTANT_ID,
-            )) {
-            Ok(()) => (),
-            Err(e) => tracing::error!("Embassy had an error sending a stop run message: {}", e),
-        }
-
-        //Wait for mutant to stop
-        loop {
-            self.poll_embassy();
-            if self.status.is_mutant_stopped() {
-                break;
-            }
-        }
-
-        tracing::info!("MuTaNT stopped.");
-        tracing::info!("Stopping CoBos...");
-
-        //Stop all of the CoBos
-        for id in 0..(NUMBER_OF_MODULES - 1) {
-            match self
-                .embassy
-                .as_mut()
-                .unwrap()
-                .submit_message(EmbassyMessage::compose_ecc_op(operation.clone().into(), id))
-            {
-                Ok(()) => (),
-                Err(e) => {
-                    tracing::error!("Embassy had an error sending a start run message: {}", e)
-                }
+    /// Replay a recording in place of the AT-TPC. The graphs are rebuilt for the topology of the recording.
+    fn start_replay(&mut self, path: PathBuf) {
+        match self.session.start_replay(&path, REPLAY_SPEEDS[0]) {
+            Ok(()) => {
+                self.graphs = GraphManager::new(self.session.get_topology(), self.max_graph_points);
             }
+            Err(e) => tracing::error!("{}", e),
         }
+    }
 
-        tracing::info!("CoBos stopped.");
-        tracing::info!("Moving .graw files...");
-
-        match execute(
-            CommandName::MoveGrawFiles,
-            self.status.get_surveyor_status_response(),
-            &self.config.experiment,
-            &self.config.run_number,
-        ) {
-            CommandStatus::Success => (),
-            CommandStatus::Failure => {
-                tracing::error!("Unable to move the graw files after the stop run signal!")
-            }
-            CommandStatus::CouldNotExecute => (),
-        }
+    /// Stop replaying and go back to the topology of the config
+    fn stop_replay(&mut self) {
+        let result = self.session.stop_replay();
+        self.handle_topology_result(result);
+    }
 
-        tracing::info!(".graw files moved.");
-        tracing::info!("Backing up GET configuration...");
-
-        match execute(
-            CommandName::BackupConfig,
-            self.status.get_surveyor_status_response(),
-            &self.config.experiment,
-            &self.config.run_number,
-        ) {
-            CommandStatus::Success => (),
-            CommandStatus::Failure => {
-                tracing::error!("Could not backup config files after the stop run signal")
-            }
-            CommandStatus::CouldNotExecute => (),
+    /// Log any error from a session action which the UI does not otherwise handle
+    fn log_error(result: Result<(), SessionError>) {
+        if let Err(e) = result {
+            tracing::error!("{}", e);
         }
-
-        tracing::info!("GET configuration backed up.");
-        tracing::info!("Run {} stopped!", self.config.run_number);
-
-        tracing::info!("Saving config to table...");
-        self.config
-            .write_table(Instant::now() - self.run_start_time);
-        tracing::info!("Config saved to table.");
-
-        self.config.run_number += 1;
-        self.write_config();
-        tracing::info!("Config autosaved to {}", self.config.config_path.display());
     }
 }
 
 impl eframe::App for EnvoyApp {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
         //Probably don't want to poll every frame, but as a test...
-        self.poll_embassy();
+        let messages = self.session.poll();
+        self.graphs.handle_messages(&messages);
+        //Nothing else may touch the ECC system while a sequence is running
+        let is_sequence_running = self.session.is_sequence_running();
+        let is_connected = self.session.is_connected();
+        let is_replaying = self.session.get_replay().is_some();
 
         // The top panel, contains the specific configuration
         eframe::egui::TopBottomPanel::top("Config_Panel").show(ctx, |ui| {
-            //Drop down menu
-            ui.menu_button(RichText::new("File").size(16.0), |ui| {
-                if ui.button(RichText::new("Save").size(14.0)).clicked() {
-                    if let Ok(Some(path)) = native_dialog::FileDialog::new()
-                        .set_location(
-                            &std::env::current_dir().expect("Couldn't access runtime directory"),
+            //Drop down menus
+            eframe::egui::menu::bar(ui, |ui| {
+                ui.menu_button(RichText::new("File").size(16.0), |ui| {
+                    if ui.button(RichText::new("Save").size(14.0)).clicked() {
+                        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+                            .set_location(
+                                &std::env::current_dir()
+                                    .expect("Couldn't access runtime directory"),
+                            )
+                            .add_filter("YAML file", &["yaml"])
+                            .show_save_single_file()
+                        {
+                            self.session.config.config_path = path;
+                            self.write_config();
+                        }
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(
+                            !is_connected && !is_replaying,
+                            Button::new(RichText::new("Open").size(14.0)),
                         )
-                        .add_filter("YAML file", &["yaml"])
-                        .show_save_single_file()
+                        .clicked()
                     {
-                        self.config.config_path = path;
-                        self.write_config();
+                        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+                            .set_location(
+                                &std::env::current_dir()
+                                    .expect("Couldn't access runtime directory"),
+                            )
+                            .add_filter("YAML file", &["yaml"])
+                            .show_open_single_file()
+                        {
+                            self.read_config(path);
+                        }
+                        ui.close_menu();
                     }
-                    ui.close_menu();
-                }
-                if ui.button(RichText::new("Open").size(14.0)).clicked() {
-                    if let Ok(Some(path)) = native_dialog::FileDialog::new()
-                        .set_location(
-                            &std::env::current_dir().expect("Couldn't access runtime directory"),
+                    if ui
+                        .add_enabled(
+                            !is_connected && !is_replaying,
+                            Button::new(RichText::new("Open Topology").size(14.0)),
                         )
-                        .add_filter("YAML file", &["yaml"])
-                        .show_open_single_file()
+                        .clicked()
                     {
-                        self.read_config(path);
+                        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+                            .set_location(
+                                &std::env::current_dir()
+                                    .expect("Couldn't access runtime directory"),
+                            )
+                            .add_filter("YAML file", &["yaml", "yml"])
+                            .show_open_single_file()
+                        {
+                            self.session.config.topology_path = Some(path);
+                            self.load_topology();
+                        }
+                        ui.close_menu();
                     }
-                    ui.close_menu();
-                }
+                    if ui
+                        .add_enabled(
+                            !is_connected,
+                            Button::new(RichText::new("Replay Recording").size(14.0)),
+                        )
+                        .clicked()
+                    {
+                        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+                            .set_location(
+                                &std::env::current_dir()
+                                    .expect("Couldn't access runtime directory"),
+                            )
+                            .add_filter("Recording", &["jsonl"])
+                            .show_open_single_file()
+                        {
+                            self.start_replay(path);
+                        }
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button(RichText::new("View").size(16.0), |ui| {
+                    if ui
+                        .checkbox(
+                            &mut self.log_console.is_open,
+                            RichText::new("Log Console").size(14.0),
+                        )
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                    if ui
+                        .checkbox(
+                            &mut self.alarm_panel.is_history_open,
+                            RichText::new("Alarm History").size(14.0),
+                        )
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                });
             });
 
             // Configuration
@@ -469,11 +327,27 @@ impl eframe::App for EnvoyApp {
             ui.label(
                 RichText::new(format!(
                     "Config File: {}",
-                    self.config.config_path.display()
+                    self.session.config.config_path.display()
+                ))
+                .size(16.0)
+                .color(Color32::LIGHT_BLUE),
+            );
+            ui.label(
+                RichText::new(format!(
+                    "Topology: {} ({} modules)",
+                    self.session.get_topology().name,
+                    self.session.get_topology().number_of_modules()
                 ))
                 .size(16.0)
                 .color(Color32::LIGHT_BLUE),
             );
+            if let Some(address) = self.session.get_api_address() {
+                ui.label(
+                    RichText::new(format!("Control API: http://{}", address))
+                        .size(16.0)
+                        .color(Color32::LIGHT_BLUE),
+                );
+            }
             ui.horizontal(|ui| {
                 ui.label(
                     RichText::new("Experiment")
@@ -481,9 +355,11 @@ impl eframe::App for EnvoyApp {
                         .color(Color32::LIGHT_BLUE),
                 );
                 ui.add(
-                    eframe::egui::widgets::TextEdit::singleline(&mut self.config.experiment)
-                        .desired_width(100.0)
-                        .margin([4.0, 4.0].into()),
+                    eframe::egui::widgets::TextEdit::singleline(
+                        &mut self.session.config.experiment,
+                    )
+                    .desired_width(100.0)
+                    .margin([4.0, 4.0].into()),
                 );
             });
 
@@ -493,44 +369,46 @@ impl eframe::App for EnvoyApp {
                         .size(16.0)
                         .color(Color32::LIGHT_BLUE),
                 );
-                ui.add(DragValue::new(&mut self.config.run_number).speed(1));
+                ui.add(DragValue::new(&mut self.session.config.run_number).speed(1));
             });
 
             ui.horizontal(|ui| {
                 ui.label(RichText::new("Description").size(16.0));
                 ui.add(
-                    eframe::egui::widgets::TextEdit::singleline(&mut self.config.description)
-                        .desired_width(f32::INFINITY)
-                        .margin([4.0, 4.0].into()),
+                    eframe::egui::widgets::TextEdit::singleline(
+                        &mut self.session.config.description,
+                    )
+                    .desired_width(f32::INFINITY)
+                    .margin([4.0, 4.0].into()),
                 );
             });
             eframe::egui::Grid::new("Config grid")
                 .min_col_width(100.0)
                 .show(ui, |ui| {
                     ui.label(RichText::new("VTHGEM(V)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.v_thgem).speed(10));
+                    ui.add(DragValue::new(&mut self.session.config.v_thgem).speed(10));
                     ui.label(RichText::new("E-Drift(V)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.e_drift).speed(10));
+                    ui.add(DragValue::new(&mut self.session.config.e_drift).speed(10));
                     ui.label(RichText::new("Gas").size(16.0));
-                    ui.text_edit_singleline(&mut self.config.gas);
+                    ui.text_edit_singleline(&mut self.session.config.gas);
                     ui.label(RichText::new("Magnetic Field(T)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.magnetic_field).speed(0.01));
+                    ui.add(DragValue::new(&mut self.session.config.magnetic_field).speed(0.01));
                     ui.end_row();
 
                     ui.label(RichText::new("VCathode(kV)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.v_cathode).speed(10));
+                    ui.add(DragValue::new(&mut self.session.config.v_cathode).speed(10));
                     ui.label(RichText::new("E-Trans(V)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.e_trans).speed(10));
+                    ui.add(DragValue::new(&mut self.session.config.e_trans).speed(10));
                     ui.label(RichText::new("Beam").size(16.0));
-                    ui.text_edit_singleline(&mut self.config.beam);
+                    ui.text_edit_singleline(&mut self.session.config.beam);
                     ui.end_row();
 
                     ui.label(RichText::new("VMM(V)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.v_mm).speed(10));
+                    ui.add(DragValue::new(&mut self.session.config.v_mm).speed(10));
                     ui.label(RichText::new("Pressure(Torr)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.pressure).speed(10));
+                    ui.add(DragValue::new(&mut self.session.config.pressure).speed(10));
                     ui.label(RichText::new("Beam Energy (MeV/U)").size(16.0));
-                    ui.add(DragValue::new(&mut self.config.energy).speed(1));
+                    ui.add(DragValue::new(&mut self.session.config.energy).speed(1));
                     ui.end_row();
                 });
 
@@ -545,7 +423,7 @@ impl eframe::App for EnvoyApp {
                 );
                 if ui
                     .add_enabled(
-                        self.embassy.is_none(),
+                        !is_connected && !is_replaying,
                         Button::new(
                             RichText::new("Connect")
                                 .color(Color32::LIGHT_BLUE)
@@ -555,11 +433,22 @@ impl eframe::App for EnvoyApp {
                     )
                     .clicked()
                 {
-                    self.connect();
+                    Self::log_error(self.session.connect());
+                }
+                if ui
+                    .add_enabled(
+                        !is_connected && !is_replaying,
+                        Button::new(RichText::new("Simulate").color(Color32::GOLD).size(16.0))
+                            .min_size([100.0, 25.0].into()),
+                    )
+                    .on_hover_text("Connect to a simulated AT-TPC")
+                    .clicked()
+                {
+                    Self::log_error(self.session.connect_simulated());
                 }
                 if ui
                     .add_enabled(
-                        self.embassy.is_some(),
+                        is_connected && !is_sequence_running,
                         Button::new(
                             RichText::new("Disconnect")
                                 .color(Color32::LIGHT_RED)
@@ -569,10 +458,71 @@ impl eframe::App for EnvoyApp {
                     )
                     .clicked()
                 {
-                    self.disconnect();
+                    Self::log_error(self.session.disconnect());
+                }
+                if self.session.is_simulated() {
+                    ui.label(RichText::new("Simulation").size(16.0).color(Color32::GOLD));
                 }
             });
 
+            // Replay controls
+            let mut is_replay_stopped = false;
+            if let Some(replay) = self.session.get_replay_mut() {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "Replaying run {} of {}: {} / {}",
+                            replay.get_header().run_number,
+                            replay.get_header().experiment,
+                            format_minutes(replay.get_position()),
+                            format_minutes(replay.get_duration())
+                        ))
+                        .size(16.0)
+                        .color(Color32::GOLD),
+                    )
+                    .on_hover_text(replay.get_path().display().to_string());
+                    let pause_text = match replay.is_paused() {
+                        true => "Resume",
+                        false => "Pause",
+                    };
+                    if ui
+                        .add_enabled(
+                            !replay.is_finished(),
+                            Button::new(RichText::new(pause_text).size(16.0))
+                                .min_size([100.0, 25.0].into()),
+                        )
+                        .clicked()
+                    {
+                        replay.set_paused(!replay.is_paused());
+                    }
+                    let mut speed = replay.get_speed();
+                    eframe::egui::ComboBox::from_id_source("Replay speed")
+                        .selected_text(format!("{}x", speed))
+                        .show_ui(ui, |ui| {
+                            for option in REPLAY_SPEEDS {
+                                ui.selectable_value(&mut speed, option, format!("{}x", option));
+                            }
+                        });
+                    replay.set_speed(speed);
+                    if ui
+                        .add(
+                            Button::new(
+                                RichText::new("Stop Replay")
+                                    .color(Color32::LIGHT_RED)
+                                    .size(16.0),
+                            )
+                            .min_size([100.0, 25.0].into()),
+                        )
+                        .clicked()
+                    {
+                        is_replay_stopped = true;
+                    }
+                });
+            }
+            if is_replay_stopped {
+                self.stop_replay();
+            }
+
             // Start/Stop buttons
             ui.separator();
 
@@ -584,7 +534,9 @@ impl eframe::App for EnvoyApp {
                 );
                 if ui
                     .add_enabled(
-                        self.status.is_system_ready(),
+                        is_connected
+                            && !is_sequence_running
+                            && self.session.get_status().is_system_ready(),
                         Button::new(RichText::new("Start").color(Color32::GREEN).size(16.0))
                             .min_size([100.0, 25.0].into()),
                     )
@@ -595,17 +547,21 @@ impl eframe::App for EnvoyApp {
 
                 if ui
                     .add_enabled(
-                        self.status.is_system_running(),
+                        is_connected
+                            && !is_sequence_running
+                            && self.session.get_status().is_system_running(),
                         Button::new(RichText::new("Stop").color(Color32::RED).size(16.0))
                             .min_size([100.0, 25.0].into()),
                     )
                     .clicked()
                 {
-                    self.stop_run();
+                    Self::log_error(self.session.stop_run());
                 }
 
-                if self.status.is_system_running() {
-                    self.run_duration = Instant::now() - self.run_start_time;
+                if self.session.get_status().is_system_running() {
+                    if let Some(duration) = self.session.config.get_run_duration() {
+                        self.run_duration = duration;
+                    }
                 }
                 let mut secs = self.run_duration.as_secs();
                 let hrs = ((secs as f64) / 3600.0).floor() as u64;
@@ -621,9 +577,30 @@ impl eframe::App for EnvoyApp {
                     .color(Color32::LIGHT_BLUE),
                 );
             });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        is_sequence_running,
+                        Button::new(RichText::new("Abort").color(Color32::LIGHT_RED).size(16.0))
+                            .min_size([100.0, 25.0].into()),
+                    )
+                    .clicked()
+                {
+                    Self::log_error(self.session.abort_sequence());
+                }
+                ui.label(RichText::new(self.session.get_run_step()).size(16.0));
+            });
             ui.separator();
         });
 
+        // Alarm banner, below the configuration while any alarm is active
+        let acknowledged = self.alarm_panel.show_banner(ctx, self.session.get_alarms());
+        for id in acknowledged {
+            Self::log_error(self.session.acknowledge_alarm(id));
+        }
+        self.alarm_panel
+            .show_history(ctx, self.session.get_alarms());
+
         // Bottom panel, contains the rate graph
         eframe::egui::TopBottomPanel::bottom("Graph_Panel").show(ctx, |ui| {
             ui.separator();
@@ -637,6 +614,16 @@ impl eframe::App for EnvoyApp {
             ui.horizontal(|ui| {
                 ui.label(RichText::new("Number of Points Per Graph").size(16.0));
                 ui.add(eframe::egui::DragValue::new(&mut self.max_graph_points).speed(1));
+                ui.label(RichText::new("Smoothing").size(16.0));
+                let mut smoothing = *self.graphs.get_smoothing();
+                eframe::egui::ComboBox::from_id_source("Rate smoothing")
+                    .selected_text(smoothing.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in RateSmoothing::ALL {
+                            ui.selectable_value(&mut smoothing, option, option.to_string());
+                        }
+                    });
+                self.graphs.set_smoothing(smoothing);
             });
             ui.separator();
             if *self.graphs.get_max_points() != self.max_graph_points {
@@ -646,8 +633,16 @@ impl eframe::App for EnvoyApp {
                 .view_aspect(6.0)
                 .height(200.0)
                 .legend(egui_plot::Legend::default().position(egui_plot::Corner::LeftTop))
-                .x_axis_label(RichText::new("Time Since Last Update (s)").size(16.0))
+                .x_axis_label(RichText::new("Time (UTC)").size(16.0))
                 .y_axis_label(RichText::new("Rate (MB/s)").size(16.0))
+                .x_axis_formatter(|time, _, _| format_graph_time(time))
+                .label_formatter(|name, point| {
+                    format!(
+                        "{name}\n{}\n{:.3} MB/s",
+                        format_graph_time(point.x),
+                        point.y
+                    )
+                })
                 .show(ui, |plot_ui| {
                     for line in lines {
                         plot_ui.line(line);
@@ -663,7 +658,7 @@ impl eframe::App for EnvoyApp {
                     .color(Color32::LIGHT_BLUE)
                     .size(18.0),
             );
-            let ecc_system_stat = self.status.get_system_ecc_status();
+            let ecc_system_stat = self.session.get_status().get_system_ecc_status();
             ui.label(
                 RichText::new(format!("System Status: {}", ecc_system_stat))
                     .size(16.0)
@@ -674,37 +669,59 @@ impl eframe::App for EnvoyApp {
                 ui.label(RichText::new("Regress system").size(16.0));
                 if ui
                     .add_enabled(
-                        self.status.get_system_ecc_status().can_go_backward(),
+                        is_connected
+                            && !is_sequence_running
+                            && self
+                                .session
+                                .get_status()
+                                .get_system_ecc_status()
+                                .can_go_backward(),
                         Button::new(RichText::new("\u{25C0}").color(Color32::RED).size(16.0)),
                     )
                     .clicked()
                 {
-                    self.backward_transition_all();
+                    if let Err(e) = self.session.regress_system() {
+                        tracing::error!("{}", e);
+                    }
                 }
                 ui.label(RichText::new("Progress system").size(16.0));
                 if ui
                     .add_enabled(
-                        self.status.get_system_ecc_status().can_go_forward(),
+                        is_connected
+                            && !is_sequence_running
+                            && self
+                                .session
+                                .get_status()
+                                .get_system_ecc_status()
+                                .can_go_forward(),
                         Button::new(RichText::new("\u{25B6}").color(Color32::GREEN).size(16.0)),
                     )
                     .clicked()
                 {
-                    self.forward_transition_all();
+                    Self::log_error(self.session.progress_system());
                 }
             });
             ui.separator();
 
             let mut forward_transitions: Vec<usize> = vec![];
             let mut backward_transitions: Vec<usize> = vec![];
+            let mut mask_toggles: Vec<usize> = vec![];
+            let mutant_id = self.session.get_topology().mutant_id() as usize;
 
             ui.push_id(0, |ui| {
                 egui_extras::TableBuilder::new(ui)
                     .striped(true)
+                    .column(egui_extras::Column::auto().at_least(60.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(150.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(100.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(50.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(50.0).resizable(true))
+                    .column(egui_extras::Column::auto().at_least(90.0).resizable(true))
+                    .column(egui_extras::Column::remainder().at_least(100.0))
                     .header(40.0, |mut header| {
+                        header.col(|ui| {
+                            ui.heading("Enabled");
+                        });
                         header.col(|ui| {
                             ui.heading("Envoy");
                         });
@@ -717,32 +734,74 @@ impl eframe::App for EnvoyApp {
                         header.col(|ui| {
                             ui.heading("Progress");
                         });
+                        header.col(|ui| {
+                            ui.heading("Last Contact");
+                        });
+                        header.col(|ui| {
+                            ui.heading("Last Error");
+                        });
                     })
                     .body(|body| {
-                        let ecc_status = self.status.get_ecc_status_response();
+                        let ecc_status = self.session.get_status().get_ecc_status_response();
                         body.rows(40.0, ecc_status.len(), |ridx, mut row| {
                             let status = &ecc_status[ridx];
                             let ecc_type = ECCStatus::from(status.state);
+                            let role = match self.session.get_topology().get_module(ridx as i32) {
+                                Some(module) => module.role.clone(),
+                                None => ModuleRole::CoBo,
+                            };
+                            let is_masked = self.session.get_status().is_masked(ridx);
                             row.col(|ui| {
-                                if (ridx as i32) == MUTANT_ID {
-                                    ui.label(
-                                        RichText::new(format!("ECC Envoy {} [MuTaNT]", ridx))
-                                            .color(Color32::LIGHT_GREEN),
-                                    );
-                                } else {
-                                    ui.label(
-                                        RichText::new(format!("ECC Envoy {} [CoBo]", ridx))
-                                            .color(Color32::LIGHT_GREEN),
-                                    );
+                                //Masks can only be changed while disconnected, and never for the MuTaNT
+                                let mut enabled = !is_masked;
+                                if ui
+                                    .add_enabled(
+                                        !is_connected && !is_replaying && ridx != mutant_id,
+                                        eframe::egui::Checkbox::without_text(&mut enabled),
+                                    )
+                                    .changed()
+                                {
+                                    mask_toggles.push(ridx);
                                 }
                             });
                             row.col(|ui| {
-                                ui.label(RichText::new(format!("{}", ecc_type)).color(&ecc_type));
+                                let pending = self.session.get_pending_operation(ridx as i32).map(
+                                    |pending| {
+                                        format!(
+                                            "{} {} in flight for {}s",
+                                            pending.operation,
+                                            pending.op_id,
+                                            pending.submitted.elapsed().as_secs()
+                                        )
+                                    },
+                                );
+                                module_label(
+                                    ui,
+                                    format!("ECC Envoy {} [{}]", ridx, role),
+                                    self.log_console.get_ecc_error(ridx as i32),
+                                    pending,
+                                );
+                            });
+                            row.col(|ui| {
+                                if is_masked {
+                                    ui.label(RichText::new("Masked").color(Color32::DARK_GRAY));
+                                } else {
+                                    status_label(
+                                        ui,
+                                        format!("{}", ecc_type),
+                                        (&ecc_type).into(),
+                                        &status.connection,
+                                        self.session.get_status().is_ecc_stale(ridx),
+                                    );
+                                }
                             });
                             row.col(|ui| {
                                 if ui
                                     .add_enabled(
-                                        ecc_type.can_go_backward(),
+                                        is_connected
+                                            && !is_sequence_running
+                                            && !is_masked
+                                            && ecc_type.can_go_backward(),
                                         Button::new(RichText::new("\u{25C0}").color(Color32::RED)),
                                     )
                                     .clicked()
@@ -753,7 +812,9 @@ impl eframe::App for EnvoyApp {
                             row.col(|ui| {
                                 if ui
                                     .add_enabled(
-                                        self.status.can_ecc_go_forward(ridx),
+                                        is_connected
+                                            && !is_sequence_running
+                                            && self.session.get_status().can_ecc_go_forward(ridx),
                                         Button::new(
                                             RichText::new("\u{25B6}").color(Color32::GREEN),
                                         ),
@@ -763,17 +824,35 @@ impl eframe::App for EnvoyApp {
                                     forward_transitions.push(ridx);
                                 }
                             });
+                            row.col(|ui| {
+                                ui.label(format_last_contact(
+                                    self.session.get_status().get_ecc_last_contact(ridx),
+                                ));
+                            });
+                            row.col(|ui| {
+                                if let Some(error) = self.session.get_status().get_ecc_error(ridx) {
+                                    ui.label(RichText::new(error).color(Color32::LIGHT_RED))
+                                        .on_hover_text(error);
+                                }
+                            });
                         });
                     });
                 ui.separator();
             });
-            self.transition_ecc(forward_transitions, true);
-            self.transition_ecc(backward_transitions, false);
+            for id in forward_transitions {
+                Self::log_error(self.session.progress_module(id));
+            }
+            for id in backward_transitions {
+                Self::log_error(self.session.regress_module(id));
+            }
+            for id in mask_toggles {
+                Self::log_error(self.session.toggle_mask(id as i32));
+            }
         });
 
         //Central panel showing Data router info. Use central to allow for dynamic resizing of the window.
         eframe::egui::CentralPanel::default().show(ctx, |ui| {
-            let surv_system_stat = self.status.get_surveyor_system_status();
+            let surv_system_stat = self.session.get_status().get_surveyor_system_status();
             ui.label(
                 RichText::new("Data Router Status")
                     .color(Color32::LIGHT_BLUE)
@@ -792,6 +871,7 @@ impl eframe::App for EnvoyApp {
                     .striped(true)
                     .column(egui_extras::Column::auto().at_least(90.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(50.0).resizable(true))
+                    .column(egui_extras::Column::auto().at_least(90.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(150.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(100.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(50.0).resizable(true))
@@ -799,6 +879,8 @@ impl eframe::App for EnvoyApp {
                     .column(egui_extras::Column::auto().at_least(140.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(100.0).resizable(true))
                     .column(egui_extras::Column::auto().at_least(100.0).resizable(true))
+                    .column(egui_extras::Column::auto().at_least(100.0).resizable(true))
+                    .column(egui_extras::Column::auto().at_least(100.0).resizable(true))
                     .header(40.0, |mut header| {
                         header.col(|ui| {
                             ui.heading("Envoy");
@@ -806,6 +888,9 @@ impl eframe::App for EnvoyApp {
                         header.col(|ui| {
                             ui.heading("Status");
                         });
+                        header.col(|ui| {
+                            ui.heading("Last Contact");
+                        });
                         header.col(|ui| {
                             ui.heading("Location");
                         });
@@ -821,27 +906,60 @@ impl eframe::App for EnvoyApp {
                         header.col(|ui| {
                             ui.heading("Data Rate (MB/s)");
                         });
+                        header.col(|ui| {
+                            ui.heading("Data Flow");
+                        });
                         header.col(|ui| {
                             ui.heading("%Disk Used");
                         });
                         header.col(|ui| {
                             ui.heading("Disk Size");
                         });
+                        header.col(|ui| {
+                            ui.heading("Full In");
+                        });
                     })
                     .body(|body| {
-                        let surveyor_status = self.status.get_surveyor_status_response();
+                        let surveyor_status =
+                            self.session.get_status().get_surveyor_status_response();
+                        let surveyor_ids = self.session.get_status().get_surveyor_ids();
                         body.rows(40.0, surveyor_status.len(), |ridx, mut row| {
                             let status = &surveyor_status[ridx];
                             let disk_stat = SurveyorDiskStatus::from(status.disk_status.as_str());
                             row.col(|ui| {
-                                ui.label(
-                                    RichText::new(format!("Data Router {}", ridx))
-                                        .color(Color32::LIGHT_GREEN),
+                                module_label(
+                                    ui,
+                                    format!("Data Router {}", surveyor_ids[ridx]),
+                                    self.log_console.get_surveyor_error(surveyor_ids[ridx]),
+                                    None,
                                 );
                             });
                             row.col(|ui| {
-                                let surv_type = SurveyorState::from(status.state);
-                                ui.label(RichText::new(format!("{}", surv_type)).color(&surv_type));
+                                if self
+                                    .session
+                                    .get_status()
+                                    .is_masked(surveyor_ids[ridx] as usize)
+                                {
+                                    ui.label(RichText::new("Masked").color(Color32::DARK_GRAY));
+                                } else {
+                                    let surv_type = SurveyorState::from(status.state);
+                                    status_label(
+                                        ui,
+                                        format!("{}", surv_type),
+                                        (&surv_type).into(),
+                                        &status.connection,
+                                        self.session
+                                            .get_status()
+                                            .is_surveyor_stale(surveyor_ids[ridx]),
+                                    );
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format_last_contact(
+                                    self.session
+                                        .get_status()
+                                        .get_surveyor_last_contact(surveyor_ids[ridx]),
+                                ));
                             });
                             row.col(|ui| {
                                 ui.label(RichText::new(status.location.clone()));
@@ -853,30 +971,153 @@ impl eframe::App for EnvoyApp {
                                 ui.label(RichText::new(format!("{}", status.files)));
                             });
                             row.col(|ui| {
-                                ui.label(RichText::new(format!(
-                                    "{}",
-                                    human_bytes::human_bytes(status.bytes_used as f64)
+                                ui.label(RichText::new(human_bytes::human_bytes(
+                                    status.bytes_used as f64,
                                 )));
                             });
                             row.col(|ui| {
                                 ui.label(RichText::new(format!("{:.3}", status.data_rate)));
                             });
+                            row.col(|ui| {
+                                let flow = match self.session.get_data_flow(surveyor_ids[ridx]) {
+                                    Some(flow) => flow,
+                                    None => return,
+                                };
+                                let label = ui.label(
+                                    RichText::new(flow.to_string()).color(flow),
+                                );
+                                match flow {
+                                    DataFlow::Ok => (),
+                                    DataFlow::Stalled(_) => {
+                                        label.on_hover_text(
+                                            "No data written, check the CoBo and its data link",
+                                        );
+                                    }
+                                    DataFlow::LowRate { rate, median } => {
+                                        label.on_hover_text(format!(
+                                            "{:.3} MB/s against a median of {:.3} MB/s (1 min averages), check the AsAds",
+                                            rate, median
+                                        ));
+                                    }
+                                }
+                            });
                             row.col(|ui| {
                                 ui.label(RichText::new(status.percent_used.clone()));
                             });
                             row.col(|ui| {
-                                ui.label(RichText::new(format!(
-                                    "{}",
-                                    human_bytes::human_bytes(status.disk_space as f64)
+                                ui.label(RichText::new(human_bytes::human_bytes(
+                                    status.disk_space as f64,
                                 )));
                             });
+                            row.col(|ui| {
+                                if self
+                                    .session
+                                    .get_status()
+                                    .is_masked(surveyor_ids[ridx] as usize)
+                                {
+                                    return;
+                                }
+                                match self.session.get_disk_forecast(surveyor_ids[ridx]) {
+                                    Some(forecast) => {
+                                        ui.label(
+                                            RichText::new(format_time_to_full(
+                                                forecast.time_to_full,
+                                            ))
+                                            .color(&forecast.level),
+                                        )
+                                        .on_hover_text(
+                                            format!(
+                                            "{}: {} left before {}% at {:.3} MB/s (1 min average)",
+                                            forecast.level,
+                                            human_bytes::human_bytes(forecast.bytes_left as f64),
+                                            self.session.config.disk_forecast.full_percent,
+                                            forecast.rate
+                                        ),
+                                        );
+                                    }
+                                    None => {
+                                        ui.label(RichText::new("N/A"));
+                                    }
+                                }
+                            });
                         })
                     });
             });
 
             ui.separator();
+            let restarts = self.session.get_status().get_total_restarts();
+            eframe::egui::CollapsingHeader::new(
+                RichText::new(format!("Envoy Health ({restarts} restarts)")).size(16.0),
+            )
+            .id_source("Envoy_Health")
+            .show(ui, |ui| {
+                ui.push_id(2, |ui| {
+                    let health = self.session.get_status().get_envoy_health();
+                    egui_extras::TableBuilder::new(ui)
+                        .striped(true)
+                        .column(egui_extras::Column::auto().at_least(90.0).resizable(true))
+                        .column(egui_extras::Column::auto().at_least(120.0).resizable(true))
+                        .column(egui_extras::Column::auto().at_least(90.0).resizable(true))
+                        .column(egui_extras::Column::auto().at_least(70.0).resizable(true))
+                        .column(egui_extras::Column::remainder().at_least(100.0))
+                        .header(30.0, |mut header| {
+                            header.col(|ui| {
+                                ui.heading("Module");
+                            });
+                            header.col(|ui| {
+                                ui.heading("Task");
+                            });
+                            header.col(|ui| {
+                                ui.heading("Health");
+                            });
+                            header.col(|ui| {
+                                ui.heading("Restarts");
+                            });
+                            header.col(|ui| {
+                                ui.heading("Last Failure");
+                            });
+                        })
+                        .body(|body| {
+                            body.rows(25.0, health.len(), |ridx, mut row| {
+                                let (id, health) = health[ridx];
+                                row.col(|ui| {
+                                    ui.label(
+                                        RichText::new(format!("{id}")).color(Color32::LIGHT_GREEN),
+                                    );
+                                });
+                                row.col(|ui| {
+                                    ui.label(format!("{}", health.kind));
+                                });
+                                row.col(|ui| {
+                                    ui.label(
+                                        RichText::new(format!("{}", health.state))
+                                            .color(&health.state),
+                                    );
+                                });
+                                row.col(|ui| {
+                                    ui.label(format!("{}", health.restarts));
+                                });
+                                row.col(|ui| {
+                                    if let Some(error) = &health.last_error {
+                                        ui.label(RichText::new(error).color(Color32::LIGHT_RED))
+                                            .on_hover_text(error);
+                                    }
+                                });
+                            });
+                        });
+                });
+            });
         });
 
-        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        self.log_console
+            .show(ctx, &self.session.get_topology().module_ids());
+
+        //Status snapshots are published from here, so keep them fresh while a sequence is waiting on them.
+        //Replays are advanced from here too.
+        if is_sequence_running || is_replaying {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
     }
 }
@@ -1,48 +1,56 @@
-use super::rate_graph::RateGraph;
-use crate::envoy::constants::NUMBER_OF_MODULES;
-use crate::envoy::error::EmbassyError;
-use crate::envoy::message::{EmbassyMessage, MessageKind};
-use crate::envoy::surveyor_envoy::SurveyorResponse;
+use super::rate_graph::{RateGraph, RateSmoothing};
+use crate::envoy::message::EmbassyMessage;
+use crate::envoy::topology::Topology;
 
 /// # Graph Manager
 /// Structure used to manage RateGraphs for the UI. Acts in observer-like role, reading a list of messages
-/// from the embassy and trasmitting relevant data to the graph of interest.
+/// from the embassy and trasmitting relevant data to the graph of interest. There is one graph per data router.
 #[derive(Debug)]
 pub struct GraphManager {
     graphs: Vec<RateGraph>,
+    surveyor_ids: Vec<i32>,
     max_points: usize,
+    smoothing: RateSmoothing,
 }
 
 impl GraphManager {
-    pub fn new(max_points: usize) -> Self {
+    pub fn new(topology: &Topology, max_points: usize) -> Self {
+        let surveyor_ids = topology.surveyor_ids();
         let mut graphs: Vec<RateGraph> = vec![];
-        for i in 0..(NUMBER_OF_MODULES - 1) {
+        for i in surveyor_ids.iter() {
             graphs.push(RateGraph::new(&format!("envoy_{i}"), &max_points));
         }
-        return Self { graphs, max_points };
+        return Self {
+            graphs,
+            surveyor_ids,
+            max_points,
+            smoothing: RateSmoothing::Instantaneous,
+        };
     }
 
     /// Read messages from the embassy, looking for SurveyorResponses. If one is found, send
-    /// the rate value to the appropriate graph
-    pub fn handle_messages(&mut self, messages: &[EmbassyMessage]) -> Result<(), EmbassyError> {
+    /// the rate value and the time it was measured to the appropriate graph. Responses where the surveyor could not be
+    /// read have no measurement time and are left off.
+    pub fn handle_messages(&mut self, messages: &[EmbassyMessage]) {
         for message in messages {
-            match message.kind {
-                MessageKind::Surveyor => {
-                    if let Some(graph) = self.graphs.get_mut(message.id as usize) {
-                        let response: SurveyorResponse = message.try_into()?;
-                        graph.add_point(response.data_rate);
-                    }
+            if let EmbassyMessage::Surveyor { id, response, .. } = message {
+                let measured_at = match response.measured_at {
+                    Some(time) => time,
+                    None => continue,
+                };
+                if let Some(idx) = self.surveyor_ids.iter().position(|sid| sid == id) {
+                    self.graphs[idx].add_point(measured_at, response.data_rate);
                 }
-                _ => continue,
-            };
+            }
         }
-
-        Ok(())
     }
 
-    /// Get all of the graphs as egui_plot::Lines
+    /// Get all of the graphs as egui_plot::Lines, smoothed
     pub fn get_line_graphs(&self) -> Vec<egui_plot::Line> {
-        self.graphs.iter().map(|g| g.get_points_to_draw()).collect()
+        self.graphs
+            .iter()
+            .map(|g| g.get_points_to_draw(&self.smoothing))
+            .collect()
     }
 
     pub fn reset_graphs(&mut self) {
@@ -61,4 +69,12 @@ impl GraphManager {
     pub fn get_max_points(&self) -> &usize {
         &self.max_points
     }
+
+    pub fn set_smoothing(&mut self, smoothing: RateSmoothing) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn get_smoothing(&self) -> &RateSmoothing {
+        &self.smoothing
+    }
 }
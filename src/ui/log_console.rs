@@ -0,0 +1,389 @@
+use crate::envoy::supervisor::EnvoyKind;
+use eframe::egui::{Color32, RichText};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Number of events kept by the log console
+pub const LOG_CAPACITY: usize = 5000;
+/// Errors are highlighted in the status tables for this long
+const ERROR_HIGHLIGHT_TIME: Duration = Duration::from_secs(300);
+
+/// An event recorded by the LogBufferLayer. The module id and envoy kind come from the fields `module` and `envoy`,
+/// either of the event itself or of the spans it happened in (i.e. the span of an envoy task).
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: SystemTime,
+    pub level: Level,
+    pub message: String,
+    pub module: Option<i32>,
+    pub envoy: Option<String>,
+}
+
+/// Format a wall clock time as HH:MM:SS (UTC)
+pub fn format_clock_time(time: &SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(t) => t.as_secs() % 86400,
+        Err(_) => 0,
+    };
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+#[derive(Debug, Default)]
+struct LogStore {
+    entries: VecDeque<LogEntry>,
+    last_errors: HashMap<(i32, Option<String>), LogEntry>,
+}
+
+/// # LogBuffer
+/// A bounded, shared buffer of the most recent log events. Once full the oldest events are dropped.
+/// The last error of every module (and envoy kind) is kept separately so that the status tables can highlight it.
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    store: Arc<Mutex<LogStore>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        return Self {
+            store: Arc::new(Mutex::new(LogStore::default())),
+            capacity,
+        };
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LogStore> {
+        match self.store.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn push(&self, entry: LogEntry) {
+        let mut store = self.lock();
+        if entry.level == Level::ERROR {
+            if let Some(module) = entry.module {
+                store
+                    .last_errors
+                    .insert((module, entry.envoy.clone()), entry.clone());
+            }
+        }
+        if store.entries.len() >= self.capacity {
+            store.entries.pop_front();
+        }
+        store.entries.push_back(entry);
+    }
+
+    /// Every event which passes the filter, oldest first
+    pub fn get_entries(&self, filter: &LogFilter) -> Vec<LogEntry> {
+        self.lock()
+            .entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .cloned()
+            .collect()
+    }
+
+    /// The most recent error logged for a module by one of the given kinds of envoy (None matches events from outside
+    /// the envoys), if it happened recently
+    pub fn get_last_error(&self, module: i32, envoys: &[Option<&str>]) -> Option<LogEntry> {
+        let store = self.lock();
+        envoys
+            .iter()
+            .filter_map(|envoy| store.last_errors.get(&(module, envoy.map(String::from))))
+            .filter(|e| match e.time.elapsed() {
+                Ok(age) => age < ERROR_HIGHLIGHT_TIME,
+                Err(_) => true,
+            })
+            .max_by_key(|e| e.time)
+            .cloned()
+    }
+
+    pub fn clear(&self) {
+        let mut store = self.lock();
+        store.entries.clear();
+        store.last_errors.clear();
+    }
+}
+
+/// The fields of a span which are attached to the events inside it
+struct SpanFields {
+    module: Option<i32>,
+    envoy: Option<String>,
+}
+
+#[derive(Default)]
+struct EntryVisitor {
+    message: String,
+    fields: String,
+    module: Option<i32>,
+    envoy: Option<String>,
+}
+
+impl Visit for EntryVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        match field.name() {
+            "module" => self.module = Some(value as i32),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "module" => self.module = Some(value as i32),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "envoy" => self.envoy = Some(String::from(value)),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            "envoy" => self.envoy = Some(format!("{:?}", value)),
+            name => {
+                let _ = write!(self.fields, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+/// # LogBufferLayer
+/// A tracing layer which records every event it sees in a LogBuffer
+pub struct LogBufferLayer {
+    buffer: LogBuffer,
+}
+
+impl LogBufferLayer {
+    pub fn new(buffer: &LogBuffer) -> Self {
+        return Self {
+            buffer: buffer.clone(),
+        };
+    }
+}
+
+impl<S> Layer<S> for LogBufferLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = EntryVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields {
+                module: visitor.module,
+                envoy: visitor.envoy,
+            });
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = EntryVisitor::default();
+        event.record(&mut visitor);
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    if visitor.module.is_none() {
+                        visitor.module = fields.module;
+                    }
+                    if visitor.envoy.is_none() {
+                        visitor.envoy = fields.envoy.clone();
+                    }
+                }
+            }
+        }
+        self.buffer.push(LogEntry {
+            time: SystemTime::now(),
+            level: *event.metadata().level(),
+            message: visitor.message + &visitor.fields,
+            module: visitor.module,
+            envoy: visitor.envoy,
+        });
+    }
+}
+
+/// Which events the log console shows
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    pub level: Level,
+    pub module: Option<i32>,
+    pub envoy: Option<EnvoyKind>,
+    pub search: String,
+}
+
+impl LogFilter {
+    fn matches(&self, entry: &LogEntry) -> bool {
+        //Less verbose levels compare as smaller
+        if entry.level > self.level {
+            return false;
+        }
+        if self.module.is_some() && entry.module != self.module {
+            return false;
+        }
+        if let Some(kind) = &self.envoy {
+            if entry.envoy.as_deref() != Some(kind.to_string().as_str()) {
+                return false;
+            }
+        }
+        if !self.search.is_empty()
+            && !entry
+                .message
+                .to_lowercase()
+                .contains(&self.search.to_lowercase())
+        {
+            return false;
+        }
+        return true;
+    }
+}
+
+fn level_color(level: &Level) -> Color32 {
+    match *level {
+        Level::ERROR => Color32::LIGHT_RED,
+        Level::WARN => Color32::GOLD,
+        Level::INFO => Color32::LIGHT_GRAY,
+        _ => Color32::GRAY,
+    }
+}
+
+/// # LogConsole
+/// A window showing the events in a LogBuffer, filtered by level, module, envoy kind and text
+#[derive(Debug)]
+pub struct LogConsole {
+    buffer: LogBuffer,
+    filter: LogFilter,
+    pub is_open: bool,
+}
+
+impl LogConsole {
+    pub fn new(buffer: LogBuffer) -> Self {
+        return Self {
+            buffer,
+            filter: LogFilter {
+                level: Level::INFO,
+                module: None,
+                envoy: None,
+                search: String::new(),
+            },
+            is_open: false,
+        };
+    }
+
+    /// The most recent error logged for a module's ECC server, if it happened recently
+    pub fn get_ecc_error(&self, module: i32) -> Option<LogEntry> {
+        let transition = EnvoyKind::ECCTransition.to_string();
+        let status = EnvoyKind::ECCStatus.to_string();
+        self.buffer.get_last_error(
+            module,
+            &[None, Some(transition.as_str()), Some(status.as_str())],
+        )
+    }
+
+    /// The most recent error logged for a module's Surveyor, if it happened recently
+    pub fn get_surveyor_error(&self, module: i32) -> Option<LogEntry> {
+        let surveyor = EnvoyKind::Surveyor.to_string();
+        self.buffer
+            .get_last_error(module, &[Some(surveyor.as_str())])
+    }
+
+    pub fn show(&mut self, ctx: &eframe::egui::Context, module_ids: &[i32]) {
+        let mut is_open = self.is_open;
+        eframe::egui::Window::new("Log Console")
+            .open(&mut is_open)
+            .default_size([900.0, 400.0])
+            .show(ctx, |ui| self.draw(ui, module_ids));
+        self.is_open = is_open;
+    }
+
+    fn draw(&mut self, ui: &mut eframe::egui::Ui, module_ids: &[i32]) {
+        ui.horizontal(|ui| {
+            eframe::egui::ComboBox::from_label("Level")
+                .selected_text(self.filter.level.to_string())
+                .show_ui(ui, |ui| {
+                    for level in [Level::ERROR, Level::WARN, Level::INFO] {
+                        let text = level.to_string();
+                        ui.selectable_value(&mut self.filter.level, level, text);
+                    }
+                });
+            eframe::egui::ComboBox::from_label("Module")
+                .selected_text(match self.filter.module {
+                    Some(id) => id.to_string(),
+                    None => String::from("All"),
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.module, None, "All");
+                    for id in module_ids {
+                        ui.selectable_value(&mut self.filter.module, Some(*id), id.to_string());
+                    }
+                });
+            eframe::egui::ComboBox::from_label("Envoy")
+                .selected_text(match &self.filter.envoy {
+                    Some(kind) => kind.to_string(),
+                    None => String::from("All"),
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.envoy, None, "All");
+                    for kind in [
+                        EnvoyKind::ECCTransition,
+                        EnvoyKind::ECCStatus,
+                        EnvoyKind::Surveyor,
+                    ] {
+                        ui.selectable_value(&mut self.filter.envoy, Some(kind), kind.to_string());
+                    }
+                });
+            ui.label("Search");
+            ui.text_edit_singleline(&mut self.filter.search);
+            if ui.button("Clear").clicked() {
+                self.buffer.clear();
+            }
+        });
+        ui.separator();
+
+        let entries = self.buffer.get_entries(&self.filter);
+        let row_height = ui.text_style_height(&eframe::egui::TextStyle::Body);
+        eframe::egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, entries.len(), |ui, range| {
+                for entry in &entries[range] {
+                    let color = level_color(&entry.level);
+                    let mut source = String::new();
+                    if let Some(module) = entry.module {
+                        source = format!("[{}]", module);
+                    }
+                    if let Some(envoy) = &entry.envoy {
+                        source = format!("{} {}", source, envoy);
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(format_clock_time(&entry.time)).monospace());
+                        ui.label(
+                            RichText::new(format!("{:5}", entry.level.to_string()))
+                                .monospace()
+                                .color(color),
+                        );
+                        if !source.is_empty() {
+                            ui.label(RichText::new(source.trim()).color(Color32::LIGHT_BLUE));
+                        }
+                        ui.label(RichText::new(&entry.message).color(color));
+                    });
+                }
+            });
+    }
+}
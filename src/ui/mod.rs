@@ -1,6 +1,6 @@
+mod alarm_panel;
 pub mod app;
-mod config;
 mod graph_manager;
+pub mod log_console;
 mod rate_graph;
 mod status_colors;
-mod status_manager;
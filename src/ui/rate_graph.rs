@@ -1,58 +1,134 @@
+use super::log_console::format_clock_time;
 use egui_plot::Line;
 use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// # RateSmoothing
+/// The choices of averaging for the rate graphs. The surveyor rates are instantaneous (from one poll to the next), which
+/// is noisy when the data comes in bursts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateSmoothing {
+    Instantaneous,
+    TenSeconds,
+    OneMinute,
+}
+
+impl RateSmoothing {
+    pub const ALL: [RateSmoothing; 3] = [
+        RateSmoothing::Instantaneous,
+        RateSmoothing::TenSeconds,
+        RateSmoothing::OneMinute,
+    ];
+
+    /// The length of the averaging window in seconds, zero for no averaging
+    pub fn get_window(&self) -> f64 {
+        match self {
+            Self::Instantaneous => 0.0,
+            Self::TenSeconds => 10.0,
+            Self::OneMinute => 60.0,
+        }
+    }
+}
+
+impl std::fmt::Display for RateSmoothing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Instantaneous => write!(f, "Instantaneous"),
+            Self::TenSeconds => write!(f, "10 s Average"),
+            Self::OneMinute => write!(f, "1 min Average"),
+        }
+    }
+}
+
+/// The longest averaging window; older points are kept around this long so the oldest drawn point can be smoothed
+const MAX_SMOOTHING_WINDOW: f64 = 60.0;
+
+/// Format a time on the graph (seconds since the Unix epoch) as a UTC clock time, HH:MM:SS
+pub fn format_graph_time(time: f64) -> String {
+    //Only the time of day is shown, and keeping to one day cannot overflow the SystemTime
+    let secs = (time.max(0.0) as u64) % 86400;
+    format_clock_time(&(UNIX_EPOCH + Duration::from_secs(secs)))
+}
 
 /// # RateGraph
 /// Implementation of a graph for our data. Under the hood, it's just a double
-/// ended queue of data. If the queue reaches the maximum allowed size, then the oldest
-/// data point is dropped to add the new one (creates the ticker-tape effect).
+/// ended queue of (time, rate) points, where the time is when the surveyor was read in seconds since the Unix epoch.
+/// Once there are more than the maximum allowed points, the oldest are dropped (creates the ticker-tape effect).
+///
+/// Each rate covers the time since the point before it, so a smoothed point is the average of the rates weighted by
+/// how much of the window they cover.
 #[derive(Debug)]
 pub struct RateGraph {
-    points: VecDeque<f64>,
+    points: VecDeque<[f64; 2]>,
     max_points: usize,
-    last_time: f64,
-    time_increment: f64,
     name: String,
 }
 
 impl RateGraph {
     /// create a named graph with a max size
-    ///
-    /// Note: time increment is hard coded, should probably fix that.
     pub fn new(name: &str, max_points: &usize) -> Self {
         Self {
             points: VecDeque::new(),
             max_points: *max_points,
-            last_time: 0.0,
-            time_increment: 2.0,
             name: String::from(name),
         }
     }
 
-    pub fn add_point(&mut self, rate: f64) {
-        if self.points.len() == self.max_points {
+    /// Add a rate measured at the given time. A time before the last point (i.e. a replay started over) starts the
+    /// graph over.
+    pub fn add_point(&mut self, time: SystemTime, rate: f64) {
+        let time = match time.duration_since(UNIX_EPOCH) {
+            Ok(t) => t.as_secs_f64(),
+            Err(_) => return,
+        };
+        if let Some(last) = self.points.back() {
+            if time < last[0] {
+                self.points.clear();
+            }
+        }
+        self.points.push_back([time, rate]);
+
+        //Keep the points the oldest drawn point needs for smoothing, and nothing older
+        while self.points.len() > self.max_points.max(1) {
+            let oldest_drawn = self.points[self.points.len() - self.max_points.max(1)][0];
+            if self.points[1][0] > oldest_drawn - MAX_SMOOTHING_WINDOW {
+                break;
+            }
             self.points.pop_front();
         }
-        let current_time = self.last_time + self.time_increment;
-        self.points.push_back(rate);
-        self.last_time = current_time;
     }
 
-    /// Convert the data to a egui_plot::Line.
-    ///
-    /// Note: This might suck. egui_plot::PlotPoints requires a Vec, and that vec *has* to be cloned. A Vec would be bad for
-    /// our underlying data structure cause we need to remove points efficiently... but this conversion might be so costly (happens every frame)
-    /// that it outwieghs the cost of using a vec natively... but the Vec still would have to be cloned sooooooo...
-    ///
-    /// Needs some serious testing.
-    pub fn get_points_to_draw(&self) -> Line {
-        let total_len = self.points.len() as i32 - 1;
+    /// The rate at the point idx, averaged over the window before it
+    fn smoothed_rate(&self, idx: usize, window: f64) -> f64 {
+        let end = self.points[idx];
+        if window <= 0.0 {
+            return end[1];
+        }
+        let start = end[0] - window;
+        let mut sum = 0.0;
+        let mut weight = 0.0;
+        for i in (1..=idx).rev() {
+            let (previous, point) = (self.points[i - 1], self.points[i]);
+            if point[0] <= start {
+                break;
+            }
+            let covered = point[0] - previous[0].max(start);
+            sum += point[1] * covered;
+            weight += covered;
+        }
+        if weight > 0.0 {
+            return sum / weight;
+        }
+        return end[1];
+    }
+
+    /// Convert the data to a egui_plot::Line, with the rates smoothed
+    pub fn get_points_to_draw(&self, smoothing: &RateSmoothing) -> Line {
+        let window = smoothing.get_window();
+        let first = self.points.len().saturating_sub(self.max_points);
         let graph = Line::new(
-            self.points
-                .clone()
-                .into_iter()
-                .enumerate()
-                .rev()
-                .map(|(i, rate)| [((i as i32 - total_len) as f64) * self.time_increment, rate])
+            (first..self.points.len())
+                .map(|i| [self.points[i][0], self.smoothed_rate(i, window)])
                 .collect::<Vec<[f64; 2]>>(),
         )
         .name(&self.name);
@@ -61,7 +137,6 @@ impl RateGraph {
 
     pub fn reset(&mut self) {
         self.points.clear();
-        self.last_time = 0.0;
     }
 
     pub fn change_max_points(&mut self, max_points: &usize) {
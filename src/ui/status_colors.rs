@@ -1,5 +1,10 @@
+use crate::envoy::connection_status::ConnectionStatus;
 use crate::envoy::ecc_operation::ECCStatus;
+use crate::envoy::supervisor::EnvoyTaskState;
 use crate::envoy::surveyor_state::{SurveyorDiskStatus, SurveyorState};
+use crate::session::alarms::AlarmSeverity;
+use crate::session::data_monitor::DataFlow;
+use crate::session::disk_forecast::DiskAlarmLevel;
 use eframe::egui::Color32;
 
 impl Into<Color32> for &ECCStatus {
@@ -36,3 +41,55 @@ impl Into<Color32> for &SurveyorDiskStatus {
         }
     }
 }
+
+impl Into<Color32> for &ConnectionStatus {
+    fn into(self) -> Color32 {
+        match self {
+            ConnectionStatus::NoContact => Color32::LIGHT_GRAY,
+            ConnectionStatus::Connected => Color32::GREEN,
+            ConnectionStatus::Unreachable(_) => Color32::RED,
+            ConnectionStatus::Timeout(_) => Color32::LIGHT_RED,
+            ConnectionStatus::ParseError(_) => Color32::KHAKI,
+        }
+    }
+}
+
+impl Into<Color32> for &EnvoyTaskState {
+    fn into(self) -> Color32 {
+        match self {
+            EnvoyTaskState::Running => Color32::GREEN,
+            EnvoyTaskState::Restarting => Color32::GOLD,
+            EnvoyTaskState::Stopped => Color32::LIGHT_GRAY,
+        }
+    }
+}
+
+impl Into<Color32> for &DiskAlarmLevel {
+    fn into(self) -> Color32 {
+        match self {
+            DiskAlarmLevel::Ok => Color32::GREEN,
+            DiskAlarmLevel::Warning => Color32::GOLD,
+            DiskAlarmLevel::Critical => Color32::RED,
+        }
+    }
+}
+
+impl Into<Color32> for &DataFlow {
+    fn into(self) -> Color32 {
+        match self {
+            DataFlow::Ok => Color32::GREEN,
+            DataFlow::Stalled(_) => Color32::RED,
+            DataFlow::LowRate { .. } => Color32::GOLD,
+        }
+    }
+}
+
+impl Into<Color32> for &AlarmSeverity {
+    fn into(self) -> Color32 {
+        match self {
+            AlarmSeverity::Info => Color32::LIGHT_BLUE,
+            AlarmSeverity::Warning => Color32::GOLD,
+            AlarmSeverity::Critical => Color32::RED,
+        }
+    }
+}
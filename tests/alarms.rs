@@ -0,0 +1,200 @@
+//! Tests of the alarm rules and the hold-off, latch, and acknowledge workflow
+use attpc_envoy::envoy::connection_status::ConnectionStatus;
+use attpc_envoy::envoy::message::{EmbassyMessage, MessageTiming};
+use attpc_envoy::envoy::supervisor::EnvoyKind;
+use attpc_envoy::envoy::surveyor_envoy::SurveyorResponse;
+use attpc_envoy::envoy::topology::Topology;
+use attpc_envoy::session::alarms::{
+    AlarmChange, AlarmContext, AlarmEngine, AlarmRules, AlarmSeverity,
+};
+use attpc_envoy::session::error::AlarmError;
+use attpc_envoy::session::status_manager::StatusManager;
+use std::time::{Duration, Instant};
+
+const DISK_RULES: &str = "
+rules:
+  - name: disk_filling
+    severity: warning
+    hold_off_secs: 10
+    condition:
+      disk_above:
+        percent: 85
+";
+
+const LATCHED_RULES: &str = "
+rules:
+  - name: surveyor_offline
+    severity: critical
+    latch: true
+    condition: surveyor_offline
+";
+
+fn set_surveyor(
+    status: &mut StatusManager,
+    id: i32,
+    percent_used: &str,
+    connection: ConnectionStatus,
+) {
+    let response = SurveyorResponse {
+        state: 1,
+        percent_used: String::from(percent_used),
+        connection,
+        ..Default::default()
+    };
+    status.handle_messages(&[EmbassyMessage::compose_surveyor_response(
+        response,
+        id,
+        MessageTiming::start(),
+    )]);
+}
+
+fn raised_ids(changes: &[AlarmChange]) -> Vec<u64> {
+    changes
+        .iter()
+        .filter_map(|c| match c {
+            AlarmChange::Raised(alarm) => Some(alarm.id),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn default_rules_parse() {
+    let rules = AlarmRules::default();
+    let names: Vec<&str> = rules.rules.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "ecc_not_running",
+            "surveyor_offline",
+            "low_data_rate",
+            "disk_filling",
+            "operation_failed"
+        ]
+    );
+    assert_eq!(rules.rules[0].severity, AlarmSeverity::Critical);
+    assert!(rules.rules[0].latch);
+}
+
+#[test]
+fn duplicate_rule_is_an_error() {
+    let yaml = format!("{DISK_RULES}{}", DISK_RULES.replace("rules:\n", ""));
+    match AlarmRules::parse(&yaml) {
+        Err(AlarmError::DuplicateRule(name)) => assert_eq!(name, "disk_filling"),
+        other => panic!("Expected a duplicate rule error, got {other:?}"),
+    }
+}
+
+#[test]
+fn hold_off_then_raise_then_clear() {
+    let topology = Topology::default();
+    let id = topology.surveyor_ids()[0];
+    let mut status = StatusManager::new(&topology);
+    let mut engine = AlarmEngine::new(AlarmRules::parse(DISK_RULES).unwrap());
+    let context = AlarmContext {
+        in_run: true,
+        run_number: 7,
+    };
+    let start = Instant::now();
+
+    set_surveyor(&mut status, id, "90%", ConnectionStatus::Connected);
+    assert!(engine.evaluate(start, &status, &context).is_empty());
+    assert!(engine
+        .evaluate(start + Duration::from_secs(9), &status, &context)
+        .is_empty());
+    let changes = engine.evaluate(start + Duration::from_secs(10), &status, &context);
+    assert_eq!(raised_ids(&changes).len(), 1);
+    let active = engine.get_active();
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].module, id);
+    assert_eq!(active[0].envoy, EnvoyKind::Surveyor);
+    assert_eq!(active[0].run_number, Some(7));
+
+    //Not latched, so it clears on its own
+    set_surveyor(&mut status, id, "80%", ConnectionStatus::Connected);
+    let changes = engine.evaluate(start + Duration::from_secs(11), &status, &context);
+    assert_eq!(changes.len(), 1);
+    assert!(engine.get_active().is_empty());
+    assert_eq!(engine.get_history().len(), 1);
+    assert!(engine.get_history()[0].cleared_at.is_some());
+}
+
+#[test]
+fn latched_until_acknowledged() {
+    let topology = Topology::default();
+    let id = topology.surveyor_ids()[0];
+    let mut status = StatusManager::new(&topology);
+    let mut engine = AlarmEngine::new(AlarmRules::parse(LATCHED_RULES).unwrap());
+    let context = AlarmContext {
+        in_run: false,
+        run_number: 7,
+    };
+    let start = Instant::now();
+
+    set_surveyor(
+        &mut status,
+        id,
+        "10%",
+        ConnectionStatus::Timeout(String::from("No response")),
+    );
+    let raised = raised_ids(&engine.evaluate(start, &status, &context));
+    assert_eq!(raised.len(), 1);
+    assert_eq!(engine.get_active()[0].run_number, None);
+
+    //The router comes back, but nobody has seen the alarm yet
+    set_surveyor(&mut status, id, "10%", ConnectionStatus::Connected);
+    assert!(engine.evaluate(start, &status, &context).is_empty());
+    assert_eq!(engine.get_active().len(), 1);
+    assert!(!engine.get_active()[0].condition_present);
+
+    let changes = engine.acknowledge(raised[0]).unwrap();
+    assert_eq!(changes.len(), 2);
+    assert!(engine.get_active().is_empty());
+    assert!(engine.get_history()[0].acknowledged_at.is_some());
+}
+
+#[test]
+fn acknowledge_missing_alarm() {
+    let mut engine = AlarmEngine::new(AlarmRules::default());
+    match engine.acknowledge(42) {
+        Err(AlarmError::NoAlarm(id)) => assert_eq!(id, 42),
+        other => panic!("Expected no alarm, got {other:?}"),
+    }
+    assert!(engine.acknowledge_all().is_empty());
+}
+
+#[test]
+fn reloading_rules_clears_alarms_of_removed_rules() {
+    let topology = Topology::default();
+    let id = topology.surveyor_ids()[0];
+    let mut status = StatusManager::new(&topology);
+    let mut engine = AlarmEngine::new(AlarmRules::parse(DISK_RULES).unwrap());
+    let context = AlarmContext {
+        in_run: true,
+        run_number: 7,
+    };
+    let start = Instant::now();
+
+    set_surveyor(&mut status, id, "90%", ConnectionStatus::Connected);
+    engine.evaluate(start, &status, &context);
+    engine.evaluate(start + Duration::from_secs(10), &status, &context);
+    assert_eq!(engine.get_active().len(), 1);
+
+    //The same rule is kept, so its alarm stays up
+    assert!(engine
+        .set_rules(AlarmRules::parse(DISK_RULES).unwrap())
+        .is_empty());
+    assert_eq!(engine.get_active().len(), 1);
+
+    //The rule is gone, so nothing could ever clear its alarm
+    let changes = engine.set_rules(AlarmRules::parse(LATCHED_RULES).unwrap());
+    match changes.as_slice() {
+        [AlarmChange::Cleared(alarm)] => assert_eq!(alarm.rule, "disk_filling"),
+        other => panic!("Expected the alarm to be cleared, got {other:?}"),
+    }
+    assert!(engine.get_active().is_empty());
+    assert!(engine.get_history()[0].cleared_at.is_some());
+    assert!(engine
+        .evaluate(start + Duration::from_secs(11), &status, &context)
+        .is_empty());
+}
@@ -0,0 +1,101 @@
+//! Tests of the stall and rate imbalance checks of the data monitor
+use attpc_envoy::session::data_monitor::{DataFlow, DataMonitor, DataMonitorOptions, RouterSample};
+use std::time::{Duration, SystemTime};
+
+fn sample(id: i32, start: SystemTime, secs: u64, bytes_used: u64, rate: f64) -> RouterSample {
+    RouterSample {
+        id,
+        bytes_used,
+        rate,
+        measured_at: start + Duration::from_secs(secs),
+    }
+}
+
+#[test]
+fn stall_and_recovery() {
+    let start = SystemTime::now();
+    let options = DataMonitorOptions::default();
+    let mut monitor = DataMonitor::new();
+    monitor.update(true, &[sample(1, start, 0, 100, 1.0)], &options);
+    assert_eq!(monitor.get_flow(1), Some(&DataFlow::Ok));
+
+    monitor.update(true, &[sample(1, start, 20, 200, 1.0)], &options);
+    monitor.update(true, &[sample(1, start, 48, 200, 1.0)], &options);
+    assert_eq!(monitor.get_flow(1), Some(&DataFlow::Ok));
+    monitor.update(true, &[sample(1, start, 50, 200, 1.0)], &options);
+    assert_eq!(
+        monitor.get_flow(1),
+        Some(&DataFlow::Stalled(Duration::from_secs(30)))
+    );
+
+    monitor.update(true, &[sample(1, start, 52, 300, 1.0)], &options);
+    assert_eq!(monitor.get_flow(1), Some(&DataFlow::Ok));
+}
+
+#[test]
+fn low_rate_against_median() {
+    let start = SystemTime::now();
+    let options = DataMonitorOptions::default();
+    let mut monitor = DataMonitor::new();
+    monitor.update(
+        true,
+        &[
+            sample(0, start, 0, 100, 5.0),
+            sample(1, start, 0, 100, 1.0),
+            sample(2, start, 0, 100, 4.0),
+            sample(3, start, 0, 100, 6.0),
+        ],
+        &options,
+    );
+    assert_eq!(monitor.get_median(), Some(4.5));
+    assert_eq!(
+        monitor.get_flow(1),
+        Some(&DataFlow::LowRate {
+            rate: 1.0,
+            median: 4.5
+        })
+    );
+    for id in [0, 2, 3] {
+        assert_eq!(monitor.get_flow(id), Some(&DataFlow::Ok));
+    }
+}
+
+#[test]
+fn slow_start_is_not_flagged() {
+    let start = SystemTime::now();
+    let options = DataMonitorOptions::default();
+    let mut monitor = DataMonitor::new();
+    //Below the minimum median nothing is compared
+    monitor.update(
+        true,
+        &[
+            sample(0, start, 0, 0, 0.05),
+            sample(1, start, 0, 0, 0.0),
+            sample(2, start, 0, 0, 0.08),
+        ],
+        &options,
+    );
+    for id in 0..3 {
+        assert_eq!(monitor.get_flow(id), Some(&DataFlow::Ok));
+    }
+}
+
+#[test]
+fn forgotten_when_not_running() {
+    let start = SystemTime::now();
+    let options = DataMonitorOptions::default();
+    let mut monitor = DataMonitor::new();
+    monitor.update(true, &[sample(1, start, 0, 100, 1.0)], &options);
+    monitor.update(true, &[sample(1, start, 40, 100, 1.0)], &options);
+    assert_eq!(
+        monitor.get_flow(1),
+        Some(&DataFlow::Stalled(Duration::from_secs(40)))
+    );
+
+    monitor.update(false, &[sample(1, start, 42, 100, 1.0)], &options);
+    assert_eq!(monitor.get_flow(1), None);
+    assert_eq!(monitor.get_median(), None);
+    //The next run starts the clock over
+    monitor.update(true, &[sample(1, start, 100, 100, 1.0)], &options);
+    assert_eq!(monitor.get_flow(1), Some(&DataFlow::Ok));
+}
@@ -0,0 +1,115 @@
+//! Tests of the data router disk forecasts
+use attpc_envoy::envoy::surveyor_envoy::SurveyorResponse;
+use attpc_envoy::session::disk_forecast::{
+    format_time_to_full, DiskAlarmLevel, DiskForecastOptions, DiskForecaster,
+};
+use std::time::{Duration, SystemTime};
+
+const TB: u64 = 1_000_000_000_000;
+
+/// A response from a 1 TB disk, measured some seconds after the start
+fn response(start: SystemTime, secs: u64, percent_used: &str, rate: f64) -> SurveyorResponse {
+    SurveyorResponse {
+        state: 1,
+        percent_used: String::from(percent_used),
+        disk_space: TB,
+        data_rate: rate,
+        measured_at: Some(start + Duration::from_secs(secs)),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn time_to_full_from_rate() {
+    let start = SystemTime::now();
+    let mut forecaster = DiskForecaster::new();
+    //The first rate only marks the start of the next
+    forecaster.add_response(1, &response(start, 0, "85%", 0.0));
+    let latest = response(start, 2, "85%", 100.0);
+    forecaster.add_response(1, &latest);
+
+    let forecast = forecaster
+        .forecast(1, &latest, &DiskForecastOptions::default())
+        .unwrap();
+    //10% of 1 TB left before 95%, at 100 MB/s
+    assert_eq!(forecast.bytes_left, 100_000_000_000);
+    assert_eq!(forecast.time_to_full, Some(Duration::from_secs(1000)));
+    assert_eq!(forecast.level, DiskAlarmLevel::Warning);
+}
+
+#[test]
+fn rate_is_averaged_over_intervals() {
+    let start = SystemTime::now();
+    let mut forecaster = DiskForecaster::new();
+    forecaster.add_response(1, &response(start, 0, "10%", 0.0));
+    forecaster.add_response(1, &response(start, 2, "10%", 10.0));
+    //A slow poll: this rate covers three times as long
+    forecaster.add_response(1, &response(start, 8, "10%", 2.0));
+    assert_eq!(forecaster.get_rate(1), Some((10.0 * 2.0 + 2.0 * 6.0) / 8.0));
+
+    //Only the last minute counts, so the slow poll from 8 s to 70 s only counts from 12 s
+    forecaster.add_response(1, &response(start, 70, "10%", 4.0));
+    forecaster.add_response(1, &response(start, 72, "10%", 6.0));
+    assert_eq!(
+        forecaster.get_rate(1),
+        Some((4.0 * 58.0 + 6.0 * 2.0) / 60.0)
+    );
+    assert_eq!(forecaster.get_rate(2), None);
+}
+
+#[test]
+fn alarm_levels() {
+    let start = SystemTime::now();
+    let options = DiskForecastOptions::default();
+    let mut forecaster = DiskForecaster::new();
+    forecaster.add_response(1, &response(start, 0, "50%", 0.0));
+    forecaster.add_response(1, &response(start, 2, "50%", 1.0));
+
+    let idle = response(start, 2, "50%", 1.0);
+    assert_eq!(
+        forecaster.forecast(1, &idle, &options).unwrap().level,
+        DiskAlarmLevel::Ok
+    );
+    //0.3% of 1 TB is 3 GB, 50 min at 1 MB/s
+    let nearly = response(start, 2, "94.7%", 1.0);
+    assert_eq!(
+        forecaster.forecast(1, &nearly, &options).unwrap().level,
+        DiskAlarmLevel::Warning
+    );
+    //Already past the full level
+    let full = response(start, 2, "96%", 1.0);
+    let forecast = forecaster.forecast(1, &full, &options).unwrap();
+    assert_eq!(forecast.time_to_full, Some(Duration::ZERO));
+    assert_eq!(forecast.level, DiskAlarmLevel::Critical);
+}
+
+#[test]
+fn not_filling_or_unknown() {
+    let start = SystemTime::now();
+    let options = DiskForecastOptions::default();
+    let mut forecaster = DiskForecaster::new();
+    let idle = response(start, 0, "50%", 0.0);
+    forecaster.add_response(1, &idle);
+    let forecast = forecaster.forecast(1, &idle, &options).unwrap();
+    assert_eq!(forecast.time_to_full, None);
+    assert_eq!(forecast.level, DiskAlarmLevel::Ok);
+
+    //Nothing to forecast from without the percent used, or without a measured rate
+    let unread = SurveyorResponse::default();
+    assert!(forecaster.forecast(1, &unread, &options).is_none());
+    assert!(forecaster.forecast(2, &idle, &options).is_none());
+}
+
+#[test]
+fn formatting() {
+    assert_eq!(format_time_to_full(None), "Never");
+    assert_eq!(format_time_to_full(Some(Duration::from_secs(45))), "45s");
+    assert_eq!(
+        format_time_to_full(Some(Duration::from_secs(750))),
+        "12m 30s"
+    );
+    assert_eq!(
+        format_time_to_full(Some(Duration::from_secs(7500))),
+        "2h 05m"
+    );
+}
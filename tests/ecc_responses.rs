@@ -0,0 +1,98 @@
+//! Regression tests for ECC SOAP reply parsing against the fixtures in tests/fixtures/ecc
+use attpc_envoy::envoy::error::EnvoyError;
+use attpc_envoy::envoy::soap::{parse_ecc_operation_response, parse_ecc_status_response};
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/ecc")
+        .join(name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read fixture {}: {}", path.display(), e))
+}
+
+#[test]
+fn status_compact() {
+    let resp = parse_ecc_status_response(&fixture("status_idle_compact.xml")).unwrap();
+    assert_eq!(resp.error_code, 0);
+    assert_eq!(resp.error_message, "");
+    assert_eq!(resp.state, 1);
+    assert_eq!(resp.transition, 0);
+}
+
+#[test]
+fn status_pretty_printed_with_other_prefixes() {
+    let resp = parse_ecc_status_response(&fixture("status_running_pretty.xml")).unwrap();
+    assert_eq!(resp.error_code, 0);
+    assert_eq!(resp.error_message, "");
+    assert_eq!(resp.state, 5);
+}
+
+#[test]
+fn status_elements_out_of_order() {
+    let resp = parse_ecc_status_response(&fixture("status_reordered.xml")).unwrap();
+    assert_eq!(resp.error_code, 0);
+    assert_eq!(resp.state, 4);
+    assert_eq!(resp.transition, 2);
+}
+
+#[test]
+fn status_with_error() {
+    let resp = parse_ecc_status_response(&fixture("status_error.xml")).unwrap();
+    assert_eq!(resp.error_code, 3);
+    assert_eq!(resp.error_message, "Could not connect to CoBo[4]");
+    assert_eq!(resp.state, 0);
+}
+
+#[test]
+fn operation_compact() {
+    let resp = parse_ecc_operation_response(&fixture("operation_ok_compact.xml")).unwrap();
+    assert_eq!(resp.error_code, 0);
+    assert_eq!(resp.error_message, "");
+    assert_eq!(resp.text, "Configure done");
+}
+
+#[test]
+fn operation_without_text() {
+    let resp = parse_ecc_operation_response(&fixture("operation_ok_no_text.xml")).unwrap();
+    assert_eq!(resp.error_code, 0);
+    assert_eq!(resp.text, "");
+}
+
+#[test]
+fn operation_with_error() {
+    let resp = parse_ecc_operation_response(&fixture("operation_error.xml")).unwrap();
+    assert_eq!(resp.error_code, 1);
+    assert_eq!(
+        resp.error_message,
+        "Configuration file configure-e20009.xcfg not found"
+    );
+}
+
+#[test]
+fn fault_is_decoded() {
+    for result in [
+        parse_ecc_operation_response(&fixture("fault.xml")).map(|_| ()),
+        parse_ecc_status_response(&fixture("fault.xml")).map(|_| ()),
+    ] {
+        match result {
+            Err(EnvoyError::SoapFault(code, message)) => {
+                assert_eq!(code, "SOAP-ENV:Client");
+                assert!(message.starts_with("Method 'Configur' not implemented"));
+            }
+            other => panic!("Expected a SoapFault, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn malformed_replies_are_errors() {
+    for name in [
+        "not_soap.html",
+        "status_truncated.xml",
+        "status_bad_state.xml",
+    ] {
+        let result = parse_ecc_status_response(&fixture(name));
+        assert!(result.is_err(), "{} should not parse", name);
+    }
+}
@@ -0,0 +1,458 @@
+//! In-process tests of the ECC and Surveyor envoys, which are given canned replies by an in-memory Transport
+use attpc_envoy::envoy::connection_status::ConnectionStatus;
+use attpc_envoy::envoy::ecc_envoy::{
+    ECCConfig, ECCEnvoy, ECC_ENVOY_ERROR_CODE, ECC_TIMEOUT_ERROR_CODE,
+};
+use attpc_envoy::envoy::ecc_operation::ECCOperation;
+use attpc_envoy::envoy::error::TransportError;
+use attpc_envoy::envoy::message::{EmbassyMessage, OperationId};
+use attpc_envoy::envoy::surveyor_envoy::{SurveyorConfig, SurveyorEnvoy, SurveyorResponse};
+use attpc_envoy::envoy::topology::Topology;
+use attpc_envoy::envoy::transport::{Transport, TransportFuture};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+
+fn fixture(kind: &str, name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(kind)
+        .join(name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read fixture {}: {}", path.display(), e))
+}
+
+/// A request seen by the CannedTransport: the url, and the body if it was a POST
+type Request = (String, Option<String>);
+
+/// Answers each request with the next of its replies, after a delay, and records the requests. Once out of replies the
+/// server cannot be reached.
+#[derive(Debug)]
+struct CannedTransport {
+    replies: Mutex<VecDeque<Result<String, TransportError>>>,
+    requests: Mutex<Vec<Request>>,
+    delay: Duration,
+}
+
+impl CannedTransport {
+    fn new(replies: Vec<Result<String, TransportError>>) -> Arc<Self> {
+        Self::slow(replies, Duration::ZERO)
+    }
+
+    fn slow(replies: Vec<Result<String, TransportError>>, delay: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            replies: Mutex::new(replies.into()),
+            requests: Mutex::new(vec![]),
+            delay,
+        })
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn reply(&self, url: &str, body: Option<String>) -> Result<String, TransportError> {
+        self.requests.lock().unwrap().push((url.to_string(), body));
+        match self.replies.lock().unwrap().pop_front() {
+            Some(reply) => reply,
+            None => Err(TransportError::Connect(String::from("No more replies"))),
+        }
+    }
+}
+
+impl Transport for CannedTransport {
+    fn post<'a>(&'a self, url: &'a str, body: String, _: Duration) -> TransportFuture<'a> {
+        let reply = self.reply(url, Some(body));
+        Box::pin(async move { reply })
+    }
+
+    fn get<'a>(&'a self, url: &'a str, _: Duration) -> TransportFuture<'a> {
+        let reply = self.reply(url, None);
+        Box::pin(async move {
+            tokio::time::sleep(self.delay).await;
+            reply
+        })
+    }
+}
+
+/// The channels of an envoy under test
+struct Harness {
+    to_envoy: mpsc::Sender<EmbassyMessage>,
+    from_envoy: mpsc::Receiver<EmbassyMessage>,
+    cancel: broadcast::Sender<EmbassyMessage>,
+}
+
+/// An ECC envoy for CoBo 1 of the full AT-TPC
+fn ecc_envoy(transport: &Arc<CannedTransport>) -> (ECCEnvoy, Harness) {
+    let topology = Topology::default();
+    let config = ECCConfig::new(topology.get_module(1).unwrap(), "e20009");
+    let (to_envoy, envoy_rx) = mpsc::channel(10);
+    let (envoy_tx, from_envoy) = mpsc::channel(10);
+    let (cancel, _) = broadcast::channel(10);
+    let envoy = ECCEnvoy::new(
+        config,
+        transport.clone(),
+        envoy_rx,
+        envoy_tx,
+        cancel.subscribe(),
+    );
+    let harness = Harness {
+        to_envoy,
+        from_envoy,
+        cancel,
+    };
+    return (envoy, harness);
+}
+
+/// A Surveyor envoy for CoBo 1 of the full AT-TPC
+fn surveyor_envoy(transport: &Arc<CannedTransport>) -> (SurveyorEnvoy, Harness) {
+    let topology = Topology::default();
+    let module = topology.get_module(1).unwrap();
+    let config = SurveyorConfig::new(1, module.surveyor.as_ref().unwrap());
+    let (to_envoy, _) = mpsc::channel(10);
+    let (envoy_tx, from_envoy) = mpsc::channel(10);
+    let (cancel, _) = broadcast::channel(10);
+    let envoy = SurveyorEnvoy::new(config, transport.clone(), envoy_tx, cancel.subscribe());
+    let harness = Harness {
+        to_envoy,
+        from_envoy,
+        cancel,
+    };
+    return (envoy, harness);
+}
+
+/// Submit an operation to a transition envoy and wait for its response
+async fn transition(
+    transport: &Arc<CannedTransport>,
+    operation: ECCOperation,
+) -> (EmbassyMessage, Harness) {
+    let (mut envoy, mut harness) = ecc_envoy(transport);
+    tokio::spawn(async move { envoy.wait_for_transition().await });
+    harness
+        .to_envoy
+        .send(EmbassyMessage::compose_ecc_op(operation, 1, OperationId(7)))
+        .await
+        .unwrap();
+    let message = harness.from_envoy.recv().await.unwrap();
+    return (message, harness);
+}
+
+#[tokio::test(start_paused = true)]
+async fn transition_is_answered() {
+    let transport = CannedTransport::new(vec![Ok(fixture("ecc", "operation_ok_compact.xml"))]);
+    let (message, _) = transition(&transport, ECCOperation::Configure).await;
+    match message {
+        EmbassyMessage::ECCOperationResponse {
+            id,
+            op_id,
+            operation,
+            response,
+            ..
+        } => {
+            assert_eq!(id, 1);
+            assert_eq!(op_id, OperationId(7));
+            assert_eq!(operation.to_string(), "Configure");
+            assert_eq!(response.error_code, 0);
+            assert_eq!(response.text, "Configure done");
+        }
+        other => panic!("Expected an operation response, got {}", other),
+    }
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    let (url, body) = &requests[0];
+    assert_eq!(url, "http://192.168.41.61:8083");
+    let body = body.as_ref().unwrap();
+    assert!(body.contains("<Configure>"));
+    assert!(body.contains("cobo1"));
+    assert!(body.contains(r#"<DataSender id="CoBo[1]" />"#));
+}
+
+#[tokio::test(start_paused = true)]
+async fn transition_error_is_reported() {
+    let transport = CannedTransport::new(vec![Ok(fixture("ecc", "operation_error.xml"))]);
+    let (message, _) = transition(&transport, ECCOperation::Configure).await;
+    match message {
+        EmbassyMessage::ECCOperationResponse { response, .. } => {
+            assert_eq!(response.error_code, 1);
+            assert!(response.error_message.contains("not found"));
+        }
+        other => panic!("Expected an operation response, got {}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn transition_is_retried_when_unreachable() {
+    let transport = CannedTransport::new(vec![
+        Err(TransportError::Connect(String::from("Connection refused"))),
+        Ok(fixture("ecc", "operation_ok_compact.xml")),
+    ]);
+    let (message, _) = transition(&transport, ECCOperation::Start).await;
+    match message {
+        EmbassyMessage::ECCOperationResponse { response, .. } => {
+            assert_eq!(response.error_code, 0)
+        }
+        other => panic!("Expected an operation response, got {}", other),
+    }
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn transition_gives_up_after_retries() {
+    //Describe is retried once
+    let transport = CannedTransport::new(vec![]);
+    let (message, _) = transition(&transport, ECCOperation::Describe).await;
+    match message {
+        EmbassyMessage::ECCOperationResponse { response, .. } => {
+            assert_eq!(response.error_code, ECC_ENVOY_ERROR_CODE)
+        }
+        other => panic!("Expected an operation response, got {}", other),
+    }
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn transition_timeout_is_not_retried() {
+    let transport = CannedTransport::new(vec![Err(TransportError::Timeout)]);
+    let (message, _) = transition(&transport, ECCOperation::Start).await;
+    match message {
+        EmbassyMessage::ECCOperationResponse { response, .. } => {
+            assert_eq!(response.error_code, ECC_TIMEOUT_ERROR_CODE)
+        }
+        other => panic!("Expected an operation response, got {}", other),
+    }
+    assert_eq!(transport.requests().len(), 1);
+}
+
+/// Wait for the first status from an ECC status envoy
+async fn ecc_status(transport: &Arc<CannedTransport>) -> EmbassyMessage {
+    let (mut envoy, mut harness) = ecc_envoy(transport);
+    tokio::spawn(async move { envoy.wait_check_status().await });
+    return harness.from_envoy.recv().await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn status_is_reported() {
+    let transport = CannedTransport::new(vec![Ok(fixture("ecc", "status_running_pretty.xml"))]);
+    match ecc_status(&transport).await {
+        EmbassyMessage::ECCStatus { id, response, .. } => {
+            assert_eq!(id, 1);
+            assert_eq!(response.state, 5);
+            assert_eq!(response.connection, ConnectionStatus::Connected);
+        }
+        other => panic!("Expected a status, got {}", other),
+    }
+    let (_, body) = &transport.requests()[0];
+    assert!(body.as_ref().unwrap().contains("<GetState>"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn status_of_unreachable_server() {
+    let transport = CannedTransport::new(vec![]);
+    match ecc_status(&transport).await {
+        EmbassyMessage::ECCStatus { response, .. } => match response.connection {
+            ConnectionStatus::Unreachable(_) => (),
+            other => panic!("Expected Unreachable, got {}", other),
+        },
+        other => panic!("Expected a status, got {}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn status_timeout() {
+    let transport = CannedTransport::new(vec![Err(TransportError::Timeout)]);
+    match ecc_status(&transport).await {
+        EmbassyMessage::ECCStatus { response, .. } => match response.connection {
+            ConnectionStatus::Timeout(_) => (),
+            other => panic!("Expected Timeout, got {}", other),
+        },
+        other => panic!("Expected a status, got {}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn status_parse_error() {
+    let transport = CannedTransport::new(vec![Ok(fixture("ecc", "not_soap.html"))]);
+    match ecc_status(&transport).await {
+        EmbassyMessage::ECCStatus { response, .. } => match response.connection {
+            ConnectionStatus::ParseError(_) => (),
+            other => panic!("Expected ParseError, got {}", other),
+        },
+        other => panic!("Expected a status, got {}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn surveyor_status_is_reported() {
+    let transport = CannedTransport::new(vec![
+        Ok(fixture("surveyor", "filled.html")),
+        Ok(fixture("surveyor", "filled.html")),
+    ]);
+    let (mut envoy, mut harness) = surveyor_envoy(&transport);
+    tokio::spawn(async move { envoy.wait_check_status().await });
+    for _ in 0..2 {
+        match harness.from_envoy.recv().await.unwrap() {
+            EmbassyMessage::Surveyor { id, response, .. } => {
+                assert_eq!(id, 1);
+                assert_eq!(response.state, 1);
+                assert_eq!(response.connection, ConnectionStatus::Connected);
+                assert_eq!(response.address, "192.168.41.61");
+                assert_eq!(response.location, "/Volumes/data/e20009");
+                assert_eq!(response.percent_used, "15%");
+                assert_eq!(response.disk_space, 1953262048 * 512);
+                assert_eq!(response.files, 2);
+                assert_eq!(response.bytes_used, 1610612736);
+                assert_eq!(response.disk_status, "Filled");
+            }
+            other => panic!("Expected a surveyor status, got {}", other),
+        }
+    }
+    let (url, body) = &transport.requests()[0];
+    assert_eq!(url, "http://192.168.41.61:8081/~attpc/surveyor.html");
+    assert!(body.is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn surveyor_of_unreachable_data_router() {
+    let transport = CannedTransport::new(vec![]);
+    let (mut envoy, mut harness) = surveyor_envoy(&transport);
+    tokio::spawn(async move { envoy.wait_check_status().await });
+    match harness.from_envoy.recv().await.unwrap() {
+        EmbassyMessage::Surveyor { response, .. } => match response.connection {
+            ConnectionStatus::Unreachable(_) => (),
+            other => panic!("Expected Unreachable, got {}", other),
+        },
+        other => panic!("Expected a surveyor status, got {}", other),
+    }
+}
+
+/// The next surveyor status from an envoy
+async fn next_surveyor(harness: &mut Harness) -> SurveyorResponse {
+    match harness.from_envoy.recv().await.unwrap() {
+        EmbassyMessage::Surveyor { response, .. } => response,
+        other => panic!("Expected a surveyor status, got {}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn surveyor_handles_files_being_moved() {
+    let transport = CannedTransport::new(vec![
+        Ok(fixture("surveyor", "filled.html")),
+        Ok(fixture("surveyor", "empty_directory.html")),
+        Ok(fixture("surveyor", "other_files.html")),
+    ]);
+    let (mut envoy, mut harness) = surveyor_envoy(&transport);
+    tokio::spawn(async move { envoy.wait_check_status().await });
+
+    assert_eq!(next_surveyor(&mut harness).await.bytes_used, 1610612736);
+    let moved = next_surveyor(&mut harness).await;
+    assert_eq!(moved.bytes_used, 0);
+    assert_eq!(moved.data_rate, 0.0);
+    //Everything on the disk after the move is new
+    let refilled = next_surveyor(&mut harness).await;
+    assert_eq!(refilled.bytes_used, 1073741824);
+    assert!(refilled.data_rate > 0.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn surveyor_rate_uses_elapsed_time() {
+    //Each request takes 3 s on top of the 2 s between polls
+    let transport = CannedTransport::slow(
+        vec![
+            Ok(fixture("surveyor", "empty_directory.html")),
+            Ok(fixture("surveyor", "other_files.html")),
+        ],
+        Duration::from_secs(3),
+    );
+    let (mut envoy, mut harness) = surveyor_envoy(&transport);
+    tokio::spawn(async move { envoy.wait_check_status().await });
+
+    let first = next_surveyor(&mut harness).await;
+    assert!(first.measured_at.is_some());
+    assert_eq!(first.data_rate, 0.0);
+    let second = next_surveyor(&mut harness).await;
+    assert!(second.measured_at >= first.measured_at);
+    assert!((second.data_rate - 1073741824.0e-6 / 5.0).abs() < 1.0e-9);
+}
+
+#[tokio::test(start_paused = true)]
+async fn surveyor_survives_broken_page() {
+    let transport = CannedTransport::new(vec![
+        Ok(fixture("surveyor", "remount.html")),
+        Ok(String::new()),
+        Ok(fixture("surveyor", "filled.html")),
+    ]);
+    let (mut envoy, mut harness) = surveyor_envoy(&transport);
+    tokio::spawn(async move { envoy.wait_check_status().await });
+
+    match next_surveyor(&mut harness).await.connection {
+        ConnectionStatus::ParseError(_) => (),
+        other => panic!("Expected ParseError, got {}", other),
+    }
+    //The empty page is skipped
+    let response = next_surveyor(&mut harness).await;
+    assert_eq!(response.connection, ConnectionStatus::Connected);
+    assert_eq!(response.files, 2);
+    assert_eq!(transport.requests().len(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_stops_transition_envoy() {
+    let transport = CannedTransport::new(vec![]);
+    let (mut envoy, harness) = ecc_envoy(&transport);
+    let handle = tokio::spawn(async move { envoy.wait_for_transition().await });
+    harness
+        .cancel
+        .send(EmbassyMessage::compose_cancel())
+        .unwrap();
+    let result = tokio::time::timeout(Duration::from_secs(1), handle)
+        .await
+        .expect("Envoy did not stop");
+    assert!(result.unwrap().is_ok());
+    assert!(transport.requests().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn closed_channel_stops_transition_envoy() {
+    let transport = CannedTransport::new(vec![]);
+    let (mut envoy, harness) = ecc_envoy(&transport);
+    let handle = tokio::spawn(async move { envoy.wait_for_transition().await });
+    drop(harness.to_envoy);
+    let result = tokio::time::timeout(Duration::from_secs(1), handle)
+        .await
+        .expect("Envoy did not stop");
+    assert!(result.unwrap().is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_stops_status_envoys() {
+    let transport = CannedTransport::new(vec![
+        Ok(fixture("ecc", "status_idle_compact.xml")),
+        Ok(fixture("surveyor", "filled.html")),
+    ]);
+    let (mut ecc, mut ecc_harness) = ecc_envoy(&transport);
+    let (mut surveyor, mut surveyor_harness) = surveyor_envoy(&transport);
+    let ecc_handle = tokio::spawn(async move { ecc.wait_check_status().await });
+    let surveyor_handle = tokio::spawn(async move { surveyor.wait_check_status().await });
+    //Let each envoy make one request before cancelling
+    ecc_harness.from_envoy.recv().await.unwrap();
+    surveyor_harness.from_envoy.recv().await.unwrap();
+
+    ecc_harness
+        .cancel
+        .send(EmbassyMessage::compose_cancel())
+        .unwrap();
+    surveyor_harness
+        .cancel
+        .send(EmbassyMessage::compose_cancel())
+        .unwrap();
+    for handle in [ecc_handle, surveyor_handle] {
+        let result = tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("Envoy did not stop");
+        assert!(result.unwrap().is_ok());
+    }
+    assert_eq!(transport.requests().len(), 2);
+}
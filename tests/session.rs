@@ -0,0 +1,108 @@
+//! Tests of the Session run control against a simulated test bench
+use attpc_envoy::envoy::ecc_operation::ECCStatus;
+use attpc_envoy::session::error::SessionError;
+use attpc_envoy::session::run_log::RunLog;
+use attpc_envoy::session::session::Session;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const POLL_PERIOD: Duration = Duration::from_millis(50);
+const SEQUENCE_WAIT: Duration = Duration::from_secs(60);
+
+/// A session connected to a simulation of the two CoBo test bench, running in its own directory so that the
+/// run log and recording are not left in the repository
+fn simulated_session(dir: &PathBuf) -> Session {
+    let topology = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("topologies/test_bench.yml");
+    std::fs::create_dir_all(dir).unwrap();
+    std::env::set_current_dir(dir).unwrap();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut session = Session::new(runtime);
+    session.config.experiment = String::from("session_test");
+    session.config.topology_path = Some(topology);
+    session.config.record_runs = true;
+    session.config.simulation.latency_scale = 0.01;
+    session.config.simulation.error_rate = 0.0;
+    session.load_topology().unwrap();
+    session.connect_simulated().unwrap();
+    return session;
+}
+
+fn wait_for_status(session: &mut Session, status: ECCStatus) {
+    let start = Instant::now();
+    while session.get_status().get_system_ecc_status() != status {
+        assert!(
+            start.elapsed() < SEQUENCE_WAIT,
+            "System did not reach {status} in time"
+        );
+        session.poll();
+        std::thread::sleep(POLL_PERIOD);
+    }
+}
+
+fn wait_for_sequence(session: &mut Session) {
+    let start = Instant::now();
+    loop {
+        session.poll();
+        if let Some((sequence, result)) = session.take_sequence_outcome() {
+            result.unwrap_or_else(|e| panic!("{sequence} failed: {e}"));
+            return;
+        }
+        assert!(
+            start.elapsed() < SEQUENCE_WAIT,
+            "Sequence did not finish in time"
+        );
+        std::thread::sleep(POLL_PERIOD);
+    }
+}
+
+#[test]
+fn refused_start_keeps_the_run_log_and_recording() {
+    let dir = std::env::temp_dir().join(format!("attpc_envoy_session_{}", std::process::id()));
+    let mut session = simulated_session(&dir);
+    let run_log = RunLog::new();
+    session.set_run_log(run_log.clone());
+
+    //Each progress moves the system one step, Idle to Described to Prepared to Ready
+    for status in [ECCStatus::Idle, ECCStatus::Described, ECCStatus::Prepared] {
+        wait_for_status(&mut session, status);
+        session.progress_system().unwrap();
+        wait_for_sequence(&mut session);
+    }
+    wait_for_status(&mut session, ECCStatus::Ready);
+
+    session.start_run().unwrap();
+    assert!(run_log.is_open());
+    let recording = session.get_recording_path().map(|p| p.to_path_buf());
+    assert!(recording.is_some());
+
+    //The system still looks Ready while the start sequence runs
+    match session.start_run() {
+        Err(SessionError::SequenceRunning) => (),
+        other => panic!("Expected the start to be refused, got {other:?}"),
+    }
+    match session.stop_run() {
+        Err(SessionError::InvalidState(_, _)) | Err(SessionError::SequenceRunning) => (),
+        other => panic!("Expected the stop to be refused, got {other:?}"),
+    }
+    assert!(run_log.is_open());
+    assert_eq!(
+        session.get_recording_path().map(|p| p.to_path_buf()),
+        recording
+    );
+
+    wait_for_sequence(&mut session);
+    assert!(run_log.is_open());
+    assert_eq!(
+        session.get_recording_path().map(|p| p.to_path_buf()),
+        recording
+    );
+
+    session.disconnect().unwrap();
+    drop(session);
+    std::env::set_current_dir(env!("CARGO_MANIFEST_DIR")).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,100 @@
+//! Regression tests for parsing surveyor pages against the fixtures in tests/fixtures/surveyor
+use attpc_envoy::envoy::error::SurveyorParseError;
+use attpc_envoy::envoy::surveyor_page::parse_surveyor_page;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/surveyor")
+        .join(name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read fixture {}: {}", path.display(), e))
+}
+
+#[test]
+fn filled_directory() {
+    let resp = parse_surveyor_page(&fixture("filled.html")).unwrap();
+    assert_eq!(resp.state, 1);
+    assert_eq!(resp.location, "/Volumes/data/e20009");
+    assert_eq!(resp.disk_space, 1953262048 * 512);
+    assert_eq!(resp.percent_used, "15%");
+    assert_eq!(resp.files, 2);
+    assert_eq!(resp.bytes_used, 1610612736);
+    assert_eq!(resp.disk_status, "Filled");
+}
+
+#[test]
+fn empty_directory() {
+    let resp = parse_surveyor_page(&fixture("empty_directory.html")).unwrap();
+    assert_eq!(resp.state, 1);
+    assert_eq!(resp.files, 0);
+    assert_eq!(resp.bytes_used, 0);
+    assert_eq!(resp.disk_status, "Empty");
+}
+
+#[test]
+fn offline() {
+    let resp = parse_surveyor_page(&fixture("offline.html")).unwrap();
+    assert_eq!(resp.state, 0);
+    assert_eq!(resp.files, 0);
+}
+
+#[test]
+fn only_graw_files_are_counted() {
+    let resp = parse_surveyor_page(&fixture("other_files.html")).unwrap();
+    assert_eq!(resp.files, 1);
+    assert_eq!(resp.bytes_used, 1073741824);
+}
+
+#[test]
+fn windows_line_endings_and_missing_df_header() {
+    let resp = parse_surveyor_page(&fixture("crlf_no_header.html")).unwrap();
+    assert_eq!(resp.state, 1);
+    assert_eq!(resp.location, "/Volumes/data/e20009");
+    assert_eq!(resp.disk_space, 1953262048 * 512);
+    assert_eq!(resp.percent_used, "15%");
+    assert_eq!(resp.files, 1);
+    assert_eq!(resp.bytes_used, 1073741824);
+}
+
+#[test]
+fn empty_page() {
+    match parse_surveyor_page(&fixture("empty.html")) {
+        Err(SurveyorParseError::Empty) => (),
+        other => panic!("Expected Empty, got {:?}", other),
+    }
+}
+
+#[test]
+fn malformed_pages_name_the_bad_field() {
+    for name in [
+        "not_found.html",
+        "truncated.html",
+        "remount.html",
+        "short_disk_line.html",
+        "bad_percent.html",
+        "bad_file_size.html",
+        "short_file_line.html",
+    ] {
+        let result = parse_surveyor_page(&fixture(name));
+        let is_expected = match (name, &result) {
+            ("not_found.html", Err(SurveyorParseError::BadState(_))) => true,
+            ("truncated.html", Err(SurveyorParseError::MissingDiskLine)) => true,
+            ("remount.html", Err(SurveyorParseError::BadDiskSize(_))) => true,
+            ("short_disk_line.html", Err(SurveyorParseError::BadDiskLine(_))) => true,
+            ("bad_percent.html", Err(SurveyorParseError::BadPercentUsed(_))) => true,
+            ("bad_file_size.html", Err(SurveyorParseError::BadFileLine(_))) => true,
+            ("short_file_line.html", Err(SurveyorParseError::BadFileLine(_))) => true,
+            _ => false,
+        };
+        assert!(is_expected, "{} gave {:?}", name, result);
+    }
+}
+
+#[test]
+fn missing_location() {
+    match parse_surveyor_page("1\n") {
+        Err(SurveyorParseError::MissingLocation) => (),
+        other => panic!("Expected MissingLocation, got {:?}", other),
+    }
+}